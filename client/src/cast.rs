@@ -1,25 +1,23 @@
-use std::collections::hash_map::DefaultHasher;
-use std::hash::Hash;
-use std::hash::Hasher;
-use std::path::Path;
 use std::time::Duration;
 
-use async_std::task::block_on;
-use ed25519_dalek::pkcs8::DecodePrivateKey;
-use ed25519_dalek::SigningKey;
+use ed25519_dalek::VerifyingKey;
 use libp2p::identity::Keypair;
-use libp2p::swarm::NetworkBehaviour;
 use libp2p::swarm::SwarmEvent;
-use libp2p::Multiaddr;
-use paillier::{Paillier, KeyGeneration};
+use libp2p::{Multiaddr, PeerId};
+use libp2p::request_response;
 use clap::Args;
-use libp2p::{gossipsub, noise, tcp, yamux, identify};
+use libp2p::gossipsub;
 use futures::{FutureExt, StreamExt};
 
 use rand::rngs::OsRng;
-use vote_lib::{Signed, Ballot};
+use vote_lib::{Signed, Ballot, wire};
+use vote_lib::builder::BallotBuilder;
+use vote_lib::submission::{SubmitRequest, SubmitResponse};
 
 use crate::config::Cfg;
+use crate::genesis;
+use crate::keys;
+use crate::network::{self, NodeBehavioursEvent};
 
 #[derive(Args, Debug)]
 pub(crate) struct CastArgs {
@@ -31,112 +29,115 @@ pub(crate) struct CastArgs {
     #[arg(short, long)]
     verdict: bool,
 
-    /// The identity the user wishes to sign as
+    /// The numbered test identity the user wishes to sign as
     #[arg(long)]
     id: Option<u32>,
 
+    /// The keystore identity the user wishes to sign as, overriding the configured default
+    #[arg(long)]
+    identity: Option<String>,
+
+    /// Port of a node on this machine to submit through, used when no peers are given
     #[arg(long)]
     peer_port: Option<u32>,
 
-    // / The number of peers we want to ack the vote cast before closing
-    // #[arg(long, default_value_t = 4)]
-    // required_peers: u32,
-}
+    /// Multiaddr of a node to submit through. May be repeated, overriding the configured seed peers
+    #[arg(long)]
+    peer: Vec<Multiaddr>,
+
+    /// Depth the including block must reach before the vote is confirmed, overriding the config
+    #[arg(long)]
+    confirmations: Option<u32>,
+
+    /// Expected hex genesis hash of the issue's chain, pinning the trustee key the ballot is encrypted under
+    #[arg(long, required_unless_present = "trust_node")]
+    genesis: Option<String>,
+
+    /// Encrypt under whichever genesis the node serves rather than pinning it with --genesis
+    #[arg(long, conflicts_with = "genesis")]
+    trust_node: bool,
 
-#[derive(NetworkBehaviour)]
-struct NodeBehaviours {
-    // Behaviour for PubSub via GossipSub
-    gossipsub: gossipsub::Behaviour,
-    // mdns: mdns::async_io::Behaviour,
-    identify: identify::Behaviour,
+    /// Write the signed ballot to this path, so its inclusion can be checked later with `verify`
+    #[arg(long)]
+    save: Option<String>,
 }
 
 pub(crate) async fn cast(args: CastArgs, cfg: Cfg) {
     println!("Building Vote Packet");
     
-    // Submit using fixed testing key
-    let (ek, _dk) = bincode::deserialize::<paillier::Keypair>(&std::fs::read("./temp/trustees.key").unwrap()).unwrap().keys();
-
-    let ballot = Ballot::new(&ek, args.verdict, args.issue);
-
-    let sk = match args.id {
-        Some(identity) => {
-            let keyfile = format!("./temp/identities/{identity}.der");
-            println!("Reading key from file: {keyfile}");
-            SigningKey::read_pkcs8_der_file(keyfile).unwrap()
-        },
-        None => DecodePrivateKey::read_pkcs8_der_file(Path::new(&cfg.secret_key_path)).unwrap(),
+    let peers = match network::resolve_peers(args.peer, args.peer_port, &cfg) {
+        Ok(peers) => peers,
+        Err(e) => {
+            println!("{e}");
+            return;
+        }
     };
-    let ballot_signed = Signed::new(&sk, ballot);
 
-    println!("Casting Vote:\n{}, size: {}", ballot_signed, bincode::serialize(&ballot_signed).unwrap().len());
+    let expected_genesis = match args.genesis.as_deref().map(blake3::Hash::from_hex).transpose() {
+        Ok(expected) => expected,
+        Err(e) => {
+            println!("Invalid genesis hash: {e}");
+            return;
+        }
+    };
+    // The trustee key comes from the chain's genesis, so ballots can't be encrypted under the wrong key
+    let (metadata, genesis) = match genesis::fetch_issue(&args.issue, peers.clone(), expected_genesis.as_ref()).await {
+        Ok(issue) => issue,
+        Err(e) => {
+            println!("Failed to fetch the trustee key: {e}");
+            return;
+        }
+    };
+    // Confirmations are only trusted from headers signed by known authorities
+    let authorities = match cfg.authority_keys() {
+        Ok(authorities) => authorities,
+        Err(e) => {
+            println!("{e}");
+            return;
+        }
+    };
 
-    let peer_port = match args.peer_port {
-        Some(port) => port,
-        None => 47474,
+    let sk = match keys::signing_key(args.id, args.identity, &cfg) {
+        Ok(sk) => sk,
+        Err(e) => {
+            println!("Failed to load signing key: {e}");
+            return;
+        }
     };
+    let ballot_signed = match BallotBuilder::new(&metadata).verdict(args.verdict).sign(&sk) {
+        Ok(ballot) => ballot,
+        Err(e) => {
+            println!("Failed to build ballot: {e}");
+            return;
+        }
+    };
+
+    println!("Casting Vote:\n{}, size: {}", ballot_signed, wire::encode(&ballot_signed).len());
+    println!("Ballot id: {}", ballot_signed.content_id());
 
-    send_to_swarm(ballot_signed, cfg, sk, peer_port).await;
+    if let Some(path) = args.save {
+        if let Err(e) = std::fs::write(&path, wire::encode(&ballot_signed)) {
+            println!("Failed to save ballot to {path}: {e}");
+            std::process::exit(1);
+        }
+        println!("Saved ballot to {path}");
+    }
+
+    let confirmations = args.confirmations.unwrap_or(cfg.confirmation_depth);
+    let chain = Chain { issue: args.issue, genesis, authorities };
+    send_to_swarm(ballot_signed, cfg, peers, chain, confirmations).await;
 }
 
-// -> Result<(), ErrorType>
-async fn send_to_swarm(ballot: Signed<Ballot>, cfg: Cfg, sk: SigningKey, peer_port: u32) {
-    // let keypair = Keypair::ed25519_from_bytes(&mut sk.to_keypair_bytes()).unwrap();
-    // TODO: Update to load from existing identity (Only allow provided identities)
-    let mut swarm = libp2p::SwarmBuilder::with_new_identity()
-        .with_async_std()
-        .with_tcp(
-            tcp::Config::default(),
-            // Crypto Primitive for Key Agreement
-            noise::Config::new,
-            // Multiplexer
-            yamux::Config::default,
-        )
-        // TODO: Replace with proper error handling? What are the failure conditions for this construction
-        .unwrap()
-        .with_quic()
-        .with_behaviour(|key| {
-            // To content-address ballot, we can use the associated Public Key
-            let message_id_fn = |message: &gossipsub::Message| {
-                let mut s = DefaultHasher::new();
-                message.data.hash(&mut s);
-                gossipsub::MessageId::from(s.finish().to_string())
-            };
-
-            // Set a custom gossipsub configuration
-            let gossipsub_config = gossipsub::ConfigBuilder::default()
-                // TODO: Update Interval to use a config/flag
-                .heartbeat_interval(Duration::from_secs(10)) // This is set to aid debugging by not cluttering the log space
-                .max_transmit_size(1000000) // Expand maximum transmit size to fit ballots with proofs
-                .validation_mode(gossipsub::ValidationMode::Strict) // This sets the kind of message validation. The default is Strict (enforce message signing)
-                .message_id_fn(message_id_fn) // content-address messages. No two messages of the same content will be propagated.
-                .build()
-                .unwrap(); // Potentially replace with better error handling which maps the err to std::error::Error
-
-            // build a gossipsub network behaviour
-            let gossipsub = gossipsub::Behaviour::new(
-                gossipsub::MessageAuthenticity::Signed(key.clone()),
-                gossipsub_config,
-            )
-            .unwrap();
-
-            // let mdns =
-            //     mdns::async_io::Behaviour::new(mdns::Config::default(), key.public().to_peer_id()).unwrap();
-
-            let identify = identify::Behaviour::new(identify::Config::new(
-                "/ipfs/id/1.0.0".to_string(),
-                key.public(),
-            ));
-            
-            return Ok(NodeBehaviours { gossipsub, identify })
-        })
-        .unwrap()
-        .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
-        .build();
+/// The chain a ballot must be confirmed on, checked through receipts rather than the node's word
+struct Chain {
+    issue: String,
+    genesis: blake3::Hash,
+    authorities: Vec<VerifyingKey>,
+}
 
-    
-    println!("Listening");
-    swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse().unwrap()).unwrap();
+// -> Result<(), ErrorType>
+async fn send_to_swarm(ballot: Signed<Ballot>, cfg: Cfg, peers: Vec<Multiaddr>, chain: Chain, confirmations: u32) {
+    let mut swarm = network::build_swarm();
 
     println!("Topicing");
     // Create a Gossipsub topic
@@ -144,64 +145,86 @@ async fn send_to_swarm(ballot: Signed<Ballot>, cfg: Cfg, sk: SigningKey, peer_po
     // subscribes to our topic
     swarm.behaviour_mut().gossipsub.subscribe(&topic).unwrap();
 
-    println!("Dialing");
-    let peer: Multiaddr = format!("/ip4/192.168.1.7/tcp/{}", peer_port).parse().unwrap();
-    swarm.dial(peer).unwrap();
-
-    // TODO: Implement a protocol to discover a certain threshold of nodes to publish to
-    // Do not publish until we have the required number of nodes
-    block_on(async {
-        let mut delay = futures_timer::Delay::new(std::time::Duration::from_secs(5)).fuse();
-        loop {
-            futures::select! {
-                event = swarm.next() => {
-                    match event.unwrap() {
-                        SwarmEvent::NewListenAddr { address, .. } => {
-                            tracing::info!(%address, "Listening on address");
+    network::dial_all(&mut swarm, peers);
+
+    // Submit directly to the first node we reach, then poll it until the ballot is deep enough
+    let content_id = ballot.content_id();
+    let mut node: Option<PeerId> = None;
+    let mut accepted = false;
+    let mut status_ticker = futures_ticker::Ticker::new(Duration::from_secs(5)).fuse();
+    let mut timeout = futures_timer::Delay::new(Duration::from_secs(cfg.confirmation_timeout_secs)).fuse();
+
+    loop {
+        futures::select! {
+            event = swarm.select_next_some() => match event {
+                SwarmEvent::NewListenAddr { address, .. } => {
+                    tracing::info!(%address, "Listening on address");
+                },
+                SwarmEvent::ConnectionEstablished { peer_id, .. } if node.is_none() => {
+                    println!("Submitting ballot to {peer_id}");
+                    swarm.behaviour_mut().submission.send_request(&peer_id, SubmitRequest::Submit(ballot.clone()));
+                    node = Some(peer_id);
+                },
+                SwarmEvent::Behaviour(NodeBehavioursEvent::Submission(request_response::Event::Message {
+                    message: request_response::Message::Response { response, .. }, ..
+                })) => match response {
+                    SubmitResponse::Accepted => {
+                        println!("Ballot accepted, waiting for {confirmations} confirmations");
+                        accepted = true;
+                    },
+                    SubmitResponse::Rejected(reason) => {
+                        println!("Ballot rejected: {reason}");
+                        return;
+                    },
+                    SubmitResponse::Ignored(reason) => {
+                        println!("Ballot ignored: {reason}");
+                        return;
+                    },
+                    SubmitResponse::Pending => println!("Ballot pending inclusion"),
+                    SubmitResponse::Receipt(receipt) => {
+                        // The depth is counted from headers we check ourselves, not reported by the node
+                        let depth = match receipt.verify(&content_id, &chain.issue, &chain.genesis, &chain.authorities) {
+                            Ok(depth) => depth,
+                            Err(e) => {
+                                println!("Node returned a receipt which failed to verify: {e}");
+                                return;
+                            },
+                        };
+                        let inclusion = &receipt.inclusion;
+                        if depth >= confirmations {
+                            println!("Ballot confirmed in block {} at index {}, depth {}", inclusion.block_hash, inclusion.block_index, depth);
+                            return;
                         }
-                        event => println!("{event:?}"),
+                        println!("Ballot included in block {}, depth {} of {confirmations}", inclusion.block_hash, depth);
+                    },
+                    SubmitResponse::Unknown => println!("Node has no record of the ballot"),
+                    SubmitResponse::Included(_) | SubmitResponse::Genesis(..) => {},
+                },
+                SwarmEvent::Behaviour(NodeBehavioursEvent::Submission(request_response::Event::OutboundFailure { peer, error, .. })) => {
+                    if accepted {
+                        println!("Status request to {peer} failed: {error}");
+                        continue;
                     }
-                }
-                _ = delay => {
-                    // Likely listening on all interfaces now, thus continuing by breaking the loop.
-                    break;
-                }
-            }
-        }
-    });
-    println!("Done Waiting");
-
-
-    // let peer_id: PeerId = PeerId::from_str("12D3KooWGqHvzVFxRcs3CwkDRtEfCYwkfDnez2um8PXBv1Cho6Vv").unwrap();
-    println!("{:?}", swarm.network_info());
-    // swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
-    for (peer, topics) in swarm.behaviour_mut().gossipsub.all_peers() {
-        println!("{peer}, {topics:?}")
-    }
-
-    match swarm.behaviour_mut().gossipsub.publish(topic, bincode::serialize(&ballot).unwrap()) {
-        Ok(res) => println!("Succesfully published ballot:\n{res}"),
-        Err(e) => println!("Error publishing ballot:\n{e}")
-    }
 
-    block_on(async {
-        let mut delay = futures_timer::Delay::new(std::time::Duration::from_secs(1)).fuse();
-        println!("Waiting 15 seconds for vote ACK");
-        loop {
-            futures::select! {
-                event = swarm.next() => {
-                    match event.unwrap() {
-                        SwarmEvent::NewListenAddr { address, .. } => {
-                            tracing::info!(%address, "Listening on address");
-                        }
-                        event => println!("{event:?}"),
+                    // The node can't take submissions directly, fall back to gossip without an acknowledgement
+                    println!("Submission to {peer} failed: {error}, publishing via gossip");
+                    match swarm.behaviour_mut().gossipsub.publish(topic.clone(), wire::encode(&ballot)) {
+                        Ok(res) => println!("Succesfully published ballot:\n{res}"),
+                        Err(e) => println!("Error publishing ballot:\n{e}")
                     }
+                    return;
+                },
+                event => println!("{event:?}"),
+            },
+            _ = status_ticker.select_next_some() => {
+                if let (true, Some(peer_id)) = (accepted, node) {
+                    swarm.behaviour_mut().submission.send_request(&peer_id, SubmitRequest::Receipt(content_id));
                 }
-                _ = delay => {
-                    // Likely listening on all interfaces now, thus continuing by breaking the loop.
-                    break;
-                }
-            }
+            },
+            _ = timeout => {
+                println!("Timed out waiting for confirmation");
+                return;
+            },
         }
-    });
-}
\ No newline at end of file
+    }
+}
@@ -1,11 +1,69 @@
+use std::fmt::Display;
+
 use confique::Config;
-// use std::net::SocketAddr;
+use ed25519_dalek::VerifyingKey;
 
 #[derive(Config)]
 pub struct Cfg {
-    // Signature Config
-    #[config(default = "./temp/identities/default.der")]
-    pub secret_key_path: String,
+    // Directory of passphrase-encrypted identities
+    #[config(default = "./temp/wallet")]
+    pub keystore_path: String,
+
+    // Identity to sign ballots with when none is given on the command line
+    pub default_identity: Option<String>,
+
+    // Directory of unencrypted test identities making up the simulated census
+    #[config(default = "./temp/identities")]
+    pub identities_path: String,
+
+    // Signed trustee public key written and read by the trustee commands
+    #[config(default = "./temp/trustee.pub")]
+    pub trustee_key_path: String,
+
+    // Nodes to submit ballots through when none are given on the command line
+    #[config(default = [])]
+    pub seed_peers: Vec<String>,
+
+    // Depth the block including a cast ballot must reach, counting itself, before it is reported as confirmed
+    #[config(default = 3)]
+    pub confirmation_depth: u32,
+
+    // How long to wait for a cast ballot to be confirmed, in seconds
+    #[config(default = 300)]
+    pub confirmation_timeout_secs: u64,
+
+    // Hex public keys of the nodes trusted to sign blocks, checked when verifying a receipt
+    #[config(default = [])]
+    pub authorities: Vec<String>,
+}
+
+impl Cfg {
+    pub fn authority_keys(&self) -> Result<Vec<VerifyingKey>, ConfigError> {
+        if self.authorities.is_empty() {
+            return Err(ConfigError { field: "authorities", reason: "no block signing authorities configured".to_string() });
+        }
+
+        return self.authorities.iter()
+            .map(|key| {
+                let bytes: [u8; 32] = hex::decode(key).ok()
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .ok_or(ConfigError { field: "authorities", reason: format!("'{key}' is not a hex encoded public key") })?;
+                return VerifyingKey::from_bytes(&bytes).map_err(|e| ConfigError { field: "authorities", reason: e.to_string() });
+            })
+            .collect();
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    pub field: &'static str,
+    pub reason: String,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid configuration for {}: {}", self.field, self.reason)
+    }
+}
 
-    // pub seed_peers: Option<Vec<SocketAddr>>
-}
\ No newline at end of file
+impl std::error::Error for ConfigError {}
@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use blake3::Hash;
+use futures::{FutureExt, StreamExt};
+use libp2p::request_response;
+use libp2p::swarm::SwarmEvent;
+use libp2p::{Multiaddr, PeerId};
+use vote_lib::builder::IssueMetadata;
+use vote_lib::submission::{SubmitRequest, SubmitResponse};
+
+use crate::network::{self, NodeBehavioursEvent};
+
+/// Fetch the issue's genesis from the first node reached and return the issue metadata it commits to, along with its hash.
+/// Without an expected genesis hash, as with `--trust-node`, the node is trusted to serve the right chain and the hash is printed for checking
+pub(crate) async fn fetch_issue(issue: &str, peers: Vec<Multiaddr>, expected: Option<&Hash>) -> Result<(IssueMetadata, Hash), String> {
+    let mut swarm = network::build_swarm();
+    network::dial_all(&mut swarm, peers);
+
+    let mut node: Option<PeerId> = None;
+    let mut timeout = futures_timer::Delay::new(Duration::from_secs(30)).fuse();
+
+    loop {
+        futures::select! {
+            event = swarm.select_next_some() => match event {
+                SwarmEvent::ConnectionEstablished { peer_id, .. } if node.is_none() => {
+                    println!("Requesting genesis from {peer_id}");
+                    swarm.behaviour_mut().submission.send_request(&peer_id, SubmitRequest::Genesis);
+                    node = Some(peer_id);
+                },
+                SwarmEvent::Behaviour(NodeBehavioursEvent::Submission(request_response::Event::Message {
+                    message: request_response::Message::Response { response, .. }, ..
+                })) => {
+                    let (header, info) = match response {
+                        SubmitResponse::Genesis(header, info) => (header, info),
+                        other => return Err(format!("Unexpected response: {other:?}")),
+                    };
+
+                    info.verify(&header, issue, expected).map_err(|e| e.to_string())?;
+                    if expected.is_none() {
+                        println!("WARNING: trusting the node's genesis {}, pass --genesis instead of --trust-node to pin it", header.hash());
+                    }
+                    println!(
+                        "Encrypting under trustee key '{}' with fingerprint {}, signed by {}",
+                        info.trustee_key.label,
+                        info.trustee_key.fingerprint(),
+                        hex::encode(info.trustee_key.signer().as_bytes()),
+                    );
+                    return Ok((IssueMetadata::from_genesis(&info), header.hash()));
+                },
+                SwarmEvent::Behaviour(NodeBehavioursEvent::Submission(request_response::Event::OutboundFailure { peer, error, .. })) => {
+                    return Err(format!("Genesis request to {peer} failed: {error}"));
+                },
+                _ => {},
+            },
+            _ = timeout => return Err("Timed out waiting for a node".to_string()),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+
+use clap::Subcommand;
+use ed25519_dalek::SigningKey;
+use rand::rngs::OsRng;
+use vote_lib::keystore::{self, read_new_passphrase, read_passphrase, KeyFormat, Keystore, KeystoreError};
+
+use crate::config::Cfg;
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum KeysArgs {
+    /// Generate a new identity, encrypted under a passphrase
+    New {
+        name: String,
+    },
+
+    /// List every identity with its public key
+    List,
+
+    /// Decrypt an identity and write it out unencrypted
+    Export {
+        name: String,
+
+        /// der, pem or hex
+        #[arg(long, default_value = "pem")]
+        format: String,
+
+        /// File to write the key to, otherwise it is printed
+        #[arg(long)]
+        out: Option<String>,
+    },
+
+    /// Import an existing DER, PEM or hex key as a new identity
+    Import {
+        name: String,
+        path: String,
+
+        /// Encoding of the file, detected from its contents if not given
+        #[arg(long)]
+        format: Option<String>,
+    },
+
+    /// Remove an identity from the keystore
+    Delete {
+        name: String,
+    },
+}
+
+pub(crate) fn keys(args: KeysArgs, cfg: Cfg) -> Result<(), KeystoreError> {
+    let keystore = Keystore::open(&cfg.keystore_path);
+
+    match args {
+        KeysArgs::New { name } => {
+            let public = keystore.generate(&name, &read_new_passphrase()?)?;
+            println!("Created identity '{name}': {}", hex::encode(public.as_bytes()));
+        },
+        KeysArgs::List => {
+            for (name, public) in keystore.list()? {
+                let marker = if cfg.default_identity.as_deref() == Some(name.as_str()) { " (default)" } else { "" };
+                println!("{name}\t{}{marker}", hex::encode(public.as_bytes()));
+            }
+        },
+        KeysArgs::Export { name, format, out } => {
+            let sk = keystore.load(&name, &read_passphrase(&format!("Passphrase for '{name}'"))?)?;
+            let encoded = keystore::encode_key(&sk, format.parse()?)?;
+            match out {
+                Some(out) => {
+                    keystore::write_private(&out, &encoded)?;
+                    println!("Wrote unencrypted identity '{name}' to {out}");
+                },
+                None => std::io::stdout().write_all(&encoded)?,
+            }
+        },
+        KeysArgs::Import { name, path, format } => {
+            let bytes = std::fs::read(&path)?;
+            let format = match format {
+                Some(format) => format.parse()?,
+                None => KeyFormat::detect(&bytes),
+            };
+            let sk = keystore::decode_key(&bytes, format)?;
+            let public = keystore.insert(&name, &sk, &read_new_passphrase()?)?;
+            println!("Imported identity '{name}' from {path}: {}", hex::encode(public.as_bytes()));
+        },
+        KeysArgs::Delete { name } => {
+            keystore.delete(&name)?;
+            println!("Deleted identity '{name}'");
+        },
+    }
+
+    return Ok(());
+}
+
+/// The key to sign as: a numbered test identity, a named keystore identity, or the configured default
+pub(crate) fn signing_key(id: Option<u32>, identity: Option<String>, cfg: &Cfg) -> Result<SigningKey, Box<dyn Error>> {
+    if let Some(id) = id {
+        let keyfile = Path::new(&cfg.identities_path).join(format!("{id}.der"));
+        println!("Reading key from file: {}", keyfile.display());
+        return Ok(keystore::decode_key(&std::fs::read(keyfile)?, KeyFormat::Der)?);
+    }
+
+    let name = match identity.or(cfg.default_identity.clone()) {
+        Some(name) => name,
+        None => return Err("No identity given. Pass --identity, or set default_identity in the config".into()),
+    };
+    let keystore = Keystore::open(&cfg.keystore_path);
+    return Ok(keystore.load(&name, &read_passphrase(&format!("Passphrase for '{name}'"))?)?);
+}
+
+/// Write `count` distinct unencrypted test identities into `dir`, numbered from `start`, for the simulated census
+pub(crate) fn generate_population(dir: &Path, start: u32, count: u32, force: bool) -> Result<(), KeystoreError> {
+    std::fs::create_dir_all(dir)?;
+
+    for index in start..start + count {
+        let path = dir.join(format!("{index}.der"));
+        if path.exists() && !force {
+            return Err(KeystoreError::Exists(path.display().to_string()));
+        }
+
+        let signing_key = SigningKey::generate(&mut OsRng);
+        keystore::write_private(&path, &keystore::encode_key(&signing_key, KeyFormat::Der)?)?;
+        println!("Wrote {}: {}", path.display(), hex::encode(signing_key.verifying_key().as_bytes()));
+    }
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn population_keys_are_distinct() {
+        let dir = tempfile::tempdir().unwrap();
+        generate_population(dir.path(), 1, 8, false).unwrap();
+
+        let keys: HashSet<[u8; 32]> = (1..=8)
+            .map(|index| keystore::decode_key(&std::fs::read(dir.path().join(format!("{index}.der"))).unwrap(), KeyFormat::Der).unwrap())
+            .map(|sk| sk.verifying_key().to_bytes())
+            .collect();
+        assert_eq!(keys.len(), 8);
+
+        // Existing identities are kept unless forced
+        assert!(matches!(generate_population(dir.path(), 8, 1, false), Err(KeystoreError::Exists(_))));
+    }
+}
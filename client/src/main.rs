@@ -1,13 +1,14 @@
 mod config;
 mod cast;
+mod network;
+mod verify;
+mod genesis;
+mod keys;
+mod trustee;
 
 use std::path::Path;
 
-use ed25519_dalek::pkcs8::EncodePrivateKey;
 use clap::{Parser, Subcommand};
-use paillier::{KeyGeneration, Paillier};
-use rand::rngs::OsRng;
-use ed25519_dalek::SigningKey;
 use confique::Config;
 use local_ip_address::local_ip;
 
@@ -15,13 +16,36 @@ use serde::Serialize;
 use tracing_subscriber::EnvFilter;
 use crate::config::Cfg;
 use crate::cast::CastArgs;
+use crate::verify::VerifyArgs;
+use crate::keys::KeysArgs;
+use crate::trustee::TrusteeArgs;
 
 
 // CLI Structure
 #[derive(Subcommand, Debug)]
 enum SubCommand {
     Cast(CastArgs),
-    InitKeys,
+    Verify(VerifyArgs),
+    /// Manage the identities ballots are signed with
+    #[clap(subcommand)]
+    Keys(KeysArgs),
+    /// Generate and publish the trustees' encryption key
+    #[clap(subcommand)]
+    Trustee(TrusteeArgs),
+    /// Generate distinct, unencrypted test identities for the simulated census
+    Population {
+        /// Number of the first identity written
+        #[arg(long, default_value_t = 1)]
+        start: u32,
+
+        /// How many identities to write
+        #[arg(long, default_value_t = 20)]
+        count: u32,
+
+        /// Overwrite identities which already exist
+        #[arg(long)]
+        force: bool,
+    },
     Debugging,
 }
 
@@ -36,33 +60,6 @@ struct Cli {
 }
 
 
-// CLI Functionality Handlers
-fn generate_keys(cfg: Cfg) {
-    let mut csprng = OsRng;
-    let signing_key = SigningKey::generate(&mut csprng);
-    std::fs::create_dir_all(Path::new(&cfg.secret_key_path).parent().unwrap()).unwrap();
-    signing_key.write_pkcs8_der_file(&cfg.secret_key_path).unwrap();
-
-    println!("Wrote signing key to {}", &cfg.secret_key_path)
-}
-
-fn generate_population(cfg: Cfg) {
-    let mut csprng = OsRng;
-    let signing_key = SigningKey::generate(&mut csprng);
-    std::fs::create_dir_all(Path::new(&cfg.secret_key_path).parent().unwrap()).unwrap();
-    for index in 6..20 {
-        signing_key.write_pkcs8_der_file(Path::new(&cfg.secret_key_path).parent().unwrap().join(index.to_string() + ".der")).unwrap();
-    }
-    
-    println!("Wrote signing key to {}", &cfg.secret_key_path)
-}
-
-fn generate_trustee_keys() {
-    let kp = Paillier::keypair();
-    let _ = std::fs::write("./temp/trustees.key", bincode::serialize(&kp).unwrap());
-}
-
-
 #[async_std::main]
 async fn main() {
     let _ = tracing_subscriber::fmt()
@@ -86,12 +83,22 @@ async fn main() {
 
     match args.cmd {
         SubCommand::Cast(cast_args) => cast::cast(cast_args, cfg).await,
-        SubCommand::InitKeys => {
-            println!("Initialising Keys");
-            generate_keys(cfg);
+        SubCommand::Verify(verify_args) => verify::verify(verify_args, cfg).await,
+        SubCommand::Keys(keys_args) => {
+            if let Err(e) = keys::keys(keys_args, cfg) {
+                println!("{e}");
+            }
+        },
+        SubCommand::Population { start, count, force } => {
+            if let Err(e) = keys::generate_population(Path::new(&cfg.identities_path), start, count, force) {
+                println!("Failed to generate population: {e}");
+            }
+        },
+        SubCommand::Trustee(trustee_args) => {
+            if let Err(e) = trustee::trustee(trustee_args, cfg) {
+                println!("{e}");
+            }
         },
-        SubCommand::Debugging => {
-            // generate_trustee_keys()
-        }
+        SubCommand::Debugging => {}
     }
 }
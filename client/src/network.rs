@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use libp2p::request_response::{self, ProtocolSupport};
+use libp2p::swarm::NetworkBehaviour;
+use libp2p::{gossipsub, identify, noise, tcp, yamux, Multiaddr, StreamProtocol, Swarm};
+use vote_lib::submission::{SubmitRequest, SubmitResponse};
+
+use crate::config::{Cfg, ConfigError};
+
+#[derive(NetworkBehaviour)]
+pub(crate) struct NodeBehaviours {
+    // Behaviour for PubSub via GossipSub
+    pub(crate) gossipsub: gossipsub::Behaviour,
+    // mdns: mdns::async_io::Behaviour,
+    pub(crate) identify: identify::Behaviour,
+    // Direct submission to a node, acknowledged with the ballot's status
+    pub(crate) submission: request_response::cbor::Behaviour<SubmitRequest, SubmitResponse>,
+}
+
+/// Nodes to connect to, from the command line, then the config, then a node on this machine
+pub(crate) fn resolve_peers(peers: Vec<Multiaddr>, peer_port: Option<u32>, cfg: &Cfg) -> Result<Vec<Multiaddr>, ConfigError> {
+    match (peers.is_empty(), cfg.seed_peers.is_empty()) {
+        (false, _) => return Ok(peers),
+        (true, false) => {
+            return cfg.seed_peers.iter()
+                .map(|addr| addr.parse().map_err(|e| ConfigError { field: "seed_peers", reason: format!("'{addr}' is not a multiaddr: {e}") }))
+                .collect();
+        },
+        (true, true) => {
+            let peer_port = peer_port.unwrap_or(47474);
+            return Ok(vec![format!("/ip4/127.0.0.1/tcp/{}", peer_port).parse().unwrap()]);
+        },
+    }
+}
+
+pub(crate) fn build_swarm() -> Swarm<NodeBehaviours> {
+    // TODO: Update to load from existing identity (Only allow provided identities)
+    let mut swarm = libp2p::SwarmBuilder::with_new_identity()
+        .with_async_std()
+        .with_tcp(
+            tcp::Config::default(),
+            // Crypto Primitive for Key Agreement
+            noise::Config::new,
+            // Multiplexer
+            yamux::Config::default,
+        )
+        // TODO: Replace with proper error handling? What are the failure conditions for this construction
+        .unwrap()
+        .with_quic()
+        .with_behaviour(|key| {
+            // Content-address ballots by their signed encoding, shared with the nodes through vote_lib
+            let message_id_fn = |message: &gossipsub::Message| {
+                gossipsub::MessageId::from(vote_lib::gossip::message_id(&message.data))
+            };
+
+            // Set a custom gossipsub configuration
+            let gossipsub_config = gossipsub::ConfigBuilder::default()
+                // TODO: Update Interval to use a config/flag
+                .heartbeat_interval(Duration::from_secs(10)) // This is set to aid debugging by not cluttering the log space
+                .max_transmit_size(1000000) // Expand maximum transmit size to fit ballots with proofs
+                .validation_mode(gossipsub::ValidationMode::Strict) // This sets the kind of message validation. The default is Strict (enforce message signing)
+                .message_id_fn(message_id_fn) // content-address messages. No two messages of the same content will be propagated.
+                .build()
+                .unwrap(); // Potentially replace with better error handling which maps the err to std::error::Error
+
+            // build a gossipsub network behaviour
+            let gossipsub = gossipsub::Behaviour::new(
+                gossipsub::MessageAuthenticity::Signed(key.clone()),
+                gossipsub_config,
+            )
+            .unwrap();
+
+            // let mdns =
+            //     mdns::async_io::Behaviour::new(mdns::Config::default(), key.public().to_peer_id()).unwrap();
+
+            let identify = identify::Behaviour::new(identify::Config::new(
+                "/ipfs/id/1.0.0".to_string(),
+                key.public(),
+            ));
+
+            let submission = request_response::cbor::Behaviour::new(
+                [(StreamProtocol::new("/votechain/submit/1.0"), ProtocolSupport::Outbound)],
+                request_response::Config::default(),
+            );
+
+            return Ok(NodeBehaviours { gossipsub, identify, submission })
+        })
+        .unwrap()
+        .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+        .build();
+
+    println!("Listening");
+    swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse().unwrap()).unwrap();
+
+    return swarm;
+}
+
+pub(crate) fn dial_all(swarm: &mut Swarm<NodeBehaviours>, peers: Vec<Multiaddr>) {
+    println!("Dialing");
+    for peer in peers {
+        if let Err(e) = swarm.dial(peer.clone()) {
+            println!("Failed to dial {peer}: {e}");
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::error::Error;
+use std::path::Path;
+
+use clap::{Args, Subcommand};
+use vote_lib::keystore::read_new_passphrase;
+use vote_lib::trustee;
+
+use crate::config::Cfg;
+use crate::keys;
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum TrusteeArgs {
+    /// Generate a new trustee keypair, publishing the encryption key and protecting the decryption key
+    Generate {
+        /// Description stored with the public key, such as the issue it is for
+        #[arg(long)]
+        label: String,
+
+        #[command(flatten)]
+        output: TrusteeOutput,
+    },
+
+    /// Split a keypair in the old unprotected format into a signed public key and a protected decryption key
+    ImportLegacy {
+        /// The legacy keypair file
+        #[arg(default_value = "./temp/trustees.key")]
+        path: String,
+
+        #[arg(long, default_value = "legacy")]
+        label: String,
+
+        #[command(flatten)]
+        output: TrusteeOutput,
+    },
+
+    /// Check a published trustee key's signature and print its details
+    Show {
+        /// The public key file, otherwise the one from the config
+        path: Option<String>,
+    },
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct TrusteeOutput {
+    /// The numbered test identity to sign the public key as
+    #[arg(long)]
+    id: Option<u32>,
+
+    /// The keystore identity to sign the public key as, otherwise the configured default
+    #[arg(long)]
+    identity: Option<String>,
+
+    /// Where to write the signed public key, otherwise the one from the config
+    #[arg(long)]
+    public: Option<String>,
+
+    /// Where to write the encrypted decryption key
+    #[arg(long, default_value = "./temp/trustee.secret")]
+    secret: String,
+}
+
+pub(crate) fn trustee(args: TrusteeArgs, cfg: Cfg) -> Result<(), Box<dyn Error>> {
+    match args {
+        TrusteeArgs::Generate { label, output } => {
+            let signer = keys::signing_key(output.id, output.identity.clone(), &cfg)?;
+            println!("Generating trustee keypair, this may take a while");
+            let (public, dk) = trustee::generate(&signer, label);
+            write_pair(&output, &public, &dk, &cfg)?;
+        },
+        TrusteeArgs::ImportLegacy { path, label, output } => {
+            let keypair = trustee::read_legacy(&path)?;
+            let signer = keys::signing_key(output.id, output.identity.clone(), &cfg)?;
+            let (public, dk) = trustee::split(&signer, label, keypair);
+            write_pair(&output, &public, &dk, &cfg)?;
+            println!("{path} still holds the unprotected decryption key, delete it once the new files are backed up");
+        },
+        TrusteeArgs::Show { path } => {
+            let public = trustee::read_unpinned(path.unwrap_or(cfg.trustee_key_path))?;
+            println!("Label: {}", public.label);
+            println!("Created: {}", public.created);
+            println!("Signed by: {}", hex::encode(public.signer().as_bytes()));
+            println!("Fingerprint: {}", public.fingerprint());
+        },
+    }
+
+    return Ok(());
+}
+
+fn write_pair(output: &TrusteeOutput, public: &vote_lib::Signed<trustee::TrusteeKey>, dk: &paillier::DecryptionKey, cfg: &Cfg) -> Result<(), Box<dyn Error>> {
+    let public_path = output.public.clone().unwrap_or(cfg.trustee_key_path.clone());
+    for path in [&public_path, &output.secret] {
+        if Path::new(path).exists() {
+            return Err(format!("{path} already exists").into());
+        }
+    }
+
+    println!("Choose a passphrase protecting the decryption key");
+    trustee::write_secret(&output.secret, &public.ek, dk, &read_new_passphrase()?)?;
+    trustee::write_public(&public_path, public)?;
+
+    println!("Wrote public key {} to {public_path}", public.fingerprint());
+    println!("Wrote encrypted decryption key to {}", output.secret);
+    return Ok(());
+}
@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use clap::Args;
+use futures::{FutureExt, StreamExt};
+use libp2p::request_response;
+use libp2p::swarm::SwarmEvent;
+use libp2p::{Multiaddr, PeerId};
+use vote_lib::submission::{SubmitRequest, SubmitResponse};
+use vote_lib::{wire, Ballot};
+
+use crate::config::Cfg;
+use crate::network::{self, NodeBehavioursEvent};
+
+#[derive(Args, Debug)]
+pub(crate) struct VerifyArgs {
+    /// The issue the ballot was cast on
+    #[arg(short, long)]
+    issue: String,
+
+    /// Path to a ballot saved by `cast --save`
+    #[arg(long, required_unless_present = "hash", conflicts_with = "hash")]
+    ballot: Option<String>,
+
+    /// Hex ballot id, as printed by `cast`
+    #[arg(long)]
+    hash: Option<String>,
+
+    /// Hex genesis hash of the issue's chain, which the receipt's headers must descend from
+    #[arg(long)]
+    genesis: String,
+
+    /// Port of a node on this machine to query, used when no peers are given
+    #[arg(long)]
+    peer_port: Option<u32>,
+
+    /// Multiaddr of a node to query. May be repeated, overriding the configured seed peers
+    #[arg(long)]
+    peer: Vec<Multiaddr>,
+}
+
+pub(crate) async fn verify(args: VerifyArgs, cfg: Cfg) {
+    let content_id = match (&args.ballot, &args.hash) {
+        (Some(path), _) => {
+            let bytes = match std::fs::read(path) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    println!("Failed to read ballot file {path}: {e}");
+                    return;
+                },
+            };
+            match wire::decode::<Ballot>(&bytes) {
+                Ok(ballot) if ballot.signing_retired() => {
+                    println!("Ballot file was signed under a retired signing version, cast it again");
+                    return;
+                },
+                Ok(ballot) => ballot.content_id(),
+                Err(e) => {
+                    println!("Invalid ballot file: {e}");
+                    return;
+                },
+            }
+        },
+        (None, Some(hash)) => match blake3::Hash::from_hex(hash) {
+            Ok(hash) => hash,
+            Err(e) => {
+                println!("Invalid ballot id: {e}");
+                return;
+            },
+        },
+        (None, None) => unreachable!(),
+    };
+
+    let genesis = match blake3::Hash::from_hex(&args.genesis) {
+        Ok(hash) => hash,
+        Err(e) => {
+            println!("Invalid genesis hash: {e}");
+            return;
+        },
+    };
+    let authorities = match cfg.authority_keys() {
+        Ok(authorities) => authorities,
+        Err(e) => {
+            println!("{e}");
+            return;
+        },
+    };
+
+    let peers = match network::resolve_peers(args.peer, args.peer_port, &cfg) {
+        Ok(peers) => peers,
+        Err(e) => {
+            println!("{e}");
+            return;
+        }
+    };
+
+    let mut swarm = network::build_swarm();
+    network::dial_all(&mut swarm, peers);
+
+    let mut node: Option<PeerId> = None;
+    let mut timeout = futures_timer::Delay::new(Duration::from_secs(30)).fuse();
+
+    loop {
+        futures::select! {
+            event = swarm.select_next_some() => match event {
+                SwarmEvent::ConnectionEstablished { peer_id, .. } if node.is_none() => {
+                    println!("Requesting receipt for {content_id} from {peer_id}");
+                    swarm.behaviour_mut().submission.send_request(&peer_id, SubmitRequest::Receipt(content_id));
+                    node = Some(peer_id);
+                },
+                SwarmEvent::Behaviour(NodeBehavioursEvent::Submission(request_response::Event::Message {
+                    message: request_response::Message::Response { response, .. }, ..
+                })) => {
+                    match response {
+                        SubmitResponse::Receipt(receipt) => {
+                            match receipt.verify(&content_id, &args.issue, &genesis, &authorities) {
+                                Ok(confirmations) => println!(
+                                    "Ballot verified in block {} at index {} with {confirmations} confirmations",
+                                    receipt.inclusion.block_hash,
+                                    receipt.inclusion.block_index,
+                                ),
+                                Err(e) => {
+                                    println!("Receipt failed to verify: {e}");
+                                    return;
+                                },
+                            }
+
+                            if let Some(newer) = receipt.superseding() {
+                                let location = match &receipt.superseded_by.as_ref().unwrap().inclusion {
+                                    Some(inclusion) => format!("included in block {}", inclusion.block_hash),
+                                    None => "pending inclusion".to_string(),
+                                };
+                                println!("WARNING: superseded by ballot {} cast at {} from the same key, {location}", newer.content_id(), newer.timestamp());
+                            }
+                        },
+                        SubmitResponse::Pending => println!("Ballot is pooled but not yet included in a block"),
+                        SubmitResponse::Unknown => println!("Node has no record of the ballot"),
+                        other => println!("Unexpected response: {other:?}"),
+                    }
+                    return;
+                },
+                SwarmEvent::Behaviour(NodeBehavioursEvent::Submission(request_response::Event::OutboundFailure { peer, error, .. })) => {
+                    println!("Receipt request to {peer} failed: {error}");
+                    return;
+                },
+                _ => {},
+            },
+            _ = timeout => {
+                println!("Timed out waiting for a node");
+                return;
+            },
+        }
+    }
+}
@@ -0,0 +1,37 @@
+// Compares checking ballot signatures one by one against verifying them as a batch
+//
+// Run with `cargo bench -p vote-lib`
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ed25519_dalek::SigningKey;
+use paillier::Keypair;
+use rand::rngs::OsRng;
+use vote_lib::{verify_batch, Ballot, Signed, SigningContext};
+
+fn ballot_signatures(c: &mut Criterion) {
+    // Proofs are slow to build and don't affect signing, so every voter signs the same ballot
+    let keypair: Keypair = bincode::deserialize(include_bytes!("../../temp/trustees.key")).unwrap();
+    let (ek, _dk) = keypair.keys();
+    let genesis = blake3::Hash::from([0; 32]);
+    let ballot = Ballot::new(&ek, true, "bench".into(), genesis);
+    let ballots: Vec<Signed<Ballot>> = (0..256)
+        .map(|_| Signed::new(&SigningKey::generate(&mut OsRng), ballot.clone()))
+        .collect();
+    let context = SigningContext::Issue { issue_id: "bench", genesis: &genesis };
+
+    let mut group = c.benchmark_group("ballot_signatures");
+    for count in [16, 64, 256] {
+        let batch: Vec<&Signed<Ballot>> = ballots[..count].iter().collect();
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::new("individual", count), &batch, |b, batch| {
+            b.iter(|| batch.iter().all(|ballot| ballot.signature_valid(&context)))
+        });
+        group.bench_with_input(BenchmarkId::new("batch", count), &batch, |b, batch| {
+            b.iter(|| verify_batch(batch, &context))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, ballot_signatures);
+criterion_main!(benches);
@@ -0,0 +1,196 @@
+// Ballot construction for frontends
+//
+// Third party frontends describe the issue with `IssueMetadata`, pick an option with
+// `BallotBuilder`, and get back a signed ballot ready to send with `wire::encode`.
+
+use std::fmt::Display;
+
+use blake3::Hash;
+use ed25519_dalek::SigningKey;
+use paillier::EncryptionKey;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{genesis::GenesisInfo, wire::{self, WireError}, Ballot, Signed, SigningContext};
+
+#[derive(Debug, PartialEq)]
+pub enum BallotError {
+    /// Ballots encrypt a single for/against choice, so issues need exactly two options
+    UnsupportedOptions(usize),
+    UnknownOption(String),
+    NoChoice,
+    DeadlinePassed(OffsetDateTime),
+    WrongIssue(String),
+    Signature,
+    /// Signed without domain separation, by a client predating it
+    LegacySignature,
+    Proofs,
+    Wire(WireError),
+}
+
+impl Display for BallotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BallotError::UnsupportedOptions(count) => write!(f, "Issues must have exactly two options, found {}", count),
+            BallotError::UnknownOption(option) => write!(f, "Unknown option '{}'", option),
+            BallotError::NoChoice => write!(f, "No option chosen"),
+            BallotError::DeadlinePassed(deadline) => write!(f, "Voting closed at {}", deadline),
+            BallotError::WrongIssue(issue) => write!(f, "Ballot is for issue '{}'", issue),
+            BallotError::Signature => write!(f, "Ballot signature invalid"),
+            BallotError::LegacySignature => write!(f, "Ballot signed under a retired signing version"),
+            BallotError::Proofs => write!(f, "Ballot proofs invalid under the trustee key"),
+            BallotError::Wire(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for BallotError {}
+
+impl From<WireError> for BallotError {
+    fn from(e: WireError) -> Self {
+        return BallotError::Wire(e);
+    }
+}
+
+/// What a frontend needs to know about an issue to build ballots for it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IssueMetadata {
+    pub issue_id: String,
+    /// Hash of the genesis block of the issue's chain, which ballots are signed over
+    pub genesis: Hash,
+    /// The option counted for, then the option counted against
+    pub options: Vec<String>,
+    pub ek: EncryptionKey,
+    pub deadline: Option<OffsetDateTime>,
+}
+
+impl IssueMetadata {
+    pub fn new(issue_id: String, genesis: Hash, options: Vec<String>, ek: EncryptionKey, deadline: Option<OffsetDateTime>) -> Result<Self, BallotError> {
+        if options.len() != 2 {
+            return Err(BallotError::UnsupportedOptions(options.len()));
+        }
+        return Ok(Self { issue_id, genesis, options, ek, deadline });
+    }
+
+    /// Metadata for a yes/no issue with no deadline, from a verified genesis
+    pub fn from_genesis(genesis: &GenesisInfo) -> Self {
+        return Self {
+            issue_id: genesis.issue_id.clone(),
+            genesis: genesis.hash(),
+            options: vec!["yes".into(), "no".into()],
+            ek: genesis.encryption_key().clone(),
+            deadline: None,
+        };
+    }
+
+    fn check_open(&self, at: OffsetDateTime) -> Result<(), BallotError> {
+        match self.deadline {
+            Some(deadline) if at > deadline => return Err(BallotError::DeadlinePassed(deadline)),
+            _ => return Ok(()),
+        }
+    }
+}
+
+pub struct BallotBuilder<'a> {
+    metadata: &'a IssueMetadata,
+    verdict: Option<bool>,
+}
+
+impl<'a> BallotBuilder<'a> {
+    pub fn new(metadata: &'a IssueMetadata) -> Self {
+        return Self { metadata, verdict: None };
+    }
+
+    /// Choose an option by name, as listed in the issue metadata
+    pub fn choose(mut self, option: &str) -> Result<Self, BallotError> {
+        match self.metadata.options.iter().position(|o| o.eq_ignore_ascii_case(option)) {
+            Some(index) => self.verdict = Some(index == 0),
+            None => return Err(BallotError::UnknownOption(option.to_string())),
+        }
+        return Ok(self);
+    }
+
+    /// Choose directly, true for the first option
+    pub fn verdict(mut self, verdict: bool) -> Self {
+        self.verdict = Some(verdict);
+        return self;
+    }
+
+    /// Encrypt, prove and sign the ballot with the voter's key
+    pub fn sign(self, voter: &SigningKey) -> Result<Signed<Ballot>, BallotError> {
+        let verdict = self.verdict.ok_or(BallotError::NoChoice)?;
+        self.metadata.check_open(OffsetDateTime::now_utc())?;
+        let ballot = Ballot::new(&self.metadata.ek, verdict, self.metadata.issue_id.clone(), self.metadata.genesis);
+        return Ok(Signed::new(voter, ballot));
+    }
+
+    /// As `sign`, encoded in the current wire format
+    pub fn encode(self, voter: &SigningKey) -> Result<Vec<u8>, BallotError> {
+        return Ok(wire::encode(&self.sign(voter)?));
+    }
+}
+
+/// Check a ballot is signed, for this issue's chain, cast before the deadline and proven under its key
+pub fn validate(ballot: &Signed<Ballot>, metadata: &IssueMetadata) -> Result<(), BallotError> {
+    if ballot.signing_retired() {
+        return Err(BallotError::LegacySignature);
+    }
+    if ballot.issue_id() != metadata.issue_id {
+        return Err(BallotError::WrongIssue(ballot.issue_id().to_string()));
+    }
+    if !ballot.signature_valid(&SigningContext::Issue { issue_id: &metadata.issue_id, genesis: &metadata.genesis }) {
+        return Err(BallotError::Signature);
+    }
+    metadata.check_open(ballot.timestamp())?;
+    if !ballot.validate_proofs(&metadata.ek) {
+        return Err(BallotError::Proofs);
+    }
+    return Ok(());
+}
+
+/// Decode a wire encoded ballot and validate it against the issue
+pub fn decode_and_validate(bytes: &[u8], metadata: &IssueMetadata) -> Result<Signed<Ballot>, BallotError> {
+    let ballot = wire::decode::<Ballot>(bytes)?;
+    validate(&ballot, metadata)?;
+    return Ok(ballot);
+}
+
+#[cfg(test)]
+mod tests {
+    use paillier::{KeyGeneration, Paillier};
+    use rand::rngs::OsRng;
+    use time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn builds_valid_ballot() {
+        let (ek, _dk) = Paillier::keypair().keys();
+        let metadata = IssueMetadata::new("issue".into(), Hash::from([0; 32]), vec!["for".into(), "against".into()], ek, None).unwrap();
+        let voter = SigningKey::generate(&mut OsRng);
+
+        assert_eq!(BallotBuilder::new(&metadata).choose("maybe").err(), Some(BallotError::UnknownOption("maybe".into())));
+        assert_eq!(BallotBuilder::new(&metadata).sign(&voter).err(), Some(BallotError::NoChoice));
+
+        let encoded = BallotBuilder::new(&metadata).choose("against").unwrap().encode(&voter).unwrap();
+        let ballot = decode_and_validate(&encoded, &metadata).unwrap();
+        assert_eq!(ballot.signer(), voter.verifying_key());
+
+        let closed = IssueMetadata { deadline: Some(OffsetDateTime::now_utc() - Duration::hours(1)), ..metadata.clone() };
+        assert!(matches!(BallotBuilder::new(&closed).verdict(true).sign(&voter), Err(BallotError::DeadlinePassed(_))));
+        assert!(matches!(validate(&ballot, &closed), Err(BallotError::DeadlinePassed(_))));
+    }
+
+    #[test]
+    fn ballot_from_another_chain_rejected() {
+        let (ek, _dk) = Paillier::keypair().keys();
+        let metadata = IssueMetadata::new("issue".into(), Hash::from([0; 32]), vec!["for".into(), "against".into()], ek, None).unwrap();
+        let voter = SigningKey::generate(&mut OsRng);
+        let ballot = BallotBuilder::new(&metadata).verdict(true).sign(&voter).unwrap();
+        assert_eq!(validate(&ballot, &metadata), Ok(()));
+
+        // A second chain started for the same issue has its own genesis
+        let other_chain = IssueMetadata { genesis: Hash::from([1; 32]), ..metadata.clone() };
+        assert_eq!(validate(&ballot, &other_chain), Err(BallotError::Signature));
+    }
+}
@@ -0,0 +1,131 @@
+// The contents of an issue's genesis block
+//
+// The genesis block carries the issue id and the trustees' signed public key. Its header
+// commits to them through the ballot root field, so anyone holding the genesis hash can
+// check which key ballots on the chain must be encrypted under.
+
+use std::fmt::Display;
+
+use blake3::Hash;
+use paillier::EncryptionKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{header::{self, BlockHeader}, merkle, trustee::TrusteeKey, Signed, SigningContext};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum GenesisError {
+    /// The header isn't the first in a chain
+    NotGenesis,
+    /// The header doesn't commit to this genesis content
+    CommitmentMismatch,
+    /// The genesis is for a different issue
+    WrongIssue(String),
+    TrusteeSignature,
+    /// The genesis hash differs from the one the caller expected
+    UnexpectedHash(Hash),
+}
+
+impl Display for GenesisError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GenesisError::NotGenesis => write!(f, "Header is not a genesis header"),
+            GenesisError::CommitmentMismatch => write!(f, "Genesis header does not commit to its contents"),
+            GenesisError::WrongIssue(issue) => write!(f, "Genesis is for issue '{}'", issue),
+            GenesisError::TrusteeSignature => write!(f, "Trustee key signature invalid"),
+            GenesisError::UnexpectedHash(hash) => write!(f, "Genesis hash {} does not match the expected hash", hash),
+        }
+    }
+}
+
+impl std::error::Error for GenesisError {}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GenesisInfo {
+    pub issue_id: String,
+    /// Key every ballot on the chain must be encrypted and proven under
+    pub trustee_key: Signed<TrusteeKey>,
+}
+
+impl GenesisInfo {
+    pub fn new(issue_id: String, trustee_key: Signed<TrusteeKey>) -> Self {
+        return Self { issue_id, trustee_key };
+    }
+
+    pub fn encryption_key(&self) -> &EncryptionKey {
+        return &self.trustee_key.ek;
+    }
+
+    /// Stands in for the ballot root of the genesis header, as the root of a single leaf tree
+    pub fn commitment(&self) -> Hash {
+        return merkle::leaf_hash(&bincode::serialize(self).unwrap());
+    }
+
+    /// Hash of the genesis block carrying this content, which always has a zero timestamp and
+    /// previous hash. Ballots are signed over it, binding them to this chain
+    pub fn hash(&self) -> Hash {
+        return header::header_hash(0, &Hash::from([0; 32]), &self.commitment());
+    }
+
+    /// Check `header` is the genesis of this issue's chain and commits to this content, with a
+    /// validly signed trustee key. Pass `expected` to also pin the genesis hash
+    pub fn verify(&self, header: &BlockHeader, issue_id: &str, expected: Option<&Hash>) -> Result<(), GenesisError> {
+        if header.previous_hash != Hash::from([0; 32]) {
+            return Err(GenesisError::NotGenesis);
+        }
+        if header.ballot_root != self.commitment() {
+            return Err(GenesisError::CommitmentMismatch);
+        }
+        if self.issue_id != issue_id {
+            return Err(GenesisError::WrongIssue(self.issue_id.clone()));
+        }
+        if !self.trustee_key.signature_valid(&SigningContext::Global) {
+            return Err(GenesisError::TrusteeSignature);
+        }
+        match expected {
+            Some(expected) if &header.hash() != expected => return Err(GenesisError::UnexpectedHash(header.hash())),
+            _ => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signature, SigningKey};
+    use rand::rngs::OsRng;
+
+    use crate::trustee;
+
+    use super::*;
+
+    fn header_for(info: &GenesisInfo) -> BlockHeader {
+        return BlockHeader {
+            timestamp: 0,
+            previous_hash: Hash::from([0; 32]),
+            ballot_root: info.commitment(),
+            signatory: SigningKey::generate(&mut OsRng).verifying_key(),
+            signature: Signature::from_bytes(&[0; 64]),
+            nonce: [0; 8],
+        };
+    }
+
+    #[test]
+    fn mismatched_genesis_rejected() {
+        let (trustee_key, _dk) = trustee::generate(&SigningKey::generate(&mut OsRng), "test".into());
+        let info = GenesisInfo::new("issue".into(), trustee_key.clone());
+        let header = header_for(&info);
+        assert_eq!(info.verify(&header, "issue", Some(&header.hash())), Ok(()));
+        assert_eq!(info.hash(), header.hash());
+
+        assert_eq!(info.verify(&header, "other", None), Err(GenesisError::WrongIssue("issue".into())));
+        let elsewhere = Hash::from([1; 32]);
+        assert_eq!(info.verify(&header, "issue", Some(&elsewhere)), Err(GenesisError::UnexpectedHash(header.hash())));
+
+        // A header committing to another issue's genesis can't vouch for this one
+        let other = GenesisInfo::new("other".into(), trustee_key);
+        assert_eq!(other.verify(&header, "other", None), Err(GenesisError::CommitmentMismatch));
+
+        let mut child = header_for(&info);
+        child.previous_hash = header.hash();
+        assert_eq!(info.verify(&child, "issue", None), Err(GenesisError::NotGenesis));
+    }
+}
@@ -0,0 +1,59 @@
+// Shared gossipsub helpers, so clients and nodes agree on how messages are identified
+
+use crate::{wire, Ballot};
+
+/// Message id for a gossiped payload, hashing the canonical encoding of the ballot it decodes
+/// to. The same ballot sent in the legacy format, a newer envelope or with trailing bytes then
+/// shares one id, so is deduplicated however it is framed. Signatures are deterministic, so the
+/// same ballot signed by the same voter always shares an id. Payloads which don't decode are
+/// hashed as they are, and rejected by validation
+pub fn message_id(data: &[u8]) -> String {
+    match wire::decode::<Ballot>(data) {
+        Ok(ballot) => return blake3::hash(&wire::encode(&ballot)).to_hex().to_string(),
+        Err(_) => return blake3::hash(data).to_hex().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::SigningKey;
+    use paillier::{KeyGeneration, Paillier};
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::Signed;
+
+    #[test]
+    fn republished_ballot_shares_id() {
+        let (ek, _dk) = Paillier::keypair().keys();
+        let sk = SigningKey::generate(&mut OsRng);
+        let ballot = Ballot::new(&ek, true, "test".into(), [0; 32].into());
+
+        let first = wire::encode(&Signed::new(&sk, ballot.clone()));
+        let second = wire::encode(&Signed::new(&sk, ballot));
+        let other = wire::encode(&Signed::new(&SigningKey::generate(&mut OsRng), Ballot::new(&ek, true, "test".into(), [0; 32].into())));
+
+        assert_eq!(message_id(&first), message_id(&second));
+        assert_ne!(message_id(&first), message_id(&other));
+        assert_ne!(message_id(b"not a ballot"), message_id(&first));
+    }
+
+    #[test]
+    fn reframed_ballot_shares_id() {
+        use ed25519_dalek::Signer;
+
+        let (ek, _dk) = Paillier::keypair().keys();
+        let sk = SigningKey::generate(&mut OsRng);
+        let ballot = Ballot::new(&ek, true, "test".into(), [0; 32].into());
+
+        // Bare bincode from before envelopes, and the same ballot rewrapped in the current envelope
+        let signature = sk.sign(&bincode::serialize(&ballot).unwrap());
+        let legacy = bincode::serialize(&(signature, sk.verifying_key(), ballot)).unwrap();
+        let enveloped = wire::encode(&wire::decode::<Ballot>(&legacy).unwrap());
+        assert_ne!(legacy, enveloped);
+        assert_eq!(message_id(&legacy), message_id(&enveloped));
+
+        let padded = [enveloped.clone(), vec![0; 4]].concat();
+        assert_eq!(message_id(&padded), message_id(&enveloped));
+    }
+}
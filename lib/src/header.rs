@@ -0,0 +1,79 @@
+// Block headers, shared so clients can check chain linkage without full blocks
+//
+// A header commits to its ballots through the ballot root, and to its place in the
+// chain through the previous hash. The signatory signs the hash of both, along with its
+// timestamp and own key, as proof of work, so no field can be swapped under a reused signature.
+
+use blake3::{Hash, Hasher};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+/// Everything needed to verify a block's place in the chain, without the ballots themselves.
+/// Headers are small enough to fetch the whole chain up front before downloading bodies
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BlockHeader {
+    pub timestamp: u128,
+    pub previous_hash: Hash,
+    pub ballot_root: Hash,
+    pub signatory: VerifyingKey,
+    pub signature: Signature,
+    pub nonce: [u8; 8],
+}
+
+impl BlockHeader {
+    pub fn hash(&self) -> Hash {
+        return header_hash(self.timestamp, &self.previous_hash, &self.ballot_root);
+    }
+
+    /// The hash the signatory signed, see `signing_hash`
+    pub fn signing_hash(&self) -> Hash {
+        return signing_hash(&self.hash(), &self.signatory);
+    }
+
+    pub fn signature_valid(&self) -> bool {
+        let message = work_message(&self.signing_hash(), &self.nonce);
+        return meets_difficulty(&self.signature)
+            && self.signatory.verify(&message, &self.signature).is_ok();
+    }
+
+    /// Whether this header directly follows `prev`
+    pub fn extends(&self, prev: &BlockHeader) -> bool {
+        return self.previous_hash == prev.hash();
+    }
+}
+
+pub fn header_hash(timestamp: u128, previous_hash: &Hash, ballot_root: &Hash) -> Hash {
+    let mut hasher = Hasher::new();
+
+    hasher.update(&timestamp.to_be_bytes());
+    hasher.update(previous_hash.as_bytes());
+    hasher.update(ballot_root.as_bytes());
+
+    return hasher.finalize().into();
+}
+
+/// The hash signed by a block's signatory, covering every field of the header hash and the
+/// signatory itself, so the signature vouches for the ballot root and not just the block's position
+pub fn signing_hash(header_hash: &Hash, signatory: &VerifyingKey) -> Hash {
+    let mut hasher = Hasher::new();
+
+    hasher.update(header_hash.as_bytes());
+    hasher.update(signatory.as_bytes());
+
+    return hasher.finalize();
+}
+
+/// The message signed for proof of work, the signing hash mixed with the nonce
+pub fn work_message(hash: &Hash, nonce: &[u8; 8]) -> [u8; 32] {
+    let mut adjusted_hash = *hash.as_bytes();
+    for i in 0..8 {
+        adjusted_hash[i] = adjusted_hash[i] ^ nonce[i];
+    }
+    return adjusted_hash;
+}
+
+/// Whether a signature has its first 12 bits all zeroes
+pub fn meets_difficulty(sig: &Signature) -> bool {
+    let bytes = sig.to_bytes();
+    return bytes[0] == 0 && bytes[1] & 240 == 0;
+}
@@ -0,0 +1,394 @@
+// Signing keys kept on disk, encrypted under a passphrase
+//
+// Each key is a JSON file named after its identity. The secret key is encrypted with
+// XChaCha20-Poly1305 under a key stretched from the passphrase with Argon2id, while the
+// public key is left in the clear, bound to the ciphertext, so keys can be listed without it.
+
+use std::{fmt::Display, fs, io::Write, path::{Path, PathBuf}, str::FromStr};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{aead::{Aead, KeyInit, Payload}, Key, XChaCha20Poly1305, XNonce};
+use ed25519_dalek::{pkcs8::{DecodePrivateKey, EncodePrivateKey, LineEnding}, SigningKey, VerifyingKey, SECRET_KEY_LENGTH};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+const KEYSTORE_VERSION: u8 = 1;
+const EXTENSION: &str = "json";
+
+/// Environment variable read for a keystore passphrase before prompting for it
+pub const PASSPHRASE_ENV: &str = "PNYX_PASSPHRASE";
+
+#[derive(Debug)]
+pub enum KeystoreError {
+    Io(std::io::Error),
+    /// Key material which couldn't be parsed in the expected format
+    Format(String),
+    /// The passphrase is wrong, or the file has been tampered with
+    Decryption,
+    Kdf(String),
+    Corrupt(String),
+    NotFound(String),
+    Exists(String),
+    InvalidName(String),
+    PassphraseMismatch,
+}
+
+impl Display for KeystoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeystoreError::Io(e) => write!(f, "Keystore IO error: {}", e),
+            KeystoreError::Format(e) => write!(f, "Invalid key encoding: {}", e),
+            KeystoreError::Decryption => write!(f, "Incorrect passphrase or corrupted key"),
+            KeystoreError::Kdf(e) => write!(f, "Failed to derive key from passphrase: {}", e),
+            KeystoreError::Corrupt(e) => write!(f, "Keystore file corrupt: {}", e),
+            KeystoreError::NotFound(name) => write!(f, "No key named '{}'", name),
+            KeystoreError::Exists(name) => write!(f, "A key named '{}' already exists", name),
+            KeystoreError::InvalidName(name) => write!(f, "'{}' is not a valid key name", name),
+            KeystoreError::PassphraseMismatch => write!(f, "Passphrases do not match"),
+        }
+    }
+}
+
+impl std::error::Error for KeystoreError {}
+
+impl From<std::io::Error> for KeystoreError {
+    fn from(e: std::io::Error) -> Self {
+        return KeystoreError::Io(e);
+    }
+}
+
+/// Encodings a key can be imported from or exported to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyFormat {
+    /// PKCS#8 DER, as the pregenerated test identities are stored
+    Der,
+    /// PKCS#8 PEM
+    Pem,
+    /// The raw 32 byte secret, hex encoded
+    Hex,
+}
+
+impl FromStr for KeyFormat {
+    type Err = KeystoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "der" => return Ok(KeyFormat::Der),
+            "pem" => return Ok(KeyFormat::Pem),
+            "hex" => return Ok(KeyFormat::Hex),
+            _ => return Err(KeystoreError::Format(format!("unknown key format '{}', expected der, pem or hex", s))),
+        }
+    }
+}
+
+impl KeyFormat {
+    /// Guess the format of a key file from its contents
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(b"-----BEGIN") {
+            return KeyFormat::Pem;
+        }
+        match std::str::from_utf8(bytes) {
+            Ok(text) if text.trim().len() == SECRET_KEY_LENGTH * 2 && text.trim().chars().all(|c| c.is_ascii_hexdigit()) => return KeyFormat::Hex,
+            _ => return KeyFormat::Der,
+        }
+    }
+}
+
+pub fn encode_key(sk: &SigningKey, format: KeyFormat) -> Result<Vec<u8>, KeystoreError> {
+    match format {
+        KeyFormat::Der => {
+            let der = sk.to_pkcs8_der().map_err(|e| KeystoreError::Format(e.to_string()))?;
+            return Ok(der.as_bytes().to_vec());
+        },
+        KeyFormat::Pem => {
+            let pem = sk.to_pkcs8_pem(LineEnding::LF).map_err(|e| KeystoreError::Format(e.to_string()))?;
+            return Ok(pem.as_bytes().to_vec());
+        },
+        KeyFormat::Hex => return Ok(hex::encode(sk.to_bytes()).into_bytes()),
+    }
+}
+
+pub fn decode_key(bytes: &[u8], format: KeyFormat) -> Result<SigningKey, KeystoreError> {
+    match format {
+        KeyFormat::Der => return SigningKey::from_pkcs8_der(bytes).map_err(|e| KeystoreError::Format(e.to_string())),
+        KeyFormat::Pem => {
+            let pem = std::str::from_utf8(bytes).map_err(|e| KeystoreError::Format(e.to_string()))?;
+            return SigningKey::from_pkcs8_pem(pem).map_err(|e| KeystoreError::Format(e.to_string()));
+        },
+        KeyFormat::Hex => {
+            let text = std::str::from_utf8(bytes).map_err(|e| KeystoreError::Format(e.to_string()))?;
+            let mut secret = [0u8; SECRET_KEY_LENGTH];
+            hex::decode_to_slice(text.trim(), &mut secret).map_err(|e| KeystoreError::Format(e.to_string()))?;
+            return Ok(SigningKey::from_bytes(&secret));
+        },
+    }
+}
+
+/// Write secret key material to a file only its owner can read
+pub fn write_private(path: impl AsRef<Path>, contents: &[u8]) -> Result<(), KeystoreError> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(path)?;
+    // The mode only applies to new files, so tighten an existing one too
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+
+    file.write_all(contents)?;
+    return Ok(());
+}
+
+/// Read a keystore passphrase from the environment, or ask for it on the terminal without echoing it
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_passphrase(prompt: &str) -> Result<String, KeystoreError> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+
+    return Ok(rpassword::prompt_password(format!("{prompt}: "))?);
+}
+
+/// Ask for a new passphrase twice, so a typo doesn't lock the key away
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_new_passphrase() -> Result<String, KeystoreError> {
+    let first = read_passphrase("New passphrase")?;
+    if std::env::var(PASSPHRASE_ENV).is_err() && read_passphrase("Repeat passphrase")? != first {
+        return Err(KeystoreError::PassphraseMismatch);
+    }
+    return Ok(first);
+}
+
+/// Argon2id cost parameters, stored alongside each key so they can be raised later
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct KdfParams {
+    #[serde(with = "hex")]
+    salt: [u8; 16],
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+impl KdfParams {
+    fn generate() -> Self {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+
+        return Self {
+            salt,
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        };
+    }
+
+    fn derive(&self, passphrase: &[u8]) -> Result<[u8; 32], KeystoreError> {
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32)).map_err(|e| KeystoreError::Kdf(e.to_string()))?;
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase, &self.salt, &mut key)
+            .map_err(|e| KeystoreError::Kdf(e.to_string()))?;
+        return Ok(key);
+    }
+}
+
+/// Any secret encrypted under a passphrase, bound to some associated data left in the clear
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SealedBox {
+    kdf: KdfParams,
+    #[serde(with = "hex")]
+    nonce: [u8; 24],
+    #[serde(with = "hex")]
+    ciphertext: Vec<u8>,
+}
+
+impl SealedBox {
+    pub fn seal(secret: &[u8], aad: &[u8], passphrase: &str) -> Result<Self, KeystoreError> {
+        let kdf = KdfParams::generate();
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(&kdf.derive(passphrase.as_bytes())?));
+
+        let mut nonce = [0u8; 24];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = cipher
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: secret, aad })
+            .map_err(|_| KeystoreError::Decryption)?;
+
+        return Ok(Self { kdf, nonce, ciphertext });
+    }
+
+    pub fn open(&self, aad: &[u8], passphrase: &str) -> Result<Vec<u8>, KeystoreError> {
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(&self.kdf.derive(passphrase.as_bytes())?));
+        return cipher
+            .decrypt(XNonce::from_slice(&self.nonce), Payload { msg: &self.ciphertext, aad })
+            .map_err(|_| KeystoreError::Decryption);
+    }
+}
+
+/// A signing key encrypted at rest
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EncryptedKey {
+    version: u8,
+    #[serde(with = "hex")]
+    public_key: [u8; 32],
+    #[serde(flatten)]
+    sealed: SealedBox,
+}
+
+impl EncryptedKey {
+    pub fn seal(sk: &SigningKey, passphrase: &str) -> Result<Self, KeystoreError> {
+        let public_key = sk.verifying_key().to_bytes();
+
+        return Ok(Self {
+            version: KEYSTORE_VERSION,
+            public_key,
+            sealed: SealedBox::seal(&sk.to_bytes(), &public_key, passphrase)?,
+        });
+    }
+
+    pub fn open(&self, passphrase: &str) -> Result<SigningKey, KeystoreError> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(KeystoreError::Corrupt(format!("unsupported keystore version {}", self.version)));
+        }
+
+        let secret = self.sealed.open(&self.public_key, passphrase)?;
+        let secret: [u8; SECRET_KEY_LENGTH] = secret.try_into().map_err(|_| KeystoreError::Corrupt("wrong secret key length".into()))?;
+
+        let sk = SigningKey::from_bytes(&secret);
+        if sk.verifying_key().to_bytes() != self.public_key {
+            return Err(KeystoreError::Corrupt("secret key does not match public key".into()));
+        }
+        return Ok(sk);
+    }
+
+    pub fn public_key(&self) -> Result<VerifyingKey, KeystoreError> {
+        return VerifyingKey::from_bytes(&self.public_key).map_err(|e| KeystoreError::Corrupt(e.to_string()));
+    }
+}
+
+/// A directory of named, encrypted signing keys
+pub struct Keystore {
+    dir: PathBuf,
+}
+
+impl Keystore {
+    pub fn open(dir: impl AsRef<Path>) -> Self {
+        return Self { dir: dir.as_ref().to_path_buf() };
+    }
+
+    fn path_of(&self, name: &str) -> Result<PathBuf, KeystoreError> {
+        // Names become file names, so keep them from escaping the keystore directory
+        let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(KeystoreError::InvalidName(name.into()));
+        }
+        return Ok(self.dir.join(format!("{name}.{EXTENSION}")));
+    }
+
+    fn read(&self, name: &str) -> Result<EncryptedKey, KeystoreError> {
+        let path = self.path_of(name)?;
+        if !path.exists() {
+            return Err(KeystoreError::NotFound(name.into()));
+        }
+        return serde_json::from_slice(&fs::read(path)?).map_err(|e| KeystoreError::Corrupt(e.to_string()));
+    }
+
+    /// Encrypt and store a key under a new name
+    pub fn insert(&self, name: &str, sk: &SigningKey, passphrase: &str) -> Result<VerifyingKey, KeystoreError> {
+        let path = self.path_of(name)?;
+        if path.exists() {
+            return Err(KeystoreError::Exists(name.into()));
+        }
+
+        let sealed = EncryptedKey::seal(sk, passphrase)?;
+        fs::create_dir_all(&self.dir)?;
+        write_private(path, &serde_json::to_vec_pretty(&sealed).map_err(|e| KeystoreError::Corrupt(e.to_string()))?)?;
+        return Ok(sk.verifying_key());
+    }
+
+    pub fn generate(&self, name: &str, passphrase: &str) -> Result<VerifyingKey, KeystoreError> {
+        return self.insert(name, &SigningKey::generate(&mut OsRng), passphrase);
+    }
+
+    pub fn load(&self, name: &str, passphrase: &str) -> Result<SigningKey, KeystoreError> {
+        return self.read(name)?.open(passphrase);
+    }
+
+    pub fn public_key(&self, name: &str) -> Result<VerifyingKey, KeystoreError> {
+        return self.read(name)?.public_key();
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), KeystoreError> {
+        let path = self.path_of(name)?;
+        if !path.exists() {
+            return Err(KeystoreError::NotFound(name.into()));
+        }
+        fs::remove_file(path)?;
+        return Ok(());
+    }
+
+    /// Every stored key's name and public key, sorted by name
+    pub fn list(&self) -> Result<Vec<(String, VerifyingKey)>, KeystoreError> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut keys = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                keys.push((name.to_string(), self.public_key(name)?));
+            }
+        }
+        keys.sort_by(|a, b| a.0.cmp(&b.0));
+        return Ok(keys);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_round_trip() {
+        let sk = SigningKey::generate(&mut OsRng);
+        for format in [KeyFormat::Der, KeyFormat::Pem, KeyFormat::Hex] {
+            let encoded = encode_key(&sk, format).unwrap();
+            assert_eq!(KeyFormat::detect(&encoded), format);
+            assert_eq!(decode_key(&encoded, format).unwrap().to_bytes(), sk.to_bytes());
+        }
+    }
+
+    #[test]
+    fn keys_need_their_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = Keystore::open(dir.path());
+        let public = keystore.generate("node", "correct horse").unwrap();
+
+        assert_eq!(keystore.list().unwrap(), vec![("node".to_string(), public)]);
+        assert_eq!(keystore.load("node", "correct horse").unwrap().verifying_key(), public);
+        assert!(matches!(keystore.load("node", "battery staple"), Err(KeystoreError::Decryption)));
+        assert!(matches!(keystore.generate("node", "again"), Err(KeystoreError::Exists(_))));
+        assert!(matches!(keystore.generate("../node", "escape"), Err(KeystoreError::InvalidName(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keys_are_private_to_their_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = Keystore::open(dir.path());
+        keystore.generate("node", "correct horse").unwrap();
+        let exported = dir.path().join("exported.pem");
+        fs::write(&exported, "").unwrap();
+        write_private(&exported, b"secret").unwrap();
+
+        for path in [dir.path().join("node.json"), exported] {
+            assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+    }
+}
@@ -1,7 +1,20 @@
-use std::{fmt::{Debug, Display}, ops::Deref};
+pub mod builder;
+pub mod genesis;
+pub mod gossip;
+pub mod header;
+pub mod keystore;
+pub mod merkle;
+pub mod receipt;
+pub mod submission;
+pub mod trustee;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod wire;
+
+use std::{fmt::{Debug, Display}, ops::Deref, panic::{self, AssertUnwindSafe}};
 
 use curv::BigInt;
-use ed25519_dalek::{Signature, Signer, Verifier, SigningKey, VerifyingKey};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
 use paillier::{Add, EncryptWithChosenRandomness, EncryptionKey, Mul, Paillier, Randomness, RawCiphertext, RawPlaintext};
 use rand::{rngs::OsRng, RngCore};
 use zk_paillier::zkproofs::RangeProofNi;
@@ -16,6 +29,8 @@ fn short_hex(data: impl AsRef<[u8]>) -> String {
 pub struct Ballot {
     timestamp: OffsetDateTime,
     issue_id: String,
+    /// Hash of the genesis block of the chain the ballot is cast on
+    genesis: blake3::Hash,
     vote_for: BigInt, // Inner of a RawCiphertext
     vote_against: BigInt,
     // proof_for: String,
@@ -43,13 +58,14 @@ impl Ballot {
     //     return (ciphertext, proof)
     // }
 
-    pub fn new(ek: &EncryptionKey, verdict: bool, issue_id: String) -> Self {
+    pub fn new(ek: &EncryptionKey, verdict: bool, issue_id: String, genesis: blake3::Hash) -> Self {
         let (vote_for, proof_for) = Self::encode_verdict(ek, if verdict { 1 } else { 0 });
         let (vote_against, proof_against) = Self::encode_verdict(ek, if verdict { 0 } else { 1 });
 
         return Self {
             timestamp: OffsetDateTime::now_utc(),
             issue_id,
+            genesis,
             vote_for: vote_for.into(),
             proof_for,
             vote_against: vote_against.into(),
@@ -57,12 +73,14 @@ impl Ballot {
         }
     }
 
-    pub fn validate_proofs(&self) -> bool {
-        // let _ = self.proof_for.verify_self();
-        // let _ = self.proof_against.verify_self();
+    /// Check both ciphertexts carry a valid range proof under the trustee key `ek`
+    pub fn validate_proofs(&self, ek: &EncryptionKey) -> bool {
+        // Proofs made against a different key or ciphertext panic rather than returning an error
+        let verify = |proof: &RangeProofNi, ciphertext: &BigInt| {
+            panic::catch_unwind(AssertUnwindSafe(|| proof.verify(ek, ciphertext).is_ok())).unwrap_or(false)
+        };
 
-        // If no panics, proof verification passed
-        return true
+        return verify(&self.proof_for, &self.vote_for) && verify(&self.proof_against, &self.vote_against)
     }
 
     // TODO: Decide if this mutable style is correct
@@ -90,6 +108,14 @@ impl Ballot {
     pub fn timestamp(&self) -> OffsetDateTime {
         return self.timestamp
     }
+
+    pub fn issue_id(&self) -> &str {
+        return &self.issue_id
+    }
+
+    pub fn genesis(&self) -> &blake3::Hash {
+        return &self.genesis
+    }
 }
 
 impl Display for Ballot {
@@ -102,20 +128,89 @@ impl Display for Ballot {
     }
 }
 
+/// Signatures made before domain separation, over the bare bincode of the data
+pub const LEGACY_SIGNING_VERSION: u8 = 0;
+/// Signatures separated by type and bound to their context. Each signed message records its
+/// version, so raising this leaves stored messages verifiable under the version they were made with
+pub const SIGNING_VERSION: u8 = 1;
+/// Oldest signing version still trusted. Older messages are still decoded, but must be refused
+/// wherever their signature would be relied on
+pub const MIN_SIGNING_VERSION: u8 = 1;
+const SIGNING_PREFIX: &[u8] = b"pnyx-signed/";
+
+/// What a signature is bound to beyond the type of the data
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SigningContext<'a> {
+    /// Messages belonging to one issue's chain, which can't be replayed into another issue, nor
+    /// into another chain started for the same issue
+    Issue { issue_id: &'a str, genesis: &'a blake3::Hash },
+    /// Messages not tied to an issue, such as trustee keys
+    Global,
+}
+
+/// Data which can be signed. Each type signs under its own domain and context, so a signature
+/// over one type can never be passed off as another, nor one issue's message as another issue's
+pub trait Signable: Serialize {
+    /// Identifies the type in wire envelopes, unique across types
+    const TAG: u8;
+    const DOMAIN: &'static str;
+
+    /// The context this data is signed under
+    fn context(&self) -> SigningContext<'_>;
+}
+
+impl Signable for Ballot {
+    const TAG: u8 = 1;
+    const DOMAIN: &'static str = "ballot";
+
+    fn context(&self) -> SigningContext<'_> {
+        return SigningContext::Issue { issue_id: &self.issue_id, genesis: &self.genesis };
+    }
+}
+
+/// The bytes actually signed for `data` in `context` under the given signing version.
+/// Legacy signatures predate domains and contexts, so cover the data alone
+pub fn signing_message<T: Signable>(version: u8, context: &SigningContext, data: &T) -> Vec<u8> {
+    let encoded = bincode::serialize(data).unwrap();
+    if version == LEGACY_SIGNING_VERSION {
+        return encoded;
+    }
+
+    let mut message = Vec::from(SIGNING_PREFIX);
+    message.extend(T::DOMAIN.as_bytes());
+    message.push(0);
+    message.extend([version, T::TAG]);
+    match context {
+        SigningContext::Issue { issue_id, genesis } => {
+            message.push(1);
+            message.extend((issue_id.len() as u64).to_le_bytes());
+            message.extend(issue_id.as_bytes());
+            message.extend(genesis.as_bytes());
+        },
+        SigningContext::Global => message.push(0),
+    }
+    message.extend(encoded);
+    return message;
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Signed<T> {
     signature: Signature,
     signer: VerifyingKey,
     data: T,
+    /// How the signature was made, kept alongside it so stored and relayed messages still
+    /// verify once the current signing version moves on
+    signing_version: u8,
 }
 
 impl<T> Signed<T>
-where T: Serialize {
+where T: Signable {
     pub fn new(sk: &SigningKey, data: T) -> Self {
         return Self {
-            signature: sk.sign(&bincode::serialize(&data).unwrap()),
+            signature: sk.sign(&signing_message(SIGNING_VERSION, &data.context(), &data)),
             signer: sk.verifying_key(),
-            data: data
+            data: data,
+            signing_version: SIGNING_VERSION,
         }
     }
 
@@ -123,8 +218,62 @@ where T: Serialize {
         return self.signer;
     }
 
-    pub fn signature_valid(&self) -> bool {
-        return self.signer.verify(&bincode::serialize(&self.data).unwrap(), &self.signature).is_ok()
+    pub fn signing_version(&self) -> u8 {
+        return self.signing_version;
+    }
+
+    /// Whether this was signed under a version no longer trusted, such as a legacy signature
+    /// not bound to its type or issue
+    pub fn signing_retired(&self) -> bool {
+        return self.signing_version < MIN_SIGNING_VERSION;
+    }
+
+    /// Check the signature was made in the `expected` context, whatever context the data claims
+    pub fn signature_valid(&self, expected: &SigningContext) -> bool {
+        // Signing versions from the future can't be checked under rules we don't know
+        if self.signing_version > SIGNING_VERSION {
+            return false;
+        }
+        return verify_single(&signing_message(self.signing_version, expected, &self.data), &self.signature, &self.signer);
+    }
+
+    /// blake3 over the signer and the canonical encoding of the data, leaving out the signature
+    /// so the same content from the same signer always has the same id
+    pub fn content_id(&self) -> blake3::Hash {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(self.signer.as_bytes());
+        hasher.update(&bincode::serialize(&self.data).unwrap());
+        return hasher.finalize();
+    }
+}
+
+/// The check every signature is held to, whether alone or in a batch. Batches can only use the
+/// cofactored equation, so a single signature is checked as a batch of one to accept exactly what
+/// a batch would. Small order keys satisfy that equation for any message, so are refused outright
+fn verify_single(message: &[u8], signature: &Signature, signer: &VerifyingKey) -> bool {
+    return !signer.is_weak() && ed25519_dalek::verify_batch(&[message], &[*signature], &[*signer]).is_ok();
+}
+
+/// Check every signature was made in `context`, returning the index of the first which wasn't.
+/// Signatures are verified as one batch, only falling back to checking them one by one to find
+/// the bad signature when the batch fails. Accepts exactly the messages `signature_valid` does
+pub fn verify_batch<T: Signable>(messages: &[&Signed<T>], context: &SigningContext) -> Result<(), usize> {
+    let encoded: Vec<Vec<u8>> = messages.iter()
+        .map(|message| signing_message(message.signing_version, context, &message.data))
+        .collect();
+    let encoded: Vec<&[u8]> = encoded.iter().map(|message| message.as_slice()).collect();
+    let signatures: Vec<Signature> = messages.iter().map(|message| message.signature).collect();
+    let signers: Vec<VerifyingKey> = messages.iter().map(|message| message.signer).collect();
+
+    // Messages the batch equation can't judge are left to the individual checks
+    let batchable = messages.iter().all(|message| message.signing_version <= SIGNING_VERSION && !message.signer.is_weak());
+    if batchable && ed25519_dalek::verify_batch(&encoded, &signatures, &signers).is_ok() {
+        return Ok(());
+    }
+
+    match messages.iter().position(|message| !message.signature_valid(context)) {
+        Some(index) => return Err(index),
+        None => return Ok(()),
     }
 }
 
@@ -154,25 +303,113 @@ impl<T> Deref for Signed<T> {
 
 #[cfg(test)]
 mod tests {
-    use ed25519_dalek::SigningKey;
+    use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
     use paillier::{KeyGeneration, Paillier};
     use rand::rngs::OsRng;
 
-    use super::{Ballot, Signed};
+    use serde::Serialize;
+
+    use super::{signing_message, verify_batch, Ballot, Signable, Signed, SigningContext, LEGACY_SIGNING_VERSION, SIGNING_VERSION};
+
+    #[derive(Serialize)]
+    struct Note(&'static str);
+
+    impl Signable for Note {
+        const TAG: u8 = 0xfe;
+        const DOMAIN: &'static str = "test-note";
+
+        fn context(&self) -> SigningContext<'_> {
+            return SigningContext::Global;
+        }
+    }
+
+    #[derive(Serialize)]
+    struct OtherNote(&'static str);
+
+    impl Signable for OtherNote {
+        const TAG: u8 = 0xfd;
+        const DOMAIN: &'static str = "other-note";
+
+        fn context(&self) -> SigningContext<'_> {
+            return SigningContext::Global;
+        }
+    }
 
     #[test]
     fn ballot_build_correct() {
         let (ek, _dk) = Paillier::keypair().keys();
-        let ballot = Ballot::new(&ek, true, String::from("test"));
+        let ballot = Ballot::new(&ek, true, String::from("test"), blake3::hash(b"genesis"));
 
-        assert!(ballot.validate_proofs())
+        assert!(ballot.validate_proofs(&ek))
+    }
+
+    #[test]
+    fn proofs_fail_under_other_key() {
+        let (ek, _dk) = Paillier::keypair().keys();
+        let (other_ek, _dk) = Paillier::keypair().keys();
+        let ballot = Ballot::new(&ek, true, String::from("test"), blake3::hash(b"genesis"));
+
+        assert!(!ballot.validate_proofs(&other_ek))
     }
 
     #[test]
     fn signature_correct() {
         let sk = SigningKey::generate(&mut OsRng);
-        let signed = Signed::new(&sk, "test_data");
+        let signed = Signed::new(&sk, Note("test_data"));
+
+        assert!(signed.signature_valid(&SigningContext::Global))
+    }
 
-        assert!(signed.signature_valid())
+    #[test]
+    fn signatures_are_domain_separated() {
+        let global = SigningContext::Global;
+        assert_ne!(signing_message(SIGNING_VERSION, &global, &Note("test_data")), signing_message(SIGNING_VERSION, &global, &OtherNote("test_data")));
+        assert_eq!(signing_message(LEGACY_SIGNING_VERSION, &global, &Note("test_data")), signing_message(LEGACY_SIGNING_VERSION, &global, &OtherNote("test_data")));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn signatures_are_bound_to_issue() {
+        let (ek, _dk) = Paillier::keypair().keys();
+        let genesis = blake3::hash(b"genesis");
+        let signed = Signed::new(&SigningKey::generate(&mut OsRng), Ballot::new(&ek, true, String::from("test"), genesis));
+
+        assert!(signed.signature_valid(&SigningContext::Issue { issue_id: "test", genesis: &genesis }));
+        assert!(!signed.signature_valid(&SigningContext::Issue { issue_id: "other", genesis: &genesis }));
+        assert!(!signed.signature_valid(&SigningContext::Issue { issue_id: "test", genesis: &blake3::hash(b"other") }));
+        assert!(!signed.signature_valid(&SigningContext::Global));
+    }
+
+    #[test]
+    fn batch_finds_bad_signature() {
+        let mut notes: Vec<Signed<Note>> = (0..4).map(|_| Signed::new(&SigningKey::generate(&mut OsRng), Note("test_data"))).collect();
+        assert_eq!(verify_batch(&notes.iter().collect::<Vec<_>>(), &SigningContext::Global), Ok(()));
+        assert_eq!(verify_batch::<Note>(&[], &SigningContext::Global), Ok(()));
+
+        notes[2].signature = notes[0].signature;
+        assert_eq!(verify_batch(&notes.iter().collect::<Vec<_>>(), &SigningContext::Global), Err(2));
+    }
+
+    #[test]
+    fn batch_agrees_with_single_checks() {
+        let mut notes: Vec<Signed<Note>> = (0..3).map(|_| Signed::new(&SigningKey::generate(&mut OsRng), Note("test_data"))).collect();
+
+        // The identity key with an identity commitment and zero scalar passes the cofactored equation for any message
+        let mut identity = [0; 32];
+        identity[0] = 1;
+        notes[1].signer = VerifyingKey::from_bytes(&identity).unwrap();
+        notes[1].signature = Signature::from_bytes(&[identity, [0; 32]].concat().try_into().unwrap());
+
+        // A signature from the future is checked as one from today by the batch equation
+        let sk = SigningKey::generate(&mut OsRng);
+        notes[2].signer = sk.verifying_key();
+        notes[2].signing_version = SIGNING_VERSION + 1;
+        notes[2].signature = sk.sign(&signing_message(SIGNING_VERSION + 1, &SigningContext::Global, &notes[2].data));
+
+        let context = SigningContext::Global;
+        assert!(notes[0].signature_valid(&context));
+        assert!(!notes[1].signature_valid(&context));
+        assert!(!notes[2].signature_valid(&context));
+        assert_eq!(verify_batch(&[&notes[0], &notes[1]], &context), Err(1));
+        assert_eq!(verify_batch(&[&notes[0], &notes[2]], &context), Err(1));
+    }
+}
@@ -0,0 +1,163 @@
+// Binary merkle trees over blake3 hashes
+//
+// Used to commit to the ballots within a block, and the blocks within a sealed chain,
+// such that inclusion can be proven without holding the full data set.
+
+use blake3::{Hash, Hasher};
+use serde::{Deserialize, Serialize};
+
+// Prefixes prevent an interior node from being passed off as a leaf
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+/// Hash a piece of data for inclusion as a leaf of a tree
+pub fn leaf_hash(data: &[u8]) -> Hash {
+    let mut hasher = Hasher::new();
+    hasher.update(&[LEAF_PREFIX]);
+    hasher.update(data);
+    return hasher.finalize();
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Hasher::new();
+    hasher.update(&[NODE_PREFIX]);
+    hasher.update(left.as_bytes());
+    hasher.update(right.as_bytes());
+    return hasher.finalize();
+}
+
+/// Collapse one layer of the tree into its parent layer
+/// An unpaired final node is promoted unchanged rather than duplicated
+fn parent_layer(layer: &[Hash]) -> Vec<Hash> {
+    return layer
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!(),
+        })
+        .collect();
+}
+
+/// Compute the root of a set of leaves. An empty set has an all-zero root
+pub fn root(leaves: &[Hash]) -> Hash {
+    if leaves.is_empty() {
+        return [0; 32].into();
+    }
+
+    let mut layer = leaves.to_vec();
+    while layer.len() > 1 {
+        layer = parent_layer(&layer);
+    }
+
+    return layer[0];
+}
+
+/// Proof that a leaf is included at a given position under a root
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MerkleProof {
+    index: u32,
+    leaf_count: u32,
+    siblings: Vec<Hash>,
+}
+
+impl MerkleProof {
+    /// Build the inclusion proof for the leaf at `index`
+    pub fn new(leaves: &[Hash], index: usize) -> Option<Self> {
+        if index >= leaves.len() {
+            return None;
+        }
+
+        let mut siblings = Vec::new();
+        let mut layer = leaves.to_vec();
+        let mut position = index;
+        while layer.len() > 1 {
+            let sibling = position ^ 1;
+            if sibling < layer.len() {
+                siblings.push(layer[sibling]);
+            }
+
+            layer = parent_layer(&layer);
+            position /= 2;
+        }
+
+        return Some(Self {
+            index: index as u32,
+            leaf_count: leaves.len() as u32,
+            siblings,
+        });
+    }
+
+    pub fn index(&self) -> u32 {
+        return self.index;
+    }
+
+    /// Check that `leaf` sits at this proof's index under `root`
+    pub fn verify(&self, leaf: &Hash, root: &Hash) -> bool {
+        if self.index >= self.leaf_count {
+            return false;
+        }
+
+        let mut siblings = self.siblings.iter();
+        let mut hash = *leaf;
+        let mut position = self.index;
+        let mut width = self.leaf_count;
+
+        while width > 1 {
+            if position % 2 == 1 {
+                match siblings.next() {
+                    Some(sibling) => hash = node_hash(sibling, &hash),
+                    None => return false,
+                }
+            } else if position + 1 < width {
+                match siblings.next() {
+                    Some(sibling) => hash = node_hash(&hash, sibling),
+                    None => return false,
+                }
+            }
+            // Otherwise this node was unpaired and promoted as-is
+
+            position /= 2;
+            width = (width + 1) / 2;
+        }
+
+        return siblings.next().is_none() && &hash == root;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(count: usize) -> Vec<Hash> {
+        return (0..count).map(|i| leaf_hash(&i.to_be_bytes())).collect();
+    }
+
+    #[test]
+    fn single_leaf_is_root() {
+        let leaves = leaves(1);
+        assert_eq!(root(&leaves), leaves[0])
+    }
+
+    #[test]
+    fn proofs_verify_for_every_leaf() {
+        for count in 1..12 {
+            let leaves = leaves(count);
+            let root = root(&leaves);
+
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = MerkleProof::new(&leaves, index).unwrap();
+                assert!(proof.verify(leaf, &root), "count {count} index {index}");
+            }
+        }
+    }
+
+    #[test]
+    fn proof_rejects_wrong_leaf() {
+        let leaves = leaves(5);
+        let root = root(&leaves);
+        let proof = MerkleProof::new(&leaves, 2).unwrap();
+
+        assert!(!proof.verify(&leaves[3], &root))
+    }
+}
@@ -0,0 +1,134 @@
+// Voter-side verification that a ballot made it onto the chain
+//
+// A node answers a receipt request with the ballot, its inclusion proof and every header
+// from genesis to the head, so the voter needn't trust the node's word.
+
+use std::fmt::Display;
+
+use blake3::Hash;
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{header::BlockHeader, submission::Inclusion, Ballot, Signed, SigningContext};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Receipt {
+    pub ballot: Signed<Ballot>,
+    pub inclusion: Inclusion,
+    /// Headers from genesis up to the node's head
+    pub headers: Vec<BlockHeader>,
+    /// The latest ballot cast after this one by the same key, if any
+    pub superseded_by: Option<Supersession>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Supersession {
+    pub ballot: Signed<Ballot>,
+    /// Where the newer ballot was included, or none if it is still pooled
+    pub inclusion: Option<Inclusion>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ReceiptError {
+    /// The node returned a different ballot, or one for another issue
+    WrongBallot,
+    BallotSignature,
+    /// The ballot was signed under a signing version no longer trusted
+    RetiredSignature,
+    /// The first header isn't the expected genesis block
+    GenesisMismatch,
+    /// No header matches the block the ballot was included in
+    HeaderMismatch,
+    /// The header at this index doesn't follow the one before it
+    BrokenLink(u32),
+    /// The header at this index has an invalid signatory signature or proof of work
+    InvalidSignatory(u32),
+    /// The header at this index was signed by a key outside the known authorities
+    UnknownSignatory(u32),
+    InvalidProof,
+}
+
+impl Display for ReceiptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReceiptError::WrongBallot => write!(f, "Receipt is for a different ballot"),
+            ReceiptError::BallotSignature => write!(f, "Ballot signature invalid"),
+            ReceiptError::RetiredSignature => write!(f, "Ballot signed under a retired signing version"),
+            ReceiptError::GenesisMismatch => write!(f, "Headers do not start at the expected genesis block"),
+            ReceiptError::HeaderMismatch => write!(f, "Headers do not include the block holding the ballot"),
+            ReceiptError::BrokenLink(index) => write!(f, "Header at index {} does not extend its parent", index),
+            ReceiptError::InvalidSignatory(index) => write!(f, "Header at index {} has an invalid signatory signature", index),
+            ReceiptError::UnknownSignatory(index) => write!(f, "Header at index {} was signed by an unknown authority", index),
+            ReceiptError::InvalidProof => write!(f, "Inclusion proof invalid"),
+        }
+    }
+}
+
+impl std::error::Error for ReceiptError {}
+
+impl Receipt {
+    /// Check the receipt proves the ballot with this content id was included on a well formed
+    /// chain descending from `genesis` and signed only by `authorities`, returning the number of confirmations
+    pub fn verify(&self, content_id: &Hash, issue_id: &str, genesis: &Hash, authorities: &[VerifyingKey]) -> Result<u32, ReceiptError> {
+        if &self.ballot.content_id() != content_id || self.ballot.issue_id() != issue_id {
+            return Err(ReceiptError::WrongBallot);
+        }
+
+        if self.ballot.signing_retired() {
+            return Err(ReceiptError::RetiredSignature);
+        }
+        if !self.ballot.signature_valid(&SigningContext::Issue { issue_id, genesis }) {
+            return Err(ReceiptError::BallotSignature);
+        }
+
+        match self.headers.first() {
+            Some(header) if &header.hash() == genesis => {},
+            _ => return Err(ReceiptError::GenesisMismatch),
+        }
+
+        // Ballots can't sit in the genesis block, which is at index 1
+        let including = match self.inclusion.block_index {
+            0 | 1 => None,
+            index => self.headers.get(index as usize - 1),
+        };
+        match including {
+            Some(header) if header.hash() == self.inclusion.block_hash && header.ballot_root == self.inclusion.ballot_root => {},
+            _ => return Err(ReceiptError::HeaderMismatch),
+        }
+
+        // The genesis block is pinned by hash, so only the blocks after it need a trusted signatory
+        for (index, pair) in (2..).zip(self.headers.windows(2)) {
+            if !pair[1].extends(&pair[0]) {
+                return Err(ReceiptError::BrokenLink(index));
+            }
+            if !pair[1].signature_valid() {
+                return Err(ReceiptError::InvalidSignatory(index));
+            }
+            if !authorities.contains(&pair[1].signatory) {
+                return Err(ReceiptError::UnknownSignatory(index));
+            }
+        }
+
+        if !self.inclusion.proves(&self.ballot) {
+            return Err(ReceiptError::InvalidProof);
+        }
+
+        return Ok(self.headers.len() as u32 - self.inclusion.block_index + 1);
+    }
+
+    /// The newer ballot replacing this one, provided it really was cast later by the same key
+    pub fn superseding(&self) -> Option<&Signed<Ballot>> {
+        let newer = &self.superseded_by.as_ref()?.ballot;
+
+        if newer.signer() != self.ballot.signer()
+            || newer.signing_retired()
+            || !newer.signature_valid(&SigningContext::Issue { issue_id: self.ballot.issue_id(), genesis: self.ballot.genesis() })
+            || newer.issue_id() != self.ballot.issue_id()
+            || newer.timestamp() <= self.ballot.timestamp()
+        {
+            return None;
+        }
+
+        return Some(newer);
+    }
+}
@@ -0,0 +1,88 @@
+// Messages for submitting a ballot directly to a node and tracking its inclusion
+//
+// The client submits a ballot and is told whether the node accepted it, then polls
+// its status until the node can prove it was included deeply enough in the chain.
+
+use blake3::Hash;
+use serde::{Deserialize, Serialize};
+
+use crate::{genesis::GenesisInfo, header::BlockHeader, merkle::{self, MerkleProof}, receipt::Receipt, Ballot, Signed};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum SubmitRequest {
+    /// Validate, pool and gossip the ballot
+    Submit(Signed<Ballot>),
+    /// Ask after a previously submitted ballot, by its content id
+    Status(Hash),
+    /// Ask for everything needed to verify a ballot's inclusion, by its content id
+    Receipt(Hash),
+    /// Ask for the genesis of the node's chain, carrying the trustee key to encrypt under
+    Genesis,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum SubmitResponse {
+    /// The ballot passed validation and has been pooled
+    Accepted,
+    /// The ballot failed validation and was dropped
+    Rejected(String),
+    /// The ballot was well formed but not for this node, such as one cast on another issue
+    Ignored(String),
+    /// The ballot is pooled but not yet in a block
+    Pending,
+    /// The ballot is in a block on the node's chain
+    Included(Inclusion),
+    /// The ballot is in a block on the node's chain, with the headers to verify it
+    Receipt(Receipt),
+    /// The node has no record of the ballot
+    Unknown,
+    /// The genesis header and the content it commits to
+    Genesis(BlockHeader, GenesisInfo),
+}
+
+/// Where a ballot sits in the chain, with a proof against the block's ballot root
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Inclusion {
+    pub block_hash: Hash,
+    pub block_index: u32,
+    pub ballot_root: Hash,
+    pub proof: MerkleProof,
+    /// Number of blocks from the including block to the head, counting both
+    pub depth: u32,
+}
+
+impl Inclusion {
+    /// Check the proof places this ballot under the block's ballot root
+    pub fn proves(&self, ballot: &Signed<Ballot>) -> bool {
+        let leaf = merkle::leaf_hash(&bincode::serialize(ballot).unwrap());
+        return self.proof.verify(&leaf, &self.ballot_root);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::SigningKey;
+    use paillier::{KeyGeneration, Paillier};
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn inclusion_proves_ballot() {
+        let (ek, _dk) = Paillier::keypair().keys();
+        let sk = SigningKey::generate(&mut OsRng);
+        let ballots: Vec<Signed<Ballot>> = (0..3).map(|_| Signed::new(&sk, Ballot::new(&ek, true, "test".into(), [0; 32].into()))).collect();
+        let leaves: Vec<Hash> = ballots.iter().map(|ballot| merkle::leaf_hash(&bincode::serialize(ballot).unwrap())).collect();
+
+        let inclusion = Inclusion {
+            block_hash: [0; 32].into(),
+            block_index: 2,
+            ballot_root: merkle::root(&leaves),
+            proof: MerkleProof::new(&leaves, 1).unwrap(),
+            depth: 1,
+        };
+
+        assert!(inclusion.proves(&ballots[1]));
+        assert!(!inclusion.proves(&ballots[0]));
+    }
+}
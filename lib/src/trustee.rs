@@ -0,0 +1,173 @@
+// Keys for the trustees who tally an issue
+//
+// Ballots are encrypted under the trustees' Paillier `EncryptionKey`, published as a public
+// artifact signed by the trustee who generated it. The `DecryptionKey` never leaves the
+// trustee, and is kept in a separate file encrypted under a passphrase.
+
+use std::{fmt::Display, fs, path::Path};
+
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use paillier::{DecryptionKey, EncryptionKey, KeyGeneration, Keypair, Paillier};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{keystore::{KeystoreError, SealedBox}, Signable, Signed, SigningContext};
+
+#[derive(Debug)]
+pub enum TrusteeError {
+    Io(std::io::Error),
+    Encoding(String),
+    /// The public artifact's signature doesn't verify
+    Signature,
+    /// The public artifact was signed by someone other than the expected trustee
+    UnexpectedSigner(VerifyingKey),
+    Keystore(KeystoreError),
+}
+
+impl Display for TrusteeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrusteeError::Io(e) => write!(f, "Trustee key IO error: {}", e),
+            TrusteeError::Encoding(e) => write!(f, "Invalid trustee key file: {}", e),
+            TrusteeError::Signature => write!(f, "Trustee key signature invalid"),
+            TrusteeError::UnexpectedSigner(signer) => write!(f, "Trustee key signed by {}, not the expected trustee", hex::encode(signer.as_bytes())),
+            TrusteeError::Keystore(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for TrusteeError {}
+
+impl From<std::io::Error> for TrusteeError {
+    fn from(e: std::io::Error) -> Self {
+        return TrusteeError::Io(e);
+    }
+}
+
+impl From<KeystoreError> for TrusteeError {
+    fn from(e: KeystoreError) -> Self {
+        return TrusteeError::Keystore(e);
+    }
+}
+
+/// The public half of a trustee keypair, as published to voters and nodes
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrusteeKey {
+    /// Human readable description, such as the issue the key was generated for
+    pub label: String,
+    pub created: OffsetDateTime,
+    pub ek: EncryptionKey,
+}
+
+impl TrusteeKey {
+    pub fn new(label: String, ek: EncryptionKey) -> Self {
+        return Self {
+            label,
+            created: OffsetDateTime::now_utc(),
+            ek,
+        };
+    }
+
+    /// blake3 over the encryption key, for comparing keys by eye
+    pub fn fingerprint(&self) -> blake3::Hash {
+        return fingerprint(&self.ek);
+    }
+}
+
+impl Signable for TrusteeKey {
+    const TAG: u8 = 2;
+    const DOMAIN: &'static str = "trustee-key";
+
+    /// Trustee keys are generated before the issue's chain exists
+    fn context(&self) -> SigningContext<'_> {
+        return SigningContext::Global;
+    }
+}
+
+pub fn fingerprint(ek: &EncryptionKey) -> blake3::Hash {
+    return blake3::hash(&bincode::serialize(ek).unwrap());
+}
+
+/// Generate a fresh trustee keypair, signing the public half with the trustee's identity
+pub fn generate(signer: &SigningKey, label: String) -> (Signed<TrusteeKey>, DecryptionKey) {
+    return split(signer, label, Paillier::keypair());
+}
+
+/// Split an existing keypair into its signed public half and its decryption key
+pub fn split(signer: &SigningKey, label: String, keypair: Keypair) -> (Signed<TrusteeKey>, DecryptionKey) {
+    let (ek, dk) = keypair.keys();
+    return (Signed::new(signer, TrusteeKey::new(label, ek)), dk);
+}
+
+pub fn write_public(path: impl AsRef<Path>, key: &Signed<TrusteeKey>) -> Result<(), TrusteeError> {
+    let json = serde_json::to_vec_pretty(key).map_err(|e| TrusteeError::Encoding(e.to_string()))?;
+    fs::write(path, json)?;
+    return Ok(());
+}
+
+/// Read a published trustee key, checking it was signed by the expected trustee
+pub fn read_public(path: impl AsRef<Path>, signer: &VerifyingKey) -> Result<Signed<TrusteeKey>, TrusteeError> {
+    let key = read_unpinned(path)?;
+    if &key.signer() != signer {
+        return Err(TrusteeError::UnexpectedSigner(key.signer()));
+    }
+    return Ok(key);
+}
+
+/// Read a published trustee key checking only its signature, so the signer can be inspected before pinning it
+pub fn read_unpinned(path: impl AsRef<Path>) -> Result<Signed<TrusteeKey>, TrusteeError> {
+    let key: Signed<TrusteeKey> = serde_json::from_slice(&fs::read(path)?).map_err(|e| TrusteeError::Encoding(e.to_string()))?;
+    if !key.signature_valid(&SigningContext::Global) {
+        return Err(TrusteeError::Signature);
+    }
+    return Ok(key);
+}
+
+/// Write the decryption key encrypted under a passphrase, bound to the encryption key it pairs with
+pub fn write_secret(path: impl AsRef<Path>, ek: &EncryptionKey, dk: &DecryptionKey, passphrase: &str) -> Result<(), TrusteeError> {
+    let secret = bincode::serialize(dk).map_err(|e| TrusteeError::Encoding(e.to_string()))?;
+    let sealed = SealedBox::seal(&secret, fingerprint(ek).as_bytes(), passphrase)?;
+    let json = serde_json::to_vec_pretty(&sealed).map_err(|e| TrusteeError::Encoding(e.to_string()))?;
+    fs::write(path, json)?;
+    return Ok(());
+}
+
+/// Decrypt the decryption key paired with `ek`
+pub fn read_secret(path: impl AsRef<Path>, ek: &EncryptionKey, passphrase: &str) -> Result<DecryptionKey, TrusteeError> {
+    let sealed: SealedBox = serde_json::from_slice(&fs::read(path)?).map_err(|e| TrusteeError::Encoding(e.to_string()))?;
+    let secret = sealed.open(fingerprint(ek).as_bytes(), passphrase)?;
+    return bincode::deserialize(&secret).map_err(|e| TrusteeError::Encoding(e.to_string()));
+}
+
+/// Read a keypair in the old unprotected format, holding both halves in one bincode file
+pub fn read_legacy(path: impl AsRef<Path>) -> Result<Keypair, TrusteeError> {
+    return bincode::deserialize(&fs::read(path)?).map_err(|e| TrusteeError::Encoding(e.to_string()));
+}
+
+#[cfg(test)]
+mod tests {
+    use paillier::{Decrypt, Encrypt, RawCiphertext, RawPlaintext};
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn halves_round_trip_separately() {
+        let dir = tempfile::tempdir().unwrap();
+        let signer = SigningKey::generate(&mut OsRng);
+        let (public, dk) = generate(&signer, "test".into());
+
+        write_public(dir.path().join("trustee.pub"), &public).unwrap();
+        write_secret(dir.path().join("trustee.secret"), &public.ek, &dk, "passphrase").unwrap();
+
+        let stranger = SigningKey::generate(&mut OsRng).verifying_key();
+        assert!(matches!(read_public(dir.path().join("trustee.pub"), &stranger), Err(TrusteeError::UnexpectedSigner(_))));
+        let public = read_public(dir.path().join("trustee.pub"), &signer.verifying_key()).unwrap();
+        assert!(read_secret(dir.path().join("trustee.secret"), &public.ek, "wrong").is_err());
+
+        let dk = read_secret(dir.path().join("trustee.secret"), &public.ek, "passphrase").unwrap();
+        let ciphertext: RawCiphertext = Paillier::encrypt(&public.ek, RawPlaintext::from(curv::BigInt::from(7)));
+        let plaintext: RawPlaintext = Paillier::decrypt(&dk, ciphertext);
+        assert_eq!(curv::BigInt::from(plaintext), curv::BigInt::from(7));
+    }
+}
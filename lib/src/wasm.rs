@@ -0,0 +1,43 @@
+// JavaScript bindings, so voters can encrypt and sign ballots in the browser
+//
+// Issue metadata crosses the boundary as the JSON encoding of `IssueMetadata`, voter keys as
+// their 32 raw bytes and ballots in the wire format, ready to hand to a node.
+
+use ed25519_dalek::SigningKey;
+use wasm_bindgen::prelude::*;
+
+use crate::{builder::{self, BallotBuilder, IssueMetadata}, wire};
+
+fn metadata(json: &str) -> Result<IssueMetadata, JsError> {
+    let metadata: IssueMetadata = serde_json::from_str(json)?;
+    return Ok(IssueMetadata::new(metadata.issue_id, metadata.genesis, metadata.options, metadata.ek, metadata.deadline)?);
+}
+
+fn signing_key(secret_key: &[u8]) -> Result<SigningKey, JsError> {
+    let bytes = secret_key.try_into().map_err(|_| JsError::new("Secret key must be 32 bytes"))?;
+    return Ok(SigningKey::from_bytes(bytes));
+}
+
+/// Encrypt, prove and sign a vote for `choice`, returning the wire encoded ballot
+#[wasm_bindgen(js_name = buildBallot)]
+pub fn build_ballot(metadata_json: &str, choice: &str, secret_key: &[u8]) -> Result<Vec<u8>, JsError> {
+    let metadata = metadata(metadata_json)?;
+    return Ok(BallotBuilder::new(&metadata).choose(choice)?.encode(&signing_key(secret_key)?)?);
+}
+
+/// Validate a wire encoded ballot against the issue, returning its hex ballot id
+#[wasm_bindgen(js_name = validateBallot)]
+pub fn validate_ballot(ballot: &[u8], metadata_json: &str) -> Result<String, JsError> {
+    let ballot = builder::decode_and_validate(ballot, &metadata(metadata_json)?)?;
+    return Ok(ballot.content_id().to_hex().to_string());
+}
+
+#[wasm_bindgen(js_name = publicKey)]
+pub fn public_key(secret_key: &[u8]) -> Result<Vec<u8>, JsError> {
+    return Ok(signing_key(secret_key)?.verifying_key().to_bytes().to_vec());
+}
+
+#[wasm_bindgen(js_name = wireVersion)]
+pub fn wire_version() -> u8 {
+    return wire::WIRE_VERSION;
+}
@@ -0,0 +1,197 @@
+// Versioned envelope for signed messages sent between frontends, clients and nodes
+//
+// An envelope is a magic prefix, a version byte and a tag naming the message type, followed
+// by the bincode of the signed message, which records the signing version it was made under.
+// Decoders still accept bare bincode from before versioning, signed over the data alone. Bare
+// bincode starts with the raw signature, so is only read as such when it doesn't decode as an envelope.
+
+use std::fmt::Display;
+
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{Signable, Signed, LEGACY_SIGNING_VERSION};
+
+const MAGIC: &[u8; 2] = b"pn";
+pub const WIRE_VERSION: u8 = 1;
+
+#[derive(Debug, PartialEq)]
+pub enum WireError {
+    UnsupportedVersion(u8),
+    /// The envelope holds a different type of message
+    WrongType { expected: u8, found: u8 },
+    Decode(String),
+}
+
+impl Display for WireError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WireError::UnsupportedVersion(version) => write!(f, "Unsupported wire version {}", version),
+            WireError::WrongType { expected, found } => write!(f, "Expected message type {}, found {}", expected, found),
+            WireError::Decode(e) => write!(f, "Failed to decode message: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WireError {}
+
+pub fn encode<T: Signable>(message: &Signed<T>) -> Vec<u8> {
+    let mut bytes = Vec::from(*MAGIC);
+    bytes.push(WIRE_VERSION);
+    bytes.push(T::TAG);
+    bytes.extend(bincode::serialize(message).unwrap());
+    return bytes;
+}
+
+pub fn decode<T: Signable + DeserializeOwned>(bytes: &[u8]) -> Result<Signed<T>, WireError> {
+    let Some(envelope) = bytes.strip_prefix(MAGIC) else {
+        return legacy(bytes);
+    };
+
+    // Bare bincode starts with the signature, so may happen to look like an envelope
+    return decode_envelope(envelope).or_else(|e| legacy(bytes).map_err(|_| e));
+}
+
+fn decode_envelope<T: Signable + DeserializeOwned>(envelope: &[u8]) -> Result<Signed<T>, WireError> {
+    match envelope {
+        [WIRE_VERSION, tag, body @ ..] if *tag == T::TAG => return deserialize(body),
+        [WIRE_VERSION, tag, ..] => return Err(WireError::WrongType { expected: T::TAG, found: *tag }),
+        [version, ..] => return Err(WireError::UnsupportedVersion(*version)),
+        [] => return Err(WireError::Decode("missing version".into())),
+    }
+}
+
+fn deserialize<T: DeserializeOwned>(body: &[u8]) -> Result<T, WireError> {
+    return bincode::deserialize(body).map_err(|e| WireError::Decode(e.to_string()));
+}
+
+/// A signed message as encoded before the signing version was recorded
+#[derive(Deserialize)]
+struct LegacySigned<T> {
+    signature: Signature,
+    signer: VerifyingKey,
+    data: T,
+}
+
+fn legacy<T: Signable + DeserializeOwned>(body: &[u8]) -> Result<Signed<T>, WireError> {
+    let message: LegacySigned<T> = deserialize(body)?;
+    return Ok(Signed {
+        signature: message.signature,
+        signer: message.signer,
+        data: message.data,
+        signing_version: LEGACY_SIGNING_VERSION,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::SigningKey;
+    use paillier::{KeyGeneration, Paillier};
+    use serde::{Deserialize, Serialize};
+    use time::OffsetDateTime;
+
+    use super::*;
+    use crate::{signing_message, trustee::TrusteeKey, Ballot, SigningContext, SIGNING_VERSION};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Note(String);
+
+    impl Signable for Note {
+        const TAG: u8 = 0xfe;
+        const DOMAIN: &'static str = "test-note";
+
+        fn context(&self) -> SigningContext<'_> {
+            return SigningContext::Global;
+        }
+    }
+
+    const KEY: [u8; 32] = [7; 32];
+    const GOLDEN_MESSAGE: &str = "706e79782d7369676e65642f746573742d6e6f74650001fe000400000000000000706e7978";
+    const GOLDEN_ENVELOPE: &str = concat!(
+        "706e01fe",
+        "d44bdecbaeed6883da925d4e2cd5eeb59116cbabd3b67580677ff39de5bbd49fa704be6db55799da44baec26ea5b595bcfb3ea102f35cbe457a9be47bc288f02",
+        "2000000000000000ea4a6c63e29c520abef5507b132ec5f9954776aebebe7b92421eea691446d22c",
+        "0400000000000000706e7978",
+        "01",
+    );
+    /// Bare bincode, signed over the data without a domain
+    const GOLDEN_LEGACY: &str = concat!(
+        "80fe5bf7457e761561d9011065016bcd7852a60067efc7d8f2a2f761ebe82bb1fc540476e842ae3d9c3d073d262c19909e033908f75c3f63ad97ac603a61d902",
+        "2000000000000000ea4a6c63e29c520abef5507b132ec5f9954776aebebe7b92421eea691446d22c",
+        "0400000000000000706e7978",
+    );
+
+    #[test]
+    fn golden_encodings() {
+        let note = Note("pnyx".into());
+        assert_eq!(hex::encode(signing_message(SIGNING_VERSION, &SigningContext::Global, &note)), GOLDEN_MESSAGE);
+
+        let signed = Signed::new(&SigningKey::from_bytes(&KEY), note);
+        assert_eq!(hex::encode(encode(&signed)), GOLDEN_ENVELOPE);
+
+        let decoded = decode::<Note>(&hex::decode(GOLDEN_ENVELOPE).unwrap()).unwrap();
+        assert_eq!(decoded.signing_version(), SIGNING_VERSION);
+        assert!(decoded.signature_valid(&SigningContext::Global));
+
+        let legacy = decode::<Note>(&hex::decode(GOLDEN_LEGACY).unwrap()).unwrap();
+        assert_eq!(legacy.signing_version(), LEGACY_SIGNING_VERSION);
+        assert!(legacy.signature_valid(&SigningContext::Global));
+        assert_eq!(legacy.content_id(), decoded.content_id());
+    }
+
+    #[test]
+    fn rejects_unknown_versions_and_types() {
+        let envelope = hex::decode(GOLDEN_ENVELOPE).unwrap();
+
+        let mut future = envelope.clone();
+        future[2] = WIRE_VERSION + 1;
+        assert_eq!(decode::<Note>(&future).unwrap_err(), WireError::UnsupportedVersion(WIRE_VERSION + 1));
+        assert_eq!(decode::<Ballot>(&envelope).unwrap_err(), WireError::WrongType { expected: Ballot::TAG, found: Note::TAG });
+
+        // A signature doesn't verify once the message claims another signing version
+        let mut relabelled = envelope.clone();
+        *relabelled.last_mut().unwrap() = LEGACY_SIGNING_VERSION;
+        assert!(!decode::<Note>(&relabelled).unwrap().signature_valid(&SigningContext::Global));
+        *relabelled.last_mut().unwrap() = SIGNING_VERSION + 1;
+        assert!(!decode::<Note>(&relabelled).unwrap().signature_valid(&SigningContext::Global));
+    }
+
+    /// Check the signing message of `signed` is `message_prefix` then the bincode of its data, and
+    /// that its envelope wraps the signature, signer, data and signing version after `envelope_prefix`
+    fn assert_framing<T: Signable + Serialize>(signed: &Signed<T>, message_prefix: &str, envelope_prefix: &str) {
+        let data = bincode::serialize(&signed.data).unwrap();
+        let message = signing_message(SIGNING_VERSION, &signed.context(), &signed.data);
+        assert_eq!(message, [hex::decode(message_prefix).unwrap(), data.clone()].concat());
+
+        let envelope = [
+            hex::decode(envelope_prefix).unwrap(),
+            signed.signature.to_bytes().to_vec(),
+            hex::decode("2000000000000000").unwrap(),
+            signed.signer.to_bytes().to_vec(),
+            data,
+            vec![SIGNING_VERSION],
+        ].concat();
+        assert_eq!(encode(signed), envelope);
+    }
+
+    #[test]
+    fn golden_ballot_and_trustee_key_framing() {
+        let sk = SigningKey::from_bytes(&KEY);
+        let (ek, _dk) = Paillier::keypair().keys();
+
+        let mut ballot = Ballot::new(&ek, true, "test".into(), [0; 32].into());
+        ballot.timestamp = OffsetDateTime::UNIX_EPOCH;
+        let ballot = Signed::new(&sk, ballot);
+        assert_framing(&ballot, "706e79782d7369676e65642f62616c6c6f74000101010400000000000000746573740000000000000000000000000000000000000000000000000000000000000000", "706e0101");
+        // Timestamp as year, ordinal, time and offset, then the issue
+        let data = hex::encode(bincode::serialize(&*ballot).unwrap());
+        assert!(data.starts_with("b2070000010000000000000000000000040000000000000074657374"));
+
+        let mut key = TrusteeKey::new("test".into(), ek);
+        key.created = OffsetDateTime::UNIX_EPOCH;
+        let key = Signed::new(&sk, key);
+        assert_framing(&key, "706e79782d7369676e65642f747275737465652d6b657900010200", "706e0102");
+        let data = hex::encode(bincode::serialize(&*key).unwrap());
+        assert!(data.starts_with("040000000000000074657374b2070000010000000000000000000000"));
+    }
+}
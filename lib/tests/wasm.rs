@@ -0,0 +1,34 @@
+// Headless run of the browser bindings, with
+// `wasm-pack test --node lib --features wasm` or `--headless --firefox` in place of `--node`
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use paillier::Keypair;
+use time::OffsetDateTime;
+use vote_lib::{builder::IssueMetadata, wasm, wire, Ballot};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+fn metadata_json() -> String {
+    // Generating a fresh Paillier key is too slow for a wasm test, so reuse the test trustees
+    let keypair: Keypair = bincode::deserialize(include_bytes!("../../temp/trustees.key")).unwrap();
+    let (ek, _dk) = keypair.keys();
+    let metadata = IssueMetadata::new("issue".into(), [0; 32].into(), vec!["yes".into(), "no".into()], ek, None).unwrap();
+    return serde_json::to_string(&metadata).unwrap();
+}
+
+#[wasm_bindgen_test]
+fn builds_and_validates_ballot() {
+    let metadata = metadata_json();
+    let secret_key = [7u8; 32];
+
+    let before = OffsetDateTime::now_utc();
+    let ballot = wasm::build_ballot(&metadata, "yes", &secret_key).unwrap();
+    assert_eq!(ballot[2], wasm::wire_version());
+
+    let decoded = wire::decode::<Ballot>(&ballot).unwrap();
+    assert!(decoded.timestamp() >= before);
+    assert_eq!(decoded.signer().to_bytes().to_vec(), wasm::public_key(&secret_key).unwrap());
+    assert_eq!(wasm::validate_ballot(&ballot, &metadata).unwrap(), decoded.content_id().to_hex().to_string());
+
+    // Fresh randomness each time, so identical votes don't encrypt identically
+    assert_ne!(wasm::build_ballot(&metadata, "yes", &secret_key).unwrap(), ballot);
+}
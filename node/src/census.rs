@@ -1,17 +1,17 @@
-use std::{collections::HashSet, fs};
+use std::{collections::HashSet, fs, path::Path};
 
 use ed25519_dalek::{pkcs8::DecodePrivateKey, SigningKey, VerifyingKey};
 
 /// Super dumb file-system based census for testing
-/// Pulls a list of Identities from a set of keypair files stored locally in the './temp/identities' directory
+/// Pulls a list of Identities from a set of keypair files stored in a local directory
 pub struct DumbCensus {
     census_keys: HashSet<VerifyingKey>
 }
 
 impl DumbCensus {
-    pub fn new() -> Self {
+    pub fn new(identities: impl AsRef<Path>) -> Self {
         let mut census_keys: HashSet<VerifyingKey> = HashSet::new();
-        for file in fs::read_dir("./temp/identities/").unwrap() {
+        for file in fs::read_dir(identities).unwrap() {
             let signing_key: SigningKey = DecodePrivateKey::read_pkcs8_der_file(file.unwrap().path()).unwrap();
             census_keys.insert(signing_key.verifying_key());
         }
@@ -1,21 +1,56 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 /// Root of the cli
 #[derive(Parser, Debug)]
 #[clap(author = "Yarnley, George", version, about)]
 pub(crate) struct Cli {
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+
     /// Optional path for overriding the location of the config file
     #[arg(long, default_value = "./config.toml")]
     pub(crate) config: Option<String>,
 
+    /// Port to listen for peers on, overriding the config
+    #[arg(short, long)]
+    pub(crate) port: Option<u16>,
+
     /// An addendum to the chain for testing
     #[arg(short, long)]
     pub(crate) chain_postfix: Option<String>,
 
-    /// Hex-encoded private key
+    /// Bootstrap the chain from a verified snapshot file instead of syncing every block
+    #[arg(long)]
+    pub(crate) snapshot: Option<String>,
+
+    /// Sync by fetching the header chain first, then downloading blocks from several peers in parallel
+    #[arg(long)]
+    pub(crate) headers_first: bool,
+
+    /// Multiaddr of a node to bootstrap peer discovery from, ending in /p2p/<peer id>. May be repeated
+    #[arg(long)]
+    pub(crate) bootstrap: Vec<String>,
+
+    /// Disable mDNS, discovering peers through the bootstrap nodes and Kademlia alone
+    #[arg(long)]
+    pub(crate) no_mdns: bool,
+
+    /// Address to serve the JSON-RPC API on, overriding the config
+    #[arg(long)]
+    pub(crate) rpc_address: Option<String>,
+
+    /// Don't serve the JSON-RPC API
+    #[arg(long)]
+    pub(crate) no_rpc: bool,
+
+    /// Hex-encoded private key to sign blocks with, in place of a keystore identity
     #[arg(long)]
     pub(crate) private_key: Option<String>,
 
+    /// Name of the keystore identity to sign blocks with, overriding the config
+    #[arg(long)]
+    pub(crate) identity: Option<String>,
+
     // /// The number of peers we want to ack the vote cast before closing
     // #[arg(long)]
     // peer_id: Option<String>,
@@ -29,3 +64,56 @@ pub(crate) struct Cli {
     #[arg(long)]
     pub(crate) test_identity: Option<u32>,
 }
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum Command {
+    /// Print the configuration the node would run with, after every layer is applied
+    PrintConfig {
+        /// Print a commented template of every option and its default instead
+        #[arg(long)]
+        template: bool,
+    },
+
+    /// Manage the signing keys held in the node's keystore
+    #[command(subcommand)]
+    Keys(KeysCommand),
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum KeysCommand {
+    /// Generate a new key, encrypted under a passphrase
+    Generate {
+        name: String,
+    },
+
+    /// Import an existing key from a DER, PEM or hex file
+    Import {
+        name: String,
+        path: String,
+
+        /// Encoding of the file, detected from its contents if not given
+        #[arg(long)]
+        format: Option<String>,
+    },
+
+    /// Decrypt a key and write it out unencrypted
+    Export {
+        name: String,
+
+        /// der, pem or hex
+        #[arg(long, default_value = "pem")]
+        format: String,
+
+        /// File to write the key to, otherwise it is printed
+        #[arg(long)]
+        out: Option<String>,
+    },
+
+    /// List every stored key with its public key
+    List,
+
+    /// Show a key's public key and the peer id it gives the node
+    Show {
+        name: String,
+    },
+}
@@ -0,0 +1,262 @@
+// The node's full configuration tree
+//
+// Values are layered from lowest to highest priority: defaults, the config file, `PNYX_*`
+// environment variables, then command line flags. `print-config` shows the result.
+
+use std::{fmt::Display, net::{IpAddr, SocketAddr}};
+
+use confique::Config;
+use ed25519_dalek::VerifyingKey;
+use serde::Serialize;
+
+use crate::{cli::Cli, protocols::{discovery::{bootstrap, config::DiscoveryConfig}, reputation::config::ReputationConfig}, rpc::config::RpcConfig, votechain::config::BlockchainConfig};
+
+#[derive(Config, Serialize)]
+pub struct NodeConfig {
+    #[config(nested)]
+    pub network: NetworkConfig,
+
+    #[config(nested)]
+    pub chain: BlockchainConfig,
+
+    #[config(nested)]
+    pub sync: SyncConfig,
+
+    #[config(nested)]
+    pub discovery: DiscoveryConfig,
+
+    #[config(nested)]
+    pub reputation: ReputationConfig,
+
+    #[config(nested)]
+    pub rpc: RpcConfig,
+
+    #[config(nested)]
+    pub paths: PathsConfig,
+
+    #[config(nested)]
+    pub keys: KeysConfig,
+}
+
+#[derive(Config, Serialize)]
+pub struct NetworkConfig {
+    /// Interface to listen for peers on
+    #[config(default = "0.0.0.0", env = "PNYX_LISTEN_IP")]
+    pub listen_ip: String,
+
+    /// Port to listen for peers on. Falls back to any free port if taken
+    #[config(default = 47474, env = "PNYX_PORT")]
+    pub port: u16,
+
+    /// Seconds between gossipsub heartbeats
+    #[config(default = 10)]
+    pub gossip_heartbeat_secs: u64,
+
+    /// Seconds a connection may sit idle before it is closed
+    #[config(default = 60)]
+    pub idle_timeout_secs: u64,
+}
+
+#[derive(Config, Serialize)]
+pub struct SyncConfig {
+    /// Seconds between advertising our chain status to connected peers
+    #[config(default = 10)]
+    pub status_interval_secs: u64,
+
+    /// Seconds between checks for a peer ahead of us to sync from
+    #[config(default = 20)]
+    pub sync_interval_secs: u64,
+
+    /// Fetch the header chain first, then download blocks from several peers in parallel
+    #[config(default = false)]
+    pub headers_first: bool,
+}
+
+#[derive(Config, Serialize)]
+pub struct PathsConfig {
+    /// Signed trustee public key, committed to by the genesis of the chain this node opens
+    #[config(default = "./temp/trustee.pub", env = "PNYX_TRUSTEE_KEY")]
+    pub trustee_key: String,
+
+    /// Hex public key of the trustee who must have signed `trustee_key`. Required to run a node
+    #[config(env = "PNYX_TRUSTEE_SIGNER")]
+    pub trustee_signer: Option<String>,
+
+    /// Encrypted trustee decryption key. Only nodes given one tally the election
+    #[config(env = "PNYX_TRUSTEE_SECRET")]
+    pub trustee_secret: Option<String>,
+
+    /// Directory of identity keys making up the census
+    #[config(default = "./temp/identities", env = "PNYX_IDENTITIES")]
+    pub identities: String,
+}
+
+impl PathsConfig {
+    /// The pinned signer of the trustee key, which must be configured
+    pub fn trustee_signer_key(&self) -> Result<VerifyingKey, ConfigError> {
+        let Some(signer) = &self.trustee_signer else {
+            return Err(ConfigError { field: "paths.trustee_signer", reason: "must be set to pin the trustee key, see `trustee show` in the client".into() });
+        };
+        let bytes: [u8; 32] = match hex::decode(signer).ok().and_then(|bytes| bytes.try_into().ok()) {
+            Some(bytes) => bytes,
+            None => return Err(ConfigError { field: "paths.trustee_signer", reason: format!("'{signer}' is not a hex encoded public key") }),
+        };
+        return VerifyingKey::from_bytes(&bytes).map_err(|e| ConfigError { field: "paths.trustee_signer", reason: e.to_string() });
+    }
+}
+
+#[derive(Config, Serialize)]
+pub struct KeysConfig {
+    /// Directory of passphrase-encrypted signing keys
+    #[config(default = "./temp/keystore", env = "PNYX_KEYSTORE")]
+    pub keystore: String,
+
+    /// Keystore identity to sign blocks with. A throwaway key is generated if unset
+    #[config(env = "PNYX_IDENTITY")]
+    pub identity: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    pub field: &'static str,
+    pub reason: String,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid configuration for {}: {}", self.field, self.reason)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn invalid(field: &'static str, reason: impl Into<String>) -> Result<(), ConfigError> {
+    return Err(ConfigError { field, reason: reason.into() });
+}
+
+impl NodeConfig {
+    /// Load every layer, apply the command line on top, then check the result
+    pub(crate) fn load(args: &Cli) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut builder = NodeConfig::builder().env();
+        if let Some(path) = &args.config {
+            builder = builder.file(path);
+        }
+
+        let mut config = builder.load()?;
+        config.apply_cli(args);
+        config.validate()?;
+
+        return Ok(config);
+    }
+
+    fn apply_cli(&mut self, args: &Cli) {
+        if let Some(port) = args.port {
+            self.network.port = port;
+        }
+        if args.headers_first {
+            self.sync.headers_first = true;
+        }
+        if args.no_mdns {
+            self.discovery.mdns = false;
+        }
+        self.discovery.bootstrap.extend(args.bootstrap.iter().cloned());
+        if let Some(address) = &args.rpc_address {
+            self.rpc.address = address.clone();
+        }
+        if args.no_rpc {
+            self.rpc.enabled = false;
+        }
+        if let Some(identity) = &args.identity {
+            self.keys.identity = Some(identity.clone());
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.network.listen_ip.parse::<IpAddr>().is_err() {
+            return invalid("network.listen_ip", format!("'{}' is not an IP address", self.network.listen_ip));
+        }
+        if self.network.gossip_heartbeat_secs == 0 {
+            return invalid("network.gossip_heartbeat_secs", "must be non-zero");
+        }
+
+        if self.chain.block_size == 0 {
+            return invalid("chain.block_size", "must be non-zero");
+        }
+        if self.chain.map_size == 0 || self.chain.map_size > self.chain.max_map_size {
+            return invalid("chain.map_size", "must be non-zero and no larger than max_map_size");
+        }
+        if let Err(e) = self.chain.authority_keys() {
+            return invalid("chain.authorities", e);
+        }
+
+        if self.paths.trustee_signer.is_some() {
+            self.paths.trustee_signer_key()?;
+        }
+
+        if self.sync.status_interval_secs == 0 || self.sync.sync_interval_secs == 0 {
+            return invalid("sync", "intervals must be non-zero");
+        }
+
+        if self.discovery.random_walk_secs == 0 || self.discovery.provider_lookup_secs == 0 {
+            return invalid("discovery", "intervals must be non-zero");
+        }
+        for addr in &self.discovery.bootstrap {
+            if let Err(e) = bootstrap::parse_bootstrap(addr) {
+                return invalid("discovery.bootstrap", e.to_string());
+            }
+        }
+
+        if self.reputation.ban_threshold >= 0 || self.reputation.max_score <= 0 {
+            return invalid("reputation", "ban_threshold must be negative and max_score positive");
+        }
+        if self.reputation.decay_per_minute < 0 {
+            return invalid("reputation.decay_per_minute", "must not be negative");
+        }
+
+        if self.rpc.address.parse::<SocketAddr>().is_err() {
+            return invalid("rpc.address", format!("'{}' is not a socket address", self.rpc.address));
+        }
+
+        return Ok(());
+    }
+
+    /// Every option with its documentation and default, ready to fill in as a config file
+    pub fn template() -> String {
+        return confique::toml::template::<NodeConfig>(confique::toml::FormatOptions::default());
+    }
+
+    /// The resolved configuration, as it would be written to a config file
+    pub fn to_toml(&self) -> String {
+        return toml::to_string_pretty(self).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn cli_overrides_and_validation() {
+        let args = Cli::parse_from(["vote-node", "--port", "5000", "--no-mdns", "--rpc-address", "127.0.0.1:6000"]);
+        let mut config = NodeConfig::builder().load().unwrap();
+        config.apply_cli(&args);
+
+        assert_eq!(config.network.port, 5000);
+        assert!(!config.discovery.mdns);
+        assert!(config.validate().is_ok());
+
+        config.chain.block_size = 0;
+        assert_eq!(config.validate().unwrap_err().field, "chain.block_size");
+
+        config.chain.block_size = 2;
+        config.discovery.bootstrap.push("/ip4/127.0.0.1/tcp/47474".into());
+        assert_eq!(config.validate().unwrap_err().field, "discovery.bootstrap");
+
+        config.discovery.bootstrap.clear();
+        assert!(config.paths.trustee_signer_key().is_err());
+        config.paths.trustee_signer = Some("not a key".into());
+        assert_eq!(config.validate().unwrap_err().field, "paths.trustee_signer");
+    }
+}
@@ -0,0 +1,79 @@
+// The node's signing key, and the `keys` commands managing its keystore
+
+use std::{error::Error, io::Write, path::Path};
+
+use ed25519_dalek::{pkcs8::DecodePrivateKey, SigningKey};
+use rand::rngs::OsRng;
+use tracing::{info, warn};
+use vote_lib::keystore::{self, read_new_passphrase, read_passphrase, KeyFormat, Keystore};
+
+use crate::{cli::{Cli, KeysCommand}, config::NodeConfig, protocols::authority};
+
+/// Pick the key the node signs blocks with, in order: `--private-key`, a test identity,
+/// the configured keystore identity, then a freshly generated throwaway key
+pub fn signing_key(args: &Cli, config: &NodeConfig) -> Result<SigningKey, Box<dyn Error>> {
+    if let Some(private_key) = &args.private_key {
+        warn!("Using the private key given on the command line, which other local users may be able to see");
+        return Ok(keystore::decode_key(private_key.as_bytes(), KeyFormat::Hex)?);
+    }
+
+    if let Some(identity) = args.test_identity {
+        let keyfile = Path::new(&config.paths.identities).join(format!("{identity}.der"));
+        info!("Reading key from file: {}", keyfile.display());
+        return Ok(SigningKey::read_pkcs8_der_file(keyfile)?);
+    }
+
+    if let Some(name) = &config.keys.identity {
+        info!("Unlocking key '{name}' from {}", config.keys.keystore);
+        let keystore = Keystore::open(&config.keys.keystore);
+        return Ok(keystore.load(name, &read_passphrase(&format!("Passphrase for '{name}'"))?)?);
+    }
+
+    info!("No identity configured, generating a throwaway signing key");
+    return Ok(SigningKey::generate(&mut OsRng));
+}
+
+pub fn run(command: &KeysCommand, config: &NodeConfig) -> Result<(), Box<dyn Error>> {
+    let keystore = Keystore::open(&config.keys.keystore);
+
+    match command {
+        KeysCommand::Generate { name } => {
+            let passphrase = read_new_passphrase()?;
+            let public = keystore.generate(name, &passphrase)?;
+            println!("Generated '{name}': {}", hex::encode(public.as_bytes()));
+        },
+        KeysCommand::Import { name, path, format } => {
+            let bytes = std::fs::read(path)?;
+            let format = match format {
+                Some(format) => format.parse()?,
+                None => KeyFormat::detect(&bytes),
+            };
+            let sk = keystore::decode_key(&bytes, format)?;
+            let public = keystore.insert(name, &sk, &read_new_passphrase()?)?;
+            println!("Imported '{name}' from {path}: {}", hex::encode(public.as_bytes()));
+        },
+        KeysCommand::Export { name, format, out } => {
+            let sk = keystore.load(name, &read_passphrase(&format!("Passphrase for '{name}'"))?)?;
+            let encoded = keystore::encode_key(&sk, format.parse()?)?;
+            match out {
+                Some(out) => {
+                    keystore::write_private(out, &encoded)?;
+                    println!("Wrote unencrypted key '{name}' to {out}");
+                },
+                None => std::io::stdout().write_all(&encoded)?,
+            }
+        },
+        KeysCommand::List => {
+            for (name, public) in keystore.list()? {
+                println!("{name}\t{}", hex::encode(public.as_bytes()));
+            }
+        },
+        KeysCommand::Show { name } => {
+            let public = keystore.public_key(name)?;
+            println!("Public key: {}", hex::encode(public.as_bytes()));
+            println!("Peer id: {}", authority::peer_id(&public));
+        },
+    }
+
+    return Ok(());
+}
@@ -3,30 +3,29 @@ mod protocols;
 mod cli;
 mod trustee;
 mod census;
+mod rpc;
+mod config;
+mod keys;
 
 use std::{
-    collections::hash_map::DefaultHasher, error::Error, hash::{Hash, Hasher}, sync::Arc, time::Duration
+    error::Error, sync::Arc, time::Duration
 };
 
-use crate::{census::DumbCensus, votechain::{block::Block, chain::Blockchain, config::BlockchainConfig}, gossipsub::TopicHash};
+use crate::{census::DumbCensus, config::NodeConfig, rpc::state::{NodeState, SyncReport}, trustee::delegations::DelegationGraph, votechain::{block::Block, chain::Blockchain, snapshot}, gossipsub::TopicHash};
 use async_std::{io, net::TcpListener, sync::Mutex};
-use bincode::deserialize;
 use clap::Parser;
-use cli::Cli;
-use confique::Config;
-use ed25519_dalek::{pkcs8::DecodePrivateKey, SigningKey};
+use cli::{Cli, Command};
+use ed25519_dalek::SigningKey;
 use futures::{select, AsyncBufReadExt, FutureExt, StreamExt};
 use libp2p::{
-    gossipsub, identify, identity, kad::{self, store::MemoryStore}, mdns, noise, swarm::{behaviour::toggle::Toggle, NetworkBehaviour, SwarmEvent}, tcp, yamux, Multiaddr, PeerId
+    gossipsub, identify, identity, kad::{self, store::MemoryStore}, mdns, noise, request_response, swarm::{behaviour::toggle::Toggle, NetworkBehaviour, SwarmEvent}, tcp, yamux, Multiaddr, PeerId, StreamProtocol
 };
 use local_ip_address::local_ip;
-use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+use rand::{distributions::Alphanumeric, Rng};
 use tracing::{debug, error, info, level_filters::LevelFilter, span, warn, Level};
 use tracing_subscriber::EnvFilter;
-use vote_lib::{Ballot, Signed};
-use protocols::chain_sync;
-
-static DEFAULT_PORT: u16 = 47474;
+use vote_lib::{genesis::GenesisInfo, keystore::read_passphrase, submission::{SubmitRequest, SubmitResponse}, wire, Ballot, Signed};
+use protocols::{authority::{self, AuthorityClaim}, ballot_gossip, chain_sync::{self, protocol::SyncFailure}, discovery::bootstrap, heartbeat::{self, HeartbeatMessage}, reputation::{self, behaviour::PeerAction}, submission};
 
 #[derive(NetworkBehaviour)]
 struct NodeBehaviours {
@@ -42,25 +41,32 @@ struct NodeBehaviours {
     identify: identify::Behaviour,
 
     chain_sync: chain_sync::behaviour::Behaviour,
+
+    // Chain status advertisement used to pick sync peers
+    heartbeat: heartbeat::Behaviour,
+
+    // Peer scoring, banning peers which misbehave
+    reputation: reputation::behaviour::Behaviour,
+
+    // Ballots submitted directly by clients, with acknowledgements
+    submission: submission::Behaviour,
 }
 
 impl NodeBehaviours {
-    fn new(local_keypair: &identity::Keypair, chain: Arc<Mutex<Blockchain>>) -> Self {
+    fn new(local_keypair: &identity::Keypair, signing_key: &SigningKey, chain: Arc<Mutex<Blockchain>>, config: &NodeConfig) -> Self {
         let local_peer_id = local_keypair.public().to_peer_id();
 
-        // To content-address ballot, we can use the associated Public Key
+        // Content-address ballots by their signed encoding, shared with the client through vote_lib
         let message_id_fn = |message: &gossipsub::Message| {
-            let mut s = DefaultHasher::new();
-            message.data.hash(&mut s);
-            gossipsub::MessageId::from(s.finish().to_string())
+            gossipsub::MessageId::from(vote_lib::gossip::message_id(&message.data))
         };
 
         // Build gossipsub behaviour
         let gossipsub_config = gossipsub::ConfigBuilder::default()
-            // TODO: Update Interval to use a config/flag
-            .heartbeat_interval(Duration::from_secs(10)) // Avoid cluttering the log space
+            .heartbeat_interval(Duration::from_secs(config.network.gossip_heartbeat_secs)) // Avoid cluttering the log space
             .max_transmit_size(1000000) // Expand maximum transmit size to fit ballots with proofs
             .validation_mode(gossipsub::ValidationMode::Strict) // Strict validation enforces message signing
+            .validate_messages() // Hold messages back from propagation until the ballot has been validated
             .message_id_fn(message_id_fn) // content-address messages. No two messages of the same content will be propagated.
             .build()
             .unwrap(); // TODO: Potentially replace with better error handling which maps the err to std::error::Error
@@ -71,17 +77,30 @@ impl NodeBehaviours {
         .unwrap();
 
         // Discover any nodes on the same private network as this node -> Trusted bootstrapping
-        let mdns = Toggle::from(Some(mdns::async_io::Behaviour::new(mdns::Config::default(), local_peer_id).unwrap()));
-
-        // Peer discovery and routing via Kademlia DHT
-        let kad = kad::Behaviour::new(local_peer_id, MemoryStore::new(local_peer_id));
-
+        // Disabled when relying on bootstrap peers alone, e.g. several nodes on loopback
+        let mdns = Toggle::from(config.discovery.mdns.then(|| mdns::async_io::Behaviour::new(mdns::Config::default(), local_peer_id).unwrap()));
+
+        // Peer discovery and routing via Kademlia DHT, kept apart from the public IPFS DHT
+        let mut kad_config = kad::Config::default();
+        kad_config.set_protocol_names(vec![StreamProtocol::new("/votechain/kad/1.0.0")]);
+        let mut kad = kad::Behaviour::with_config(local_peer_id, MemoryStore::new(local_peer_id), kad_config);
+        // Nodes without a confirmed external address would otherwise stay in client mode and never serve provider records
+        kad.set_mode(Some(kad::Mode::Server));
+
+        // Advertise the key we sign blocks with, proving we hold it
+        let claim = AuthorityClaim::new(signing_key, &local_peer_id);
         let identify = identify::Behaviour::new(identify::Config::new(
             "/pnyx/id/1.0.0".to_string(),
             local_keypair.public(),
-        ));
+        ).with_agent_version(claim.to_agent_version()));
+
+        let chain_sync = protocols::chain_sync::behaviour::Behaviour::new(chain, config.sync.headers_first, Duration::from_secs(config.sync.sync_interval_secs));
 
-        let chain_sync = protocols::chain_sync::behaviour::Behaviour::new(chain);
+        let heartbeat = heartbeat::get_behaviour();
+
+        let reputation = reputation::behaviour::Behaviour::new(config.reputation.clone());
+
+        let submission = submission::get_behaviour();
 
         return Self {
             gossipsub,
@@ -89,6 +108,9 @@ impl NodeBehaviours {
             kad,
             identify,
             chain_sync,
+            heartbeat,
+            reputation,
+            submission,
         };
     }
 }
@@ -113,6 +135,25 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let args: Cli = cli::Cli::parse();
 
+    // The template needs no config, so print it before a missing or invalid one can fail loading
+    if let Some(Command::PrintConfig { template: true }) = &args.command {
+        print!("{}", NodeConfig::template());
+        return Ok(());
+    }
+
+    let config = NodeConfig::load(&args)?;
+
+    match &args.command {
+        Some(Command::PrintConfig { .. }) => {
+            print!("{}", config.to_toml());
+            return Ok(());
+        },
+        Some(Command::Keys(command)) => return keys::run(command, &config),
+        None => {},
+    }
+
+    let mut signing_key = keys::signing_key(&args, &config)?;
+
     // Subscribe to a random issue topic for testing purposes
     let issue_id = match args.chain_postfix {
         Some(postfix) => postfix,
@@ -125,24 +166,34 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     };
 
-    
-    let mut signing_key = match args.test_identity {
-        Some(identity) => {
-            let keyfile = format!("./temp/identities/{identity}.der");
-            info!("Reading key from file: {keyfile}");
-            SigningKey::read_pkcs8_der_file(keyfile)?
-        },
-        None => SigningKey::generate(&mut OsRng),
-    };
+    let census = Arc::new(DumbCensus::new(&config.paths.identities));
+    // Random delegations stand in for real ones, fixed for the node's lifetime so lookups match resolution
+    let delegations = Arc::new(DelegationGraph::random(census.as_vec()));
 
-    let census = DumbCensus::new();
+    // Ballots are encrypted under the published trustee key, committed to by the chain's genesis
+    let trustee_key = vote_lib::trustee::read_public(&config.paths.trustee_key, &config.paths.trustee_signer_key()?)?;
+    info!("Using trustee key '{}' with fingerprint {}", trustee_key.label, trustee_key.fingerprint());
+    let genesis = GenesisInfo::new(issue_id.clone(), trustee_key);
 
     // Setup Storage
-    let chain = Arc::new(Mutex::new(Blockchain::new(BlockchainConfig::builder().load()?, issue_id, &signing_key)?));
+    let mut blockchain = Blockchain::new(config.chain.clone(), genesis, &signing_key)?;
+    info!("Opened chain with genesis {}", blockchain.get_hash_at(1)?);
+    let ek = blockchain.trustee_key().clone();
+    let genesis_hash = blockchain.genesis_hash();
+
+    let dk = match &config.paths.trustee_secret {
+        Some(path) => Some(vote_lib::trustee::read_secret(path, &ek, &read_passphrase("Trustee passphrase")?)?),
+        None => None,
+    };
 
-    // TODO: Link ed25519 signing curve key into libp2p identity
-    // let keypair = Keypair::ed25519_from_bytes(&mut signing_key.to_keypair_bytes()).unwrap();
-    let mut swarm = libp2p::SwarmBuilder::with_new_identity()
+    if let Some(snapshot_path) = args.snapshot {
+        info!("Bootstrapping chain from snapshot: {snapshot_path}");
+        blockchain.bootstrap(snapshot::read_from(snapshot_path)?, dk.as_ref())?;
+    }
+    let chain = Arc::new(Mutex::new(blockchain));
+
+    // Derive our PeerId from the key we sign blocks with
+    let mut swarm = libp2p::SwarmBuilder::with_existing_identity(authority::keypair(&signing_key))
         .with_async_std()
         .with_tcp(
             tcp::Config::default(),
@@ -154,9 +205,9 @@ async fn main() -> Result<(), Box<dyn Error>> {
         // TODO: Replace with proper error handling? What are the failure conditions for this construction
         .unwrap()
         .with_quic()
-        .with_behaviour(|key| NodeBehaviours::new(key, chain.clone()))
+        .with_behaviour(|key| NodeBehaviours::new(key, &signing_key, chain.clone(), &config))
         .unwrap()
-        .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+        .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(config.network.idle_timeout_secs)))
         .build();
 
     // Create a Gossipsub topic
@@ -170,17 +221,12 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
 
     // let local_ip = local_ip().unwrap();
-    let local_ip = "0.0.0.0";
-    let port: u16 = match TcpListener::bind(("127.0.0.1", DEFAULT_PORT)).await {
-        Ok(_) => {
-            let mut port: u16 = 0;
-            if args.test_identity == Some(1) {
-                port = DEFAULT_PORT
-            }
-            port
-        },
+    let local_ip = &config.network.listen_ip;
+    // Test identities other than the first share a host, so leave the configured port to it
+    let port: u16 = match TcpListener::bind(("127.0.0.1", config.network.port)).await {
+        Ok(_) if args.test_identity.unwrap_or(1) == 1 => config.network.port,
         // If the port is already in use, bind to a random available one
-        Err(_) => 0
+        _ => 0
     };
 
     let our_addr: Multiaddr = format!("/ip4/{}/tcp/{}", local_ip, port).parse()?;
@@ -196,10 +242,40 @@ async fn main() -> Result<(), Box<dyn Error>> {
         our_addr
     );
 
+    // Seed the routing table with the bootstrap peers, then walk the DHT from them
+    for addr in &config.discovery.bootstrap {
+        let (peer_id, multiaddr) = bootstrap::parse_bootstrap(addr)?;
+        info!("Bootstrapping from {peer_id} on {multiaddr}");
+        swarm.behaviour_mut().kad.add_address(&peer_id, multiaddr.clone());
+        swarm.behaviour_mut().chain_sync.add_explicit_peer(peer_id);
+        let _ = swarm.dial(multiaddr);
+    }
+    if let Err(e) = swarm.behaviour_mut().kad.bootstrap() {
+        debug!("Skipping Kademlia bootstrap: {e}");
+    }
+
+    // Advertise that we host this issue's chain
+    let issue_key = bootstrap::issue_key(&issue_id);
+    swarm.behaviour_mut().kad.start_providing(issue_key.clone())?;
+
+    // Serve the query API, sharing the network view the event loop keeps up to date
+    let node_state = Arc::new(NodeState::new(chain.clone(), census.clone(), delegations.clone(), *swarm.local_peer_id()));
+    if config.rpc.enabled {
+        let rpc_address = &config.rpc.address;
+        let listener = match TcpListener::bind(&rpc_address).await {
+            Ok(listener) => listener,
+            // If the port is already in use, bind to a random available one
+            Err(_) => TcpListener::bind((rpc_address.parse::<std::net::SocketAddr>()?.ip(), 0)).await?,
+        };
+        info!("RPC API listening on http://{}", listener.local_addr()?);
+        async_std::task::spawn(rpc::http::serve(listener, node_state.clone()));
+    }
+
     let mut stdin_buf = io::BufReader::new(io::stdin()).lines().fuse();
     let mut delay = futures_timer::Delay::new(std::time::Duration::from_secs(5)).fuse();
-
-    let (ek, dk) = bincode::deserialize::<paillier::Keypair>(&std::fs::read("./temp/trustees.key").unwrap()).unwrap().keys();
+    let mut heartbeat_ticker = futures_ticker::Ticker::new(Duration::from_secs(config.sync.status_interval_secs)).fuse();
+    let mut random_walk_ticker = futures_ticker::Ticker::new(Duration::from_secs(config.discovery.random_walk_secs)).fuse();
+    let mut provider_ticker = futures_ticker::Ticker::new(Duration::from_secs(config.discovery.provider_lookup_secs)).fuse();
 
     // Event Handling Loop
     loop {
@@ -212,30 +288,18 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
                 println!("{:?}", chain);
 
-                if swarm.connected_peers().collect::<Vec<&PeerId>>().len() != 0 {
-                    let peer_id = swarm.connected_peers().collect::<Vec<&PeerId>>()[0].clone();
-                    info!("Forcing Sync with peer {}", peer_id);
-    
-                    swarm.behaviour_mut().chain_sync.force_sync(peer_id);
+                if args.test_append == 0 {
+                    continue;
                 }
 
-                
-
                 let mut guard = chain.lock().await;
                 let start_h = guard.get_height();
 
-                info!("Post Sync Height: {}", start_h);
-
-
-                if args.test_append == 0 {
-                    continue;
-                }
-
                 for height in start_h..(start_h+args.test_append) {
                     let genesis = guard.get_block(&height)?;
                     info!("{}", genesis.hash());
                     
-                    let ballots = vec!(Signed::new(&signing_key, Ballot::new(&ek, true, "test".into())));
+                    let ballots = vec!(Signed::new(&signing_key, Ballot::new(&ek, true, guard.issue_id().to_string(), guard.genesis_hash())));
                     let block = Block::new(&mut signing_key, &genesis, ballots).unwrap();
 
                     match guard.append(block) {
@@ -247,6 +311,32 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 }
             }
 
+            // Advertise our chain status to every connected peer
+            _ = heartbeat_ticker.select_next_some() => {
+                let status = match HeartbeatMessage::from_chain(&*chain.lock().await) {
+                    Ok(status) => status,
+                    Err(e) => {
+                        error!("Failed to read chain status: {}", e);
+                        continue;
+                    }
+                };
+
+                let peers: Vec<PeerId> = swarm.connected_peers().cloned().collect();
+                for peer_id in peers {
+                    swarm.behaviour_mut().heartbeat.send_request(&peer_id, status.clone());
+                }
+            },
+
+            // Look up a random peer id, discovering peers across the keyspace
+            _ = random_walk_ticker.select_next_some() => {
+                swarm.behaviour_mut().kad.get_closest_peers(PeerId::random());
+            },
+
+            // Find other nodes hosting our issue chain
+            _ = provider_ticker.select_next_some() => {
+                swarm.behaviour_mut().kad.get_providers(issue_key.clone());
+            },
+
             // Enable user input to the console in dev mode for debugging.
             line = stdin_buf.select_next_some() => {
                 match line {
@@ -293,28 +383,42 @@ async fn main() -> Result<(), Box<dyn Error>> {
                     let id_hex: String = format!("{}", id)[0..8].to_string();
                     let span = span!(Level::INFO, "message", id = id_hex);
 
-                    // Recieve ballot, validate and pool
+                    // Recieve ballot, validate and pool. Only accepted ballots are propagated further
                     info!(parent: &span, "Recieving Ballot...");
-                    let ballot: Signed<Ballot> = match deserialize(&message.data) {
-                        Ok(ballot) => ballot,
-                        Err(_) => {
-                            warn!(parent: &span, "Recieved Invalid Ballot: {}", id);
+                    let validation = ballot_gossip::validate_ballot(&message.data, &issue_id, &genesis_hash, &census, &ek);
+                    let _ = swarm.behaviour_mut().gossipsub.report_message_validation_result(&id, &peer_id, validation.acceptance());
+
+                    let ballot = match validation {
+                        ballot_gossip::Validation::Accept(ballot) => ballot,
+                        ballot_gossip::Validation::Reject(reason) => {
+                            warn!(parent: &span, "Rejected ballot {id} from peer {peer_id}: {reason}");
+                            swarm.behaviour_mut().reputation.report(peer_id, PeerAction::InvalidBallot);
                             continue;
-                        }
+                        },
+                        ballot_gossip::Validation::Ignore(reason) => {
+                            debug!(parent: &span, "Ignored ballot {id} from peer {peer_id}: {reason}");
+                            continue;
+                        },
                     };
-
-                    if !ballot.signature_valid() || !census.contains_voter(&ballot.signer()) {
-                        // TODO: Reduce rep score of invalid caster
-                        warn!(parent: &span, "Got message: {id} from peer: {peer_id} with invalid signature");
-                        continue;
-                    }
+                    swarm.behaviour_mut().reputation.report(peer_id, PeerAction::ValidBallot);
 
                     info!(parent: &span, "Got message: '{ballot}' with id: {id} from peer: {peer_id}");
                     swarm.behaviour_mut().chain_sync.pool_ballot(ballot).await;
 
+                    // Only a node holding the trustees' decryption key can tally
+                    let Some(dk) = &dk else {
+                        continue;
+                    };
+
                     info!("Attempting Evaluation");
-                    match swarm.behaviour().chain_sync.try_resolve(&dk, &ek, &census).await {
+                    match swarm.behaviour().chain_sync.try_resolve(dk, &ek, &delegations).await {
                         Some(result) => {
+                            // Never hold the election lock while taking the chain lock, the RPC takes them the other way round
+                            let height = chain.lock().await.get_height();
+                            let mut election = node_state.election.lock().await;
+                            election.result = Some(result);
+                            election.resolved_at = Some(height);
+
                             if result {
                                 info!("Vote Passed Successfully")
                             } else {
@@ -327,19 +431,190 @@ async fn main() -> Result<(), Box<dyn Error>> {
                     }
                 },
 
+                // Chain Sync Events
+                SwarmEvent::Behaviour(NodeBehavioursEvent::ChainSync(chain_sync::behaviour::Event::SyncInitiated(peer_id))) => {
+                    debug!("Started sync with {peer_id}");
+                    node_state.network.lock().await.syncing = Some(peer_id);
+                },
+                SwarmEvent::Behaviour(NodeBehavioursEvent::ChainSync(chain_sync::behaviour::Event::SyncCompleted {
+                    peer, fork_index, blocks_applied, ballots_returned
+                })) => {
+                    info!("Synced with {peer}: forked at {fork_index}, applied {blocks_applied} blocks, returned {ballots_returned} ballots to the pool");
+                    let mut network = node_state.network.lock().await;
+                    network.syncing = None;
+                    network.last_sync = Some(SyncReport { peer, blocks_applied, error: None });
+                    drop(network);
+
+                    if blocks_applied > 0 {
+                        swarm.behaviour_mut().reputation.report(peer, PeerAction::SyncServed);
+                    }
+                },
+                SwarmEvent::Behaviour(NodeBehavioursEvent::ChainSync(chain_sync::behaviour::Event::SyncError { peer, cause })) => {
+                    warn!("Sync with {peer} failed: {cause}");
+                    let mut network = node_state.network.lock().await;
+                    network.syncing = None;
+                    network.last_sync = Some(SyncReport { peer, blocks_applied: 0, error: Some(cause.to_string()) });
+                    drop(network);
+
+                    match cause {
+                        SyncFailure::InvalidData(_) | SyncFailure::NoCommonBlock => {
+                            swarm.behaviour_mut().reputation.report(peer, PeerAction::InvalidSyncData);
+                        },
+                        SyncFailure::Stream(_) | SyncFailure::Timeout => swarm.behaviour_mut().reputation.report(peer, PeerAction::SyncFailure),
+                        SyncFailure::Local(_) => {},
+                    }
+                },
+
+                // Heartbeat Events
+                SwarmEvent::Behaviour(NodeBehavioursEvent::Heartbeat(request_response::Event::Message { peer, message })) => match message {
+                    request_response::Message::Request { request, channel, .. } => {
+                        debug!("Heartbeat from {peer} at height {}", request.height);
+                        node_state.network.lock().await.peer_status.insert(peer, request.clone());
+                        swarm.behaviour_mut().chain_sync.update_peer_status(peer, request);
+
+                        match HeartbeatMessage::from_chain(&*chain.lock().await) {
+                            Ok(status) => {
+                                let _ = swarm.behaviour_mut().heartbeat.send_response(channel, status);
+                            },
+                            Err(e) => error!("Failed to read chain status: {}", e),
+                        }
+                    },
+                    request_response::Message::Response { response, .. } => {
+                        debug!("Heartbeat response from {peer} at height {}", response.height);
+                        node_state.network.lock().await.peer_status.insert(peer, response.clone());
+                        swarm.behaviour_mut().chain_sync.update_peer_status(peer, response);
+                    },
+                },
+                SwarmEvent::Behaviour(NodeBehavioursEvent::Heartbeat(request_response::Event::OutboundFailure { peer, error, .. })) => {
+                    debug!("Heartbeat to {peer} failed: {error}");
+                    // Clients don't speak the heartbeat protocol, which is no fault of theirs
+                    if !matches!(error, request_response::OutboundFailure::UnsupportedProtocols) {
+                        swarm.behaviour_mut().reputation.report(peer, PeerAction::ProtocolError);
+                    }
+                },
+                SwarmEvent::Behaviour(NodeBehavioursEvent::Heartbeat(request_response::Event::InboundFailure { peer, error, .. })) => {
+                    debug!("Heartbeat from {peer} failed: {error}");
+                    swarm.behaviour_mut().reputation.report(peer, PeerAction::ProtocolError);
+                },
+
+                // Submission Events
+                SwarmEvent::Behaviour(NodeBehavioursEvent::Submission(request_response::Event::Message {
+                    peer, message: request_response::Message::Request { request, channel, .. }
+                })) => {
+                    let response = match request {
+                        SubmitRequest::Submit(ballot) => {
+                            match ballot_gossip::validate_signed(ballot, &issue_id, &genesis_hash, &census, &ek) {
+                                ballot_gossip::Validation::Accept(ballot) => {
+                                    info!("Accepted ballot submitted by {peer}");
+                                    // Gossip on the client's behalf, the mesh won't echo it back to us
+                                    if let Err(e) = swarm.behaviour_mut().gossipsub.publish(vote_topic.hash(), wire::encode(&ballot)) {
+                                        warn!("Failed to gossip submitted ballot: {e:?}");
+                                    }
+                                    swarm.behaviour_mut().chain_sync.pool_ballot(ballot).await;
+                                    SubmitResponse::Accepted
+                                },
+                                ballot_gossip::Validation::Reject(reason) => {
+                                    info!("Rejected ballot submitted by {peer}: {reason}");
+                                    SubmitResponse::Rejected(reason.to_string())
+                                },
+                                ballot_gossip::Validation::Ignore(reason) => {
+                                    info!("Ignored ballot submitted by {peer}: {reason}");
+                                    SubmitResponse::Ignored(reason.to_string())
+                                },
+                            }
+                        },
+                        SubmitRequest::Status(content_id) => {
+                            let guard = chain.lock().await;
+                            match guard.ballot_inclusion(&content_id) {
+                                Some(inclusion) => SubmitResponse::Included(inclusion),
+                                None if guard.is_pooled(&content_id) => SubmitResponse::Pending,
+                                None => SubmitResponse::Unknown,
+                            }
+                        },
+                        SubmitRequest::Genesis => {
+                            let guard = chain.lock().await;
+                            match guard.get_block(&1) {
+                                Ok(genesis) => SubmitResponse::Genesis(genesis.header(), guard.genesis_info().clone()),
+                                Err(e) => {
+                                    warn!("Failed to read genesis block: {e}");
+                                    SubmitResponse::Unknown
+                                },
+                            }
+                        },
+                        SubmitRequest::Receipt(content_id) => {
+                            let guard = chain.lock().await;
+                            match guard.ballot_receipt(&content_id) {
+                                Ok(Some(receipt)) => SubmitResponse::Receipt(receipt),
+                                Ok(None) if guard.is_pooled(&content_id) => SubmitResponse::Pending,
+                                Ok(None) => SubmitResponse::Unknown,
+                                Err(e) => {
+                                    error!("Failed to build receipt: {}", e);
+                                    SubmitResponse::Unknown
+                                },
+                            }
+                        },
+                    };
+
+                    let _ = swarm.behaviour_mut().submission.send_response(channel, response);
+                },
+                SwarmEvent::Behaviour(NodeBehavioursEvent::Submission(request_response::Event::InboundFailure { peer, error, .. })) => {
+                    debug!("Submission from {peer} failed: {error}");
+                },
+
+                // Reputation Events
+                SwarmEvent::Behaviour(NodeBehavioursEvent::Reputation(reputation::behaviour::Event::Banned { peer, score })) => {
+                    warn!("Banned peer {peer} with score {score}");
+                    swarm.behaviour_mut().gossipsub.blacklist_peer(&peer);
+                },
+                SwarmEvent::Behaviour(NodeBehavioursEvent::Reputation(reputation::behaviour::Event::Unbanned(peer))) => {
+                    info!("Ban on peer {peer} expired");
+                    swarm.behaviour_mut().gossipsub.remove_blacklisted_peer(&peer);
+                },
+
                 // Kad Events
-                // TODO: Expand for better discovery. Maybe should register topics against KAD?
                 SwarmEvent::Behaviour(NodeBehavioursEvent::Kad(kad::Event::RoutingUpdated {
                     peer, ..
                 })) => {
                     info!("Discovered Route to Peer: '{peer}'");
                 },
+                SwarmEvent::Behaviour(NodeBehavioursEvent::Kad(kad::Event::OutboundQueryProgressed { result, .. })) => match result {
+                    kad::QueryResult::GetProviders(Ok(kad::GetProvidersOk::FoundProviders { providers, .. })) => {
+                        for peer_id in providers {
+                            if peer_id == *swarm.local_peer_id() {
+                                continue;
+                            }
+
+                            swarm.behaviour_mut().chain_sync.add_explicit_peer(peer_id);
+                            if !swarm.is_connected(&peer_id) {
+                                info!("Found provider of issue {issue_id}: {peer_id}");
+                                let _ = swarm.dial(peer_id);
+                            }
+                        }
+                    },
+                    kad::QueryResult::StartProviding(Err(e)) => warn!("Failed to advertise issue {issue_id}: {e:?}"),
+                    kad::QueryResult::Bootstrap(Ok(kad::BootstrapOk { num_remaining, .. })) if num_remaining == 0 => {
+                        info!("Kademlia bootstrap complete");
+                    },
+                    kad::QueryResult::Bootstrap(Err(e)) => warn!("Kademlia bootstrap failed: {e:?}"),
+                    other => debug!("Kademlia query progressed: {other:?}"),
+                },
 
                 SwarmEvent::Behaviour(NodeBehavioursEvent::Identify(identify::Event::Sent { peer_id, .. })) => {
                     debug!("Sent identify info to {peer_id:?}");
                 },
                 SwarmEvent::Behaviour(NodeBehavioursEvent::Identify(identify::Event::Received { peer_id, info: peer_info })) => {
                     debug!("Received {peer_info:?}");
+                    match AuthorityClaim::from_agent_version(&peer_info.agent_version) {
+                        Some(claim) if claim.verify(&peer_id) => {
+                            debug!("Peer {peer_id} holds authority key {} (census member: {})", hex::encode(claim.key.as_bytes()), census.contains_voter(&claim.key));
+                        },
+                        Some(_) => {
+                            warn!("Peer {peer_id} advertised an authority key it could not prove it holds");
+                            swarm.behaviour_mut().reputation.report(peer_id, PeerAction::ProtocolError);
+                        },
+                        None => {},
+                    }
+
                     for address in peer_info.listen_addrs {
                         // TODO: Work out if we should actually be storing every address we id
                         swarm.behaviour_mut().kad.add_address(&peer_id, address.clone());
@@ -347,6 +622,13 @@ async fn main() -> Result<(), Box<dyn Error>> {
                     }
                 },
 
+                SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                    node_state.network.lock().await.connected.insert(peer_id);
+                },
+                SwarmEvent::ConnectionClosed { peer_id, num_established: 0, .. } => {
+                    node_state.network.lock().await.disconnected(&peer_id);
+                },
+
                 // Startup Events
                 SwarmEvent::NewListenAddr { address, .. } => info!("Local node listening on {address}"),
 
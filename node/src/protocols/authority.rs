@@ -0,0 +1,90 @@
+// Linking a node's ed25519 signing key to its libp2p identity
+//
+// The node's PeerId is derived from the same key that signs its blocks, and identify
+// advertises that key along with a signature over the PeerId as proof of possession.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use libp2p::{identity, PeerId};
+
+const AGENT_PREFIX: &str = "pnyx-node/0.1.0";
+
+/// Domain separation for the proof of possession, so it can't be replayed as any other signature
+const CLAIM_CONTEXT: &[u8] = b"pnyx/authority-claim";
+
+/// The libp2p identity for a node signing with `sk`
+pub fn keypair(sk: &SigningKey) -> identity::Keypair {
+    return identity::Keypair::ed25519_from_bytes(sk.to_bytes()).unwrap();
+}
+
+/// The PeerId a node signing with `key` is known by, mapping block signatories to peers
+pub fn peer_id(key: &VerifyingKey) -> PeerId {
+    let public = identity::ed25519::PublicKey::try_from_bytes(key.as_bytes()).unwrap();
+    return identity::PublicKey::from(public).to_peer_id();
+}
+
+/// An authority key claimed by a peer, with a signature over its PeerId proving the peer holds it
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthorityClaim {
+    pub key: VerifyingKey,
+    proof: Signature,
+}
+
+impl AuthorityClaim {
+    pub fn new(sk: &SigningKey, peer_id: &PeerId) -> Self {
+        return Self {
+            key: sk.verifying_key(),
+            proof: sk.sign(&claim_message(peer_id)),
+        };
+    }
+
+    /// Whether the claim was made by the holder of the key for this peer
+    pub fn verify(&self, peer_id: &PeerId) -> bool {
+        return self.key.verify(&claim_message(peer_id), &self.proof).is_ok();
+    }
+
+    /// Encode the claim into an identify agent version
+    pub fn to_agent_version(&self) -> String {
+        return format!("{AGENT_PREFIX} authority/{}/{}", hex::encode(self.key.as_bytes()), hex::encode(self.proof.to_bytes()));
+    }
+
+    /// Read a claim back out of an identify agent version, if the peer advertised one
+    pub fn from_agent_version(agent_version: &str) -> Option<Self> {
+        let claim = agent_version.strip_prefix(AGENT_PREFIX)?.trim().strip_prefix("authority/")?;
+        let (key, proof) = claim.split_once('/')?;
+
+        let key = VerifyingKey::from_bytes(&hex::decode(key).ok()?.try_into().ok()?).ok()?;
+        let proof = Signature::from_slice(&hex::decode(proof).ok()?).ok()?;
+
+        return Some(Self { key, proof });
+    }
+}
+
+fn claim_message(peer_id: &PeerId) -> Vec<u8> {
+    return [CLAIM_CONTEXT, &peer_id.to_bytes()].concat();
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn identity_follows_signing_key() {
+        let sk = SigningKey::generate(&mut OsRng);
+        assert_eq!(keypair(&sk).public().to_peer_id(), peer_id(&sk.verifying_key()));
+    }
+
+    #[test]
+    fn claims_round_trip_and_verify() {
+        let sk = SigningKey::generate(&mut OsRng);
+        let local_peer_id = peer_id(&sk.verifying_key());
+        let claim = AuthorityClaim::new(&sk, &local_peer_id);
+
+        let decoded = AuthorityClaim::from_agent_version(&claim.to_agent_version()).unwrap();
+        assert_eq!(decoded, claim);
+        assert!(decoded.verify(&local_peer_id));
+        assert!(!decoded.verify(&PeerId::random()));
+        assert!(AuthorityClaim::from_agent_version("rust-libp2p/0.44.0").is_none());
+    }
+}
@@ -1 +1,105 @@
-// TODO: Potentially move out the gossip handling to here for modularity
\ No newline at end of file
+// Validation of ballots received over gossipsub
+//
+// Gossipsub runs with manual validation, so a ballot is only forwarded to the rest of
+// the mesh once it has passed every check here and been reported as accepted.
+
+use blake3::Hash;
+use libp2p::gossipsub::MessageAcceptance;
+use paillier::EncryptionKey;
+use vote_lib::{wire, Ballot, Signed, SigningContext};
+
+use crate::census::DumbCensus;
+
+/// Outcome of validating a gossiped ballot
+#[derive(Debug)]
+pub enum Validation {
+    /// Valid for our issue, pool it and propagate
+    Accept(Signed<Ballot>),
+    /// Malformed or forged, drop it and penalise the peer which forwarded it
+    Reject(&'static str),
+    /// Well formed but not ours to handle, drop it without penalty
+    Ignore(&'static str),
+}
+
+impl Validation {
+    pub fn acceptance(&self) -> MessageAcceptance {
+        match self {
+            Validation::Accept(_) => return MessageAcceptance::Accept,
+            Validation::Reject(_) => return MessageAcceptance::Reject,
+            Validation::Ignore(_) => return MessageAcceptance::Ignore,
+        }
+    }
+}
+
+/// The single path every gossiped ballot takes before it is pooled or propagated
+pub fn validate_ballot(data: &[u8], issue_id: &str, genesis: &Hash, census: &DumbCensus, ek: &EncryptionKey) -> Validation {
+    match wire::decode::<Ballot>(data) {
+        Ok(ballot) => return validate_signed(ballot, issue_id, genesis, census, ek),
+        Err(_) => return Validation::Reject("could not be decoded"),
+    }
+}
+
+/// Checks shared by gossiped ballots and those submitted directly by clients
+pub fn validate_signed(ballot: Signed<Ballot>, issue_id: &str, genesis: &Hash, census: &DumbCensus, ek: &EncryptionKey) -> Validation {
+    // Still decoded from older clients, whose signatures aren't bound to the issue
+    if ballot.signing_retired() {
+        return Validation::Ignore("signed under a retired signing version");
+    }
+
+    if ballot.issue_id() != issue_id {
+        return Validation::Ignore("cast for a different issue");
+    }
+
+    if ballot.genesis() != genesis {
+        return Validation::Ignore("cast on another chain for the issue");
+    }
+
+    if !ballot.signature_valid(&SigningContext::Issue { issue_id, genesis }) {
+        return Validation::Reject("signature invalid");
+    }
+
+    if !census.contains_voter(&ballot.signer()) {
+        return Validation::Reject("signer is not in the census");
+    }
+
+    if !ballot.validate_proofs(ek) {
+        return Validation::Reject("range proofs invalid");
+    }
+
+    return Validation::Accept(ballot);
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::SigningKey;
+    use paillier::{KeyGeneration, Paillier};
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn validation_outcomes() {
+        let (ek, _dk) = Paillier::keypair().keys();
+        let voter = SigningKey::generate(&mut OsRng);
+        let outsider = SigningKey::generate(&mut OsRng);
+        let census = DumbCensus::from_vec(vec![voter.verifying_key()]);
+        let genesis = Hash::from([0; 32]);
+
+        let ballot = wire::encode(&Signed::new(&voter, Ballot::new(&ek, true, "issue".into(), genesis)));
+        assert!(matches!(validate_ballot(&ballot, "issue", &genesis, &census, &ek), Validation::Accept(_)));
+        assert!(matches!(validate_ballot(&ballot, "other", &genesis, &census, &ek), Validation::Ignore(_)));
+        assert!(matches!(validate_ballot(&ballot[..ballot.len() - 1], "issue", &genesis, &census, &ek), Validation::Reject(_)));
+
+        let outsider_ballot = wire::encode(&Signed::new(&outsider, Ballot::new(&ek, true, "issue".into(), genesis)));
+        assert!(matches!(validate_ballot(&outsider_ballot, "issue", &genesis, &census, &ek), Validation::Reject(_)));
+
+        // Proofs made under any key but the chain's trustee key don't verify
+        let (other_ek, _dk) = Paillier::keypair().keys();
+        let misdirected = wire::encode(&Signed::new(&voter, Ballot::new(&other_ek, true, "issue".into(), genesis)));
+        assert!(matches!(validate_ballot(&misdirected, "issue", &genesis, &census, &ek), Validation::Reject(_)));
+
+        // A chain restarted for the same issue has another genesis, so its ballots aren't ours
+        let other_chain = Hash::from([1; 32]);
+        assert!(matches!(validate_ballot(&ballot, "issue", &other_chain, &census, &ek), Validation::Ignore(_)));
+    }
+}
@@ -1,28 +1,40 @@
-use std::{collections::{HashMap, HashSet, VecDeque}, sync::Arc, task::Poll, time::Duration};
+use std::{collections::{HashMap, HashSet, VecDeque}, sync::Arc, task::Poll, time::{Duration, Instant}};
 
 use async_std::sync::Mutex;
-use futures::StreamExt;
+use futures::{future::BoxFuture, FutureExt, StreamExt};
 use futures_ticker::Ticker;
 use libp2p::{swarm::{behaviour::ConnectionEstablished, ConnectionClosed, FromSwarm, NetworkBehaviour, NotifyHandler, THandlerInEvent, ToSwarm}, PeerId};
 use paillier::{DecryptionKey, EncryptionKey};
 use tracing::info;
 use vote_lib::{Ballot, Signed};
-use rand::seq::SliceRandom;
 
-use crate::{census::DumbCensus, trustee, votechain::chain::Blockchain};
+use crate::{protocols::heartbeat::HeartbeatMessage, trustee::{self, delegations::DelegationGraph}, votechain::{block::Block, chain::{Blockchain, ChainUpdate}, errors}};
 
-use super::handler::{self, Handler};
+use super::{download::{BlockDownload, DownloadError}, handler::{self, Handler, OutEvent}, protocol::{HeaderChain, SyncFailure}};
+
+/// How long a peer is passed over as a sync source after a sync with it failed or applied nothing
+pub const UNPRODUCTIVE_SYNC_BACKOFF: Duration = Duration::from_secs(60);
 
 #[derive(Debug)]
 pub enum Event {
     ResolveReady,
 
-    // /// Began a sync attempt with a peer
-    // SyncInitiated(PeerId),
-    // /// Successfully updated our chain to a new longest version
-    // SyncCompleted(PeerId, u32),
-    // /// Failed to update our chain
-    // SyncError,
+    /// Began a sync attempt with a peer
+    SyncInitiated(PeerId),
+    /// Finished syncing with a peer, whether or not our chain changed
+    SyncCompleted {
+        peer: PeerId,
+        /// Most recent block shared with the peer
+        fork_index: u32,
+        blocks_applied: u32,
+        /// Ballots from replaced blocks which were returned to the pool
+        ballots_returned: usize,
+    },
+    /// Failed to sync with a peer
+    SyncError {
+        peer: PeerId,
+        cause: SyncFailure,
+    },
 
 
     // Old Heartbeat Events
@@ -47,21 +59,59 @@ pub struct Behaviour {
     /// List of Known Peers used for syncing
     sync_peers: HashSet<PeerId>,
 
+    /// Latest chain status advertised by each peer through heartbeats
+    peer_status: HashMap<PeerId, HeartbeatMessage>,
+
+    /// Peer we are currently syncing from, at most one sync runs at a time
+    syncing: Option<PeerId>,
+
+    /// Peers passed over as sync sources until the given time, so one overstating its work can't starve sync
+    penalised: HashMap<PeerId, Instant>,
+
     heartbeat: Ticker,
+
+    /// Fetch headers from one peer then download bodies from many, rather than syncing over a single stream
+    headers_first: bool,
+
+    /// Block bodies being downloaded against a validated header chain
+    download: Option<ActiveDownload>,
+
+    /// Checks for peers which have held a download range for too long
+    download_ticker: Ticker,
+
+    /// Downloaded blocks being written to the chain
+    apply: Option<BoxFuture<'static, Result<ChainUpdate, errors::Error>>>,
+}
+
+/// A headers-first sync in progress
+struct ActiveDownload {
+    /// Peer whose header chain is being followed
+    peer: PeerId,
+    blocks: BlockDownload,
+    blocks_applied: u32,
+    ballots_returned: usize,
 }
 
 impl Behaviour {
-    pub fn new(chain: Arc<Mutex<Blockchain>>) -> Self {
+    pub fn new(chain: Arc<Mutex<Blockchain>>, headers_first: bool, sync_interval: Duration) -> Self {
         return Self {
             chain: chain,
             events: VecDeque::new(),
             sync_peers: HashSet::new(),
-            // TODO: Make heartbeat time configurable
-            heartbeat: Ticker::new(Duration::from_secs(20))
+            peer_status: HashMap::new(),
+            syncing: None,
+            penalised: HashMap::new(),
+            heartbeat: Ticker::new(sync_interval),
+            headers_first,
+            download: None,
+            download_ticker: Ticker::new(Duration::from_secs(1)),
+            apply: None,
         }
     }
 
     pub fn force_sync(&mut self, peer_id: PeerId) {
+        self.syncing = Some(peer_id);
+        self.events.push_back(ToSwarm::GenerateEvent(Event::SyncInitiated(peer_id)));
         self.events.push_back(ToSwarm::NotifyHandler {
             peer_id,
             event: handler::InEvent::ForceSync,
@@ -69,38 +119,260 @@ impl Behaviour {
         });
     }
 
+    fn fetch_headers(&mut self, peer_id: PeerId) {
+        self.syncing = Some(peer_id);
+        self.events.push_back(ToSwarm::GenerateEvent(Event::SyncInitiated(peer_id)));
+        self.events.push_back(ToSwarm::NotifyHandler {
+            peer_id,
+            event: handler::InEvent::FetchHeaders,
+            handler: NotifyHandler::Any,
+        });
+    }
+
     pub async fn pool_ballot(&mut self, ballot: Signed<Ballot>) {
         let _ = self.chain.lock().await.pool_ballot(ballot);
     }
 
-    pub async fn try_resolve(&self, dk: &DecryptionKey, ek: &EncryptionKey, census: &DumbCensus) -> Option<bool> {
+    pub async fn try_resolve(&self, dk: &DecryptionKey, ek: &EncryptionKey, delegations: &DelegationGraph) -> Option<bool> {
         let guard = self.chain.lock().await;
         if guard.get_height() > 4 {
-            return Some(trustee::resolve::generate_vote_result(&dk, &ek, &guard, trustee::delegations::DelegationGraph::random(census.as_vec())));
+            return Some(trustee::resolve::generate_vote_result(&dk, &ek, &guard, delegations.clone()));
         }
         return None
     }
 
+    /// Record the chain status a peer advertised, syncing straight away if it has pulled ahead of us
+    pub fn update_peer_status(&mut self, peer_id: PeerId, status: HeartbeatMessage) {
+        self.peer_status.insert(peer_id, status);
+        self.try_sync();
+    }
+
+    /// Periodic fallback for syncs which failed, or statuses which arrived while the chain was busy
     pub fn heartbeat(&mut self) {
-        // Select a random peer id
-        // TODO: This cloning approach seems stupid, definitely a better way must exist to pull a cloned random value from a hashset
-        // Alternatively, investigate if we can make the sync method take a reference
-        let mut peers_vec: Vec<PeerId> = Vec::new();
-        for peer in self.sync_peers.iter() {
-            peers_vec.push(peer.clone());
+        if self.peer_status.is_empty() {
+            info!("HEARTBEAT: No peer statuses to sync against");
+        }
+        self.try_sync();
+    }
+
+    /// Start syncing from whichever peer advertises the most work, if that is more than ours
+    /// Peers which recently failed to deliver are skipped in favour of the next best
+    fn try_sync(&mut self) {
+        if self.syncing.is_some() || self.download.is_some() {
+            return;
+        }
+
+        // The chain is busy, the next heartbeat will try again
+        let local_work = match self.chain.try_lock() {
+            Some(guard) => guard.total_work(),
+            None => return,
         };
 
-        if let Some(peer_id) = peers_vec.choose(&mut rand::thread_rng()) {
-            info!("HEARTBEAT: Syncing with {}", peer_id);
-            self.force_sync(*peer_id);
-        } else {
-            info!("HEARTBEAT: No peers to sync with");
+        let now = Instant::now();
+        self.penalised.retain(|_, until| *until > now);
+        let best = best_peer(&self.peer_status, &self.penalised, local_work);
+
+        if let Some((peer_id, height)) = best {
+            info!("SYNC: {} is ahead of us at height {}", peer_id, height);
+            if self.headers_first {
+                self.fetch_headers(peer_id);
+            } else {
+                self.force_sync(peer_id);
+            }
+        }
+    }
+
+    /// Queue a sync outcome for the swarm, freeing us to sync again if it closes the current attempt.
+    /// A peer which failed or gave us nothing is penalised, and the next best peer tried in its place
+    fn report(&mut self, event: Event) {
+        let mut closed = false;
+        if let Event::SyncCompleted { peer, .. } | Event::SyncError { peer, .. } = &event {
+            if self.syncing == Some(*peer) && self.download.is_none() {
+                self.syncing = None;
+                closed = true;
+            }
+        }
+
+        let unproductive = match &event {
+            Event::SyncCompleted { peer, blocks_applied: 0, .. } | Event::SyncError { peer, .. } => Some(*peer),
+            _ => None,
+        };
+        if let Some(peer) = unproductive {
+            self.penalised.insert(peer, Instant::now() + UNPRODUCTIVE_SYNC_BACKOFF);
+        }
+
+        self.events.push_back(ToSwarm::GenerateEvent(event));
+        if closed && unproductive.is_some() {
+            self.try_sync();
         }
     }
 
     pub fn add_explicit_peer(&mut self, peer_id: PeerId) {
         self.sync_peers.insert(peer_id);
     }
+
+    fn on_headers(&mut self, peer_id: PeerId, header_chain: HeaderChain) {
+        if self.download.is_some() {
+            return;
+        }
+
+        if header_chain.tip_index() <= header_chain.local_height {
+            info!("SYNC: {} is not ahead of us, nothing to download", peer_id);
+            self.report(Event::SyncCompleted {
+                peer: peer_id,
+                fork_index: header_chain.sync_point.fork_index,
+                blocks_applied: 0,
+                ballots_returned: 0,
+            });
+            return;
+        }
+
+        info!("SYNC: Downloading blocks {} to {} headed by {}", header_chain.sync_point.fork_index + 1, header_chain.tip_index(), peer_id);
+        self.download = Some(ActiveDownload {
+            peer: peer_id,
+            blocks: BlockDownload::new(header_chain),
+            blocks_applied: 0,
+            ballots_returned: 0,
+        });
+        self.dispatch_downloads();
+    }
+
+    /// Hand any waiting ranges out to idle peers
+    fn dispatch_downloads(&mut self) {
+        let download = match self.download.as_mut() {
+            Some(download) => download,
+            None => return,
+        };
+
+        // Only peers whose chain holds the tip can serve any range of it. Besides the peer whose
+        // headers we follow, that is those advertising the tip as their head
+        let tip_hash = download.blocks.tip_hash();
+        let source = download.peer;
+        let peers: Vec<PeerId> = self.sync_peers.iter()
+            .filter(|peer_id| **peer_id == source || matches!(self.peer_status.get(peer_id), Some(status) if status.head_hash == tip_hash))
+            .cloned()
+            .collect();
+
+        for (peer_id, start_index, count) in download.blocks.assign(peers, Instant::now()) {
+            self.events.push_back(ToSwarm::NotifyHandler {
+                peer_id,
+                event: handler::InEvent::FetchBodies { start_index, count },
+                handler: NotifyHandler::Any,
+            });
+        }
+    }
+
+    /// Drop the current download, reporting why against the peer whose headers were followed
+    fn abandon_download(&mut self, cause: SyncFailure) {
+        if let Some(download) = self.download.take() {
+            info!("SYNC: Abandoning block download: {}", cause);
+            self.report(Event::SyncError { peer: download.peer, cause });
+        }
+    }
+
+    /// Start writing any verified blocks to the chain, returning whether there was anything to write
+    fn start_apply(&mut self) -> bool {
+        let download = match self.download.as_mut() {
+            Some(download) => &mut download.blocks,
+            None => return false,
+        };
+
+        if download.is_failed() {
+            self.abandon_download(SyncFailure::Stream("Block ranges failed repeatedly".to_string()));
+            return false;
+        }
+
+        // A fork replacing part of our chain is applied in one go, once every body is verified
+        if !download.extends_head() && !download.is_downloaded() {
+            return false;
+        }
+
+        let blocks = download.take_ready();
+        if blocks.is_empty() {
+            return false;
+        }
+
+        let fork_index = download.fork_index();
+        let extends_head = download.extends_head();
+        let chain = self.chain.clone();
+        self.apply = Some(async move {
+            let mut guard = chain.lock().await;
+            apply_blocks(&mut guard, fork_index, blocks, extends_head)
+        }.boxed());
+
+        return true;
+    }
+
+    fn poll_apply(&mut self, cx: &mut std::task::Context<'_>) {
+        loop {
+            if self.apply.is_none() && !self.start_apply() {
+                return;
+            }
+
+            let result = match self.apply.as_mut().unwrap().poll_unpin(cx) {
+                Poll::Pending => return,
+                Poll::Ready(result) => result,
+            };
+            self.apply = None;
+
+            let update = match result {
+                Ok(update) => update,
+                Err(e) => {
+                    self.abandon_download(e.into());
+                    continue;
+                }
+            };
+
+            let download = match self.download.as_mut() {
+                Some(download) => download,
+                None => continue,
+            };
+            info!("SYNC: Applied {} downloaded blocks", update.blocks_applied);
+            download.blocks_applied += update.blocks_applied;
+            download.ballots_returned += update.ballots_returned;
+
+            if download.blocks.is_complete() {
+                info!("SYNC: Block download complete");
+                let download = self.download.take().unwrap();
+                self.report(Event::SyncCompleted {
+                    peer: download.peer,
+                    fork_index: download.blocks.fork_index(),
+                    blocks_applied: download.blocks_applied,
+                    ballots_returned: download.ballots_returned,
+                });
+            }
+        }
+    }
+}
+
+/// The peer advertising the most work beyond `local_work`, passing over penalised peers
+fn best_peer(statuses: &HashMap<PeerId, HeartbeatMessage>, penalised: &HashMap<PeerId, Instant>, local_work: u64) -> Option<(PeerId, u32)> {
+    return statuses.iter()
+        .filter(|(peer_id, status)| status.total_work > local_work && !penalised.contains_key(peer_id))
+        .max_by_key(|(_, status)| status.total_work)
+        .map(|(peer_id, status)| (*peer_id, status.height));
+}
+
+/// Write downloaded blocks to the chain, either extending our head or replacing everything after the fork
+fn apply_blocks(chain: &mut Blockchain, fork_index: u32, blocks: Vec<Block>, extends_head: bool) -> Result<ChainUpdate, errors::Error> {
+    let count = blocks.len() as u32;
+
+    if extends_head {
+        for block in blocks {
+            chain.append(block)?;
+        }
+        return Ok(ChainUpdate { new_height: chain.get_height(), blocks_applied: count, ballots_returned: 0 });
+    }
+
+    // Our chain may have grown while downloading, only switch if the fork is still longer
+    if fork_index + count <= chain.get_height() {
+        return Ok(ChainUpdate { new_height: chain.get_height(), blocks_applied: 0, ballots_returned: 0 });
+    }
+
+    let mut fork = vec![chain.get_block(&fork_index)?];
+    fork.extend(blocks);
+
+    return chain.try_update_longest(fork_index, fork);
 }
 
 impl NetworkBehaviour for Behaviour {
@@ -130,22 +402,70 @@ impl NetworkBehaviour for Behaviour {
 
     fn on_connection_handler_event(
         &mut self,
-        _peer_id: libp2p::PeerId,
+        peer_id: libp2p::PeerId,
         _connection_id: libp2p::swarm::ConnectionId,
-        _event: libp2p::swarm::THandlerOutEvent<Self>,
+        event: libp2p::swarm::THandlerOutEvent<Self>,
     ) {
-        // TODO: Replace with mapping from handler event type to behaviour event
-        // self.events.push_back(Event::SyncError)
+        match event {
+            OutEvent::SyncCompleted(outcome) => {
+                self.report(Event::SyncCompleted {
+                    peer: peer_id,
+                    fork_index: outcome.sync_point.fork_index,
+                    blocks_applied: outcome.blocks_applied,
+                    ballots_returned: outcome.ballots_returned,
+                });
+            }
+            OutEvent::SyncError(cause) | OutEvent::HeadersFailed(cause) => {
+                self.report(Event::SyncError { peer: peer_id, cause });
+            }
+            OutEvent::HeadersReceived(header_chain) => self.on_headers(peer_id, header_chain),
+            OutEvent::BodiesReceived { start_index, blocks } => {
+                if let Some(download) = self.download.as_mut() {
+                    match download.blocks.on_bodies(&peer_id, start_index, blocks) {
+                        // The headers came from this peer, so its own bodies have no excuse not to match
+                        Err(DownloadError::Mismatch(index)) if peer_id == download.peer => {
+                            self.abandon_download(SyncFailure::InvalidData(format!("Body at {} does not match its header", index)));
+                        },
+                        // Any other peer has moved to another fork since it advertised the tip, so leave it to the rest
+                        Err(DownloadError::Mismatch(index)) => info!("SYNC: {} is on another fork from block {}, downloading elsewhere", peer_id, index),
+                        _ => {},
+                    }
+                }
+                self.dispatch_downloads();
+            }
+            OutEvent::BodiesFailed { start_index, cause } => {
+                if let Some(download) = self.download.as_mut() {
+                    download.blocks.on_failure(&peer_id, start_index);
+                }
+                self.report(Event::SyncError { peer: peer_id, cause });
+                self.dispatch_downloads();
+            }
+        }
     }
 
     // TODO: Update to TRACE once finalised
     #[tracing::instrument(level = "debug", name = "NetworkBehaviour::poll", skip(self))]
     fn poll(&mut self, cx: &mut std::task::Context<'_>) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
-        if let Some(event) = self.events.pop_back() {
+        while let Poll::Ready(Some(_)) = self.download_ticker.poll_next_unpin(cx) {
+            if let Some(download) = self.download.as_mut() {
+                for peer_id in download.blocks.expire(Instant::now()) {
+                    let cause = SyncFailure::Timeout;
+                    self.report(Event::SyncError { peer: peer_id, cause });
+                }
+            }
+            self.dispatch_downloads();
+        }
+
+        self.poll_apply(cx);
+
+        // Hand events over in the order they were queued, so a sync is initiated before it completes
+        if let Some(event) = self.events.pop_front() {
             Poll::Ready(event)
         } else {
             if let Poll::Ready(Some(_)) = self.heartbeat.poll_next_unpin(cx) {
                 self.heartbeat();
+                // Return any request queued by the heartbeat straight away
+                cx.waker().wake_by_ref();
             }
 
             Poll::Pending
@@ -167,8 +487,36 @@ impl NetworkBehaviour for Behaviour {
                 ..
             }) => {
                 self.sync_peers.remove(&peer_id);
+                self.peer_status.remove(&peer_id);
+                if self.syncing == Some(peer_id) && self.download.is_none() {
+                    self.syncing = None;
+                }
+                if let Some(download) = self.download.as_mut() {
+                    download.blocks.on_disconnect(&peer_id);
+                }
             }
             _ => {}
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(height: u32) -> HeartbeatMessage {
+        return HeartbeatMessage { height, head_hash: [0; 32].into(), total_work: height as u64 * 10 };
+    }
+
+    #[test]
+    fn penalised_peers_fall_back_to_next_best() {
+        let (liar, honest) = (PeerId::random(), PeerId::random());
+        let statuses = HashMap::from([(liar, status(1000)), (honest, status(5))]);
+
+        assert_eq!(best_peer(&statuses, &HashMap::new(), 20), Some((liar, 1000)));
+
+        let penalised = HashMap::from([(liar, Instant::now() + UNPRODUCTIVE_SYNC_BACKOFF)]);
+        assert_eq!(best_peer(&statuses, &penalised, 20), Some((honest, 5)));
+        assert_eq!(best_peer(&statuses, &penalised, 50), None);
+    }
+}
@@ -0,0 +1,297 @@
+// Parallel download of block bodies against a validated header chain
+//
+// The headers fix the hash of every block we expect, so bodies can be fetched in ranges
+// from whichever peers are available and checked independently of where they came from.
+
+use std::{collections::{BTreeMap, HashMap, HashSet, VecDeque}, time::{Duration, Instant}};
+
+use blake3::Hash;
+use libp2p::PeerId;
+
+use crate::votechain::block::{Block, BlockHeader};
+
+use super::protocol::HeaderChain;
+
+/// Number of blocks requested from a single peer at once
+pub const RANGE_BLOCKS: u32 = 16;
+
+/// Time a peer has to deliver a range before it is handed to another peer
+pub const RANGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Attempts at a single range before the whole download is abandoned
+pub const MAX_RANGE_ATTEMPTS: u32 = 3;
+
+#[derive(Debug)]
+struct Assignment {
+    start_index: u32,
+    count: u32,
+    deadline: Instant,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DownloadError {
+    /// The peer had no matching range in flight, usually because it already timed out
+    Unexpected,
+    /// The body at this index did not match its header, so the peer is following another fork
+    Mismatch(u32),
+}
+
+pub struct BlockDownload {
+    fork_index: u32,
+    local_height: u32,
+    /// `headers[0]` is the fork block, already present on our chain
+    headers: Vec<BlockHeader>,
+
+    /// Ranges waiting for a peer, as (start index, count)
+    unassigned: VecDeque<(u32, u32)>,
+    in_flight: HashMap<PeerId, Assignment>,
+    attempts: HashMap<u32, u32>,
+    /// Peers which sent bodies from another fork, never assigned again
+    off_fork: HashSet<PeerId>,
+
+    /// Verified bodies not yet handed over to the chain
+    bodies: BTreeMap<u32, Block>,
+    next_ready: u32,
+    failed: bool,
+}
+
+impl BlockDownload {
+    pub fn new(header_chain: HeaderChain) -> Self {
+        let tip_index = header_chain.tip_index();
+        let HeaderChain { sync_point, local_height, headers } = header_chain;
+
+        let mut unassigned = VecDeque::new();
+        let mut start_index = sync_point.fork_index + 1;
+        while start_index <= tip_index {
+            let count = RANGE_BLOCKS.min(tip_index - start_index + 1);
+            unassigned.push_back((start_index, count));
+            start_index += count;
+        }
+
+        return Self {
+            fork_index: sync_point.fork_index,
+            local_height,
+            headers,
+            unassigned,
+            in_flight: HashMap::new(),
+            attempts: HashMap::new(),
+            off_fork: HashSet::new(),
+            bodies: BTreeMap::new(),
+            next_ready: sync_point.fork_index + 1,
+            failed: false,
+        };
+    }
+
+    pub fn fork_index(&self) -> u32 {
+        return self.fork_index;
+    }
+
+    pub fn tip_index(&self) -> u32 {
+        return self.fork_index + self.headers.len() as u32 - 1;
+    }
+
+    /// Hash of the last block in the download, which a peer's chain must hold to serve any of it
+    pub fn tip_hash(&self) -> Hash {
+        return self.headers.last().unwrap().hash();
+    }
+
+    /// Whether the download continues directly from our head, rather than replacing part of our chain
+    pub fn extends_head(&self) -> bool {
+        return self.fork_index == self.local_height;
+    }
+
+    /// Hand out waiting ranges to any of `peers` without a range already in flight
+    pub fn assign(&mut self, peers: impl IntoIterator<Item = PeerId>, now: Instant) -> Vec<(PeerId, u32, u32)> {
+        let mut assigned = Vec::new();
+
+        for peer in peers {
+            if self.failed || self.unassigned.is_empty() {
+                break;
+            }
+            if self.in_flight.contains_key(&peer) || self.off_fork.contains(&peer) {
+                continue;
+            }
+
+            let (start_index, count) = self.unassigned.pop_front().unwrap();
+            self.in_flight.insert(peer, Assignment { start_index, count, deadline: now + RANGE_TIMEOUT });
+            assigned.push((peer, start_index, count));
+        }
+
+        return assigned;
+    }
+
+    /// Check a delivered range against the headers, keeping it if every body matches
+    /// A range which fails the check came from another fork, so is put back up for another peer
+    /// without counting as an attempt, and the peer isn't asked again
+    pub fn on_bodies(&mut self, peer: &PeerId, start_index: u32, blocks: Vec<Block>) -> Result<(), DownloadError> {
+        let assignment = match self.in_flight.get(peer) {
+            Some(assignment) if assignment.start_index == start_index => self.in_flight.remove(peer).unwrap(),
+            _ => return Err(DownloadError::Unexpected),
+        };
+
+        let mismatch = match blocks.len() as u32 == assignment.count {
+            true => blocks.iter().enumerate()
+                .map(|(offset, block)| (start_index + offset as u32, block))
+                .find(|(index, block)| block.header() != self.headers[(index - self.fork_index) as usize])
+                .map(|(index, _)| index),
+            false => Some(start_index + blocks.len() as u32),
+        };
+        if let Some(index) = mismatch {
+            self.off_fork.insert(*peer);
+            self.unassigned.push_front((assignment.start_index, assignment.count));
+            return Err(DownloadError::Mismatch(index));
+        }
+
+        for (offset, block) in blocks.into_iter().enumerate() {
+            self.bodies.insert(start_index + offset as u32, block);
+        }
+
+        return Ok(());
+    }
+
+    /// The peer could not deliver the range starting at `start_index`, put it back up for another peer
+    pub fn on_failure(&mut self, peer: &PeerId, start_index: u32) {
+        if matches!(self.in_flight.get(peer), Some(assignment) if assignment.start_index == start_index) {
+            let assignment = self.in_flight.remove(peer).unwrap();
+            self.retry(assignment.start_index, assignment.count);
+        }
+    }
+
+    /// The peer disconnected, put any range it held back up for another peer
+    pub fn on_disconnect(&mut self, peer: &PeerId) {
+        if let Some(assignment) = self.in_flight.remove(peer) {
+            self.retry(assignment.start_index, assignment.count);
+        }
+    }
+
+    /// Reclaim the ranges of peers which have passed their deadline, returning those peers
+    pub fn expire(&mut self, now: Instant) -> Vec<PeerId> {
+        let expired: Vec<(PeerId, u32)> = self.in_flight.iter()
+            .filter(|(_, assignment)| assignment.deadline <= now)
+            .map(|(peer, assignment)| (*peer, assignment.start_index))
+            .collect();
+
+        for (peer, start_index) in &expired {
+            self.on_failure(peer, *start_index);
+        }
+
+        return expired.into_iter().map(|(peer, _)| peer).collect();
+    }
+
+    fn retry(&mut self, start_index: u32, count: u32) {
+        let attempts = self.attempts.entry(start_index).or_insert(0);
+        *attempts += 1;
+
+        if *attempts >= MAX_RANGE_ATTEMPTS {
+            self.failed = true;
+        } else {
+            self.unassigned.push_front((start_index, count));
+        }
+    }
+
+    /// Take the verified blocks which directly follow those already taken
+    pub fn take_ready(&mut self) -> Vec<Block> {
+        let mut ready = Vec::new();
+
+        while let Some(block) = self.bodies.remove(&self.next_ready) {
+            ready.push(block);
+            self.next_ready += 1;
+        }
+
+        return ready;
+    }
+
+    /// Every body has been verified, though not necessarily taken
+    pub fn is_downloaded(&self) -> bool {
+        return !self.failed && self.unassigned.is_empty() && self.in_flight.is_empty();
+    }
+
+    /// Every body has been verified and taken
+    pub fn is_complete(&self) -> bool {
+        return self.next_ready > self.tip_index();
+    }
+
+    pub fn is_failed(&self) -> bool {
+        return self.failed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::SigningKey;
+
+    use crate::protocols::chain_sync::protocol::SyncPoint;
+    use crate::votechain::block::test_genesis_info;
+
+    use super::*;
+
+    fn test_download(count: u32) -> (BlockDownload, Vec<Block>) {
+        let mut sk = SigningKey::from_bytes(&[0; 32]);
+        let mut blocks = vec![Block::genesis(test_genesis_info())];
+        for _ in 0..count {
+            let block = Block::new(&mut sk, blocks.last().unwrap(), Vec::new()).unwrap();
+            blocks.push(block);
+        }
+
+        let header_chain = HeaderChain {
+            sync_point: SyncPoint { fork_index: 1, peer_height: count + 1 },
+            local_height: 1,
+            headers: blocks.iter().map(|block| block.header()).collect(),
+        };
+
+        // Drop the genesis block, leaving blocks[i] at index i + 2
+        blocks.remove(0);
+        return (BlockDownload::new(header_chain), blocks);
+    }
+
+    #[test]
+    fn ranges_spread_across_peers() {
+        let (mut download, blocks) = test_download(RANGE_BLOCKS + 4);
+        let peers = [PeerId::random(), PeerId::random(), PeerId::random()];
+        let now = Instant::now();
+
+        let assigned = download.assign(peers, now);
+        assert_eq!(assigned, vec![(peers[0], 2, RANGE_BLOCKS), (peers[1], 2 + RANGE_BLOCKS, 4)]);
+
+        // Complete out of order, nothing is ready until the first range lands
+        download.on_bodies(&peers[1], 2 + RANGE_BLOCKS, blocks[RANGE_BLOCKS as usize..].to_vec()).unwrap();
+        assert!(download.take_ready().is_empty());
+
+        download.on_bodies(&peers[0], 2, blocks[..RANGE_BLOCKS as usize].to_vec()).unwrap();
+        assert_eq!(download.take_ready().len(), blocks.len());
+        assert!(download.is_complete());
+    }
+
+    #[test]
+    fn mismatched_bodies_are_retried() {
+        let (mut download, blocks) = test_download(2);
+        let (peer, other) = (PeerId::random(), PeerId::random());
+
+        download.assign([peer], Instant::now());
+        let result = download.on_bodies(&peer, 2, vec![blocks[1].clone(), blocks[0].clone()]);
+        assert_eq!(result, Err(DownloadError::Mismatch(2)));
+
+        // The peer is on another fork, so only the other peer is asked and no attempt is used up
+        assert_eq!(download.assign([peer, other], Instant::now()), vec![(other, 2, 2)]);
+        assert!(download.attempts.is_empty());
+        download.on_bodies(&other, 2, blocks).unwrap();
+        assert!(download.is_downloaded());
+    }
+
+    #[test]
+    fn timed_out_ranges_are_reassigned() {
+        let (mut download, _) = test_download(2);
+        let (slow, other) = (PeerId::random(), PeerId::random());
+        let now = Instant::now();
+
+        download.assign([slow], now);
+        assert_eq!(download.expire(now + RANGE_TIMEOUT), vec![slow]);
+        assert_eq!(download.assign([other], now), vec![(other, 2, 2)]);
+
+        for _ in 1..MAX_RANGE_ATTEMPTS {
+            download.expire(now + RANGE_TIMEOUT);
+            download.assign([other], now);
+        }
+        assert!(download.is_failed());
+    }
+}
@@ -1,33 +1,52 @@
 use std::{collections::VecDeque, sync::Arc, task::Poll};
 
 use async_std::sync::Mutex;
-use asynchronous_codec::CborCodecError;
-use futures::{future::BoxFuture, FutureExt};
-use libp2p::{core::upgrade::ReadyUpgrade, swarm::{handler::{ConnectionEvent, FullyNegotiatedInbound, FullyNegotiatedOutbound}, ConnectionHandler, ConnectionHandlerEvent, SubstreamProtocol}, PeerId, Stream, StreamProtocol};
+use futures::{future::BoxFuture, stream::FuturesUnordered, FutureExt, StreamExt};
+use libp2p::{core::upgrade::ReadyUpgrade, swarm::{handler::{ConnectionEvent, DialUpgradeError, FullyNegotiatedInbound, FullyNegotiatedOutbound}, ConnectionHandler, ConnectionHandlerEvent, SubstreamProtocol}, PeerId, Stream, StreamProtocol};
 use tracing::info;
 
-use crate::votechain::chain::Blockchain;
+use crate::votechain::{block::Block, chain::Blockchain};
 
-use super::{behaviour::Event, protocol::{self, ChainSyncInfo, PROTOCOL_NAME}};
+use super::protocol::{self, HeaderChain, SyncFailure, SyncOutcome, PROTOCOL_NAME};
 
 
 /// Events from `Behaviour` with the information requested by the `Handler`.
 #[derive(Debug)]
 pub enum InEvent {
     /// Explicitly Trigger the behaviour to check with the associated peer
-    ForceSync
+    ForceSync,
+    /// Fetch the peer's header chain after our fork point
+    FetchHeaders,
+    /// Download a range of block bodies
+    FetchBodies { start_index: u32, count: u32 },
 }
 
 #[derive(Debug)]
 pub enum OutEvent {
-    /// Successfully updated our chain to a new longest version
-    SyncPointFound(ChainSyncInfo),
-    /// Failed to update our chain
-    SyncError,
+    /// Finished syncing with the peer, whether or not our chain changed
+    SyncCompleted(SyncOutcome),
+    /// Failed to sync with the peer
+    SyncError(SyncFailure),
+    /// Fetched and validated the peer's header chain
+    HeadersReceived(HeaderChain),
+    /// Failed to fetch a valid header chain
+    HeadersFailed(SyncFailure),
+    /// Downloaded a range of block bodies, still to be checked against the headers
+    BodiesReceived { start_index: u32, blocks: Vec<Block> },
+    /// Failed to download the range of bodies starting at `start_index`
+    BodiesFailed { start_index: u32, cause: SyncFailure },
 }
 
-type SyncSendFuture = BoxFuture<'static, Result<Stream, CborCodecError>>;
-type SyncListenFuture = BoxFuture<'static, Result<Stream, CborCodecError>>;
+/// The request an outbound stream was opened for
+#[derive(Debug, Clone, Copy)]
+pub enum OutboundRequest {
+    Sync,
+    Headers,
+    Bodies { start_index: u32, count: u32 },
+}
+
+type SyncListenFuture = BoxFuture<'static, Result<Stream, SyncFailure>>;
+type OutboundFuture = BoxFuture<'static, OutEvent>;
 
 pub struct Handler {
     /// Store a reference to the chain to enable chain interactions 
@@ -35,13 +54,13 @@ pub struct Handler {
 
     /// Queue containing actively polled events
     // TODO: Work out why the 'identify' module uses a symmetric 'Either' for the protocol upgrade
-    events: VecDeque<ConnectionHandlerEvent<ReadyUpgrade<StreamProtocol>, (), Event>>,
+    events: VecDeque<ConnectionHandlerEvent<ReadyUpgrade<StreamProtocol>, OutboundRequest, OutEvent>>,
 
     /// Listener for inbound sync requests
     inbound: Option<SyncListenFuture>,
 
-    /// Listener for progressing outbound sync requests
-    outbound: Option<SyncSendFuture>,
+    /// Outbound requests in progress, several body downloads may share a connection
+    outbound: FuturesUnordered<OutboundFuture>,
 }
 
 impl Handler {
@@ -50,18 +69,58 @@ impl Handler {
             chain: chain,
             events: VecDeque::new(),
             inbound: None,
-            outbound: None,
+            outbound: FuturesUnordered::new(),
         }
     }
+
+    fn request(&mut self, request: OutboundRequest) {
+        self.events.push_back(ConnectionHandlerEvent::OutboundSubstreamRequest {
+            protocol: SubstreamProtocol::new(ReadyUpgrade::new(PROTOCOL_NAME), request)
+        });
+    }
+
+    fn start_outbound(&mut self, stream: Stream, request: OutboundRequest) {
+        let chain = self.chain.clone();
+        let future = match request {
+            OutboundRequest::Sync => async move {
+                match protocol::send_sync(stream, chain).await {
+                    Ok(outcome) => OutEvent::SyncCompleted(outcome),
+                    Err(e) => {
+                        tracing::debug!("Failed to progress sync. Error: {:?}", e);
+                        OutEvent::SyncError(e)
+                    }
+                }
+            }.boxed(),
+            OutboundRequest::Headers => async move {
+                match protocol::fetch_headers(stream, chain).await {
+                    Ok(header_chain) => OutEvent::HeadersReceived(header_chain),
+                    Err(e) => {
+                        tracing::debug!("Failed to fetch headers. Error: {:?}", e);
+                        OutEvent::HeadersFailed(e)
+                    }
+                }
+            }.boxed(),
+            OutboundRequest::Bodies { start_index, count } => async move {
+                match protocol::fetch_bodies(stream, start_index, count).await {
+                    Ok(blocks) => OutEvent::BodiesReceived { start_index, blocks },
+                    Err(e) => {
+                        tracing::debug!("Failed to fetch bodies from {}. Error: {:?}", start_index, e);
+                        OutEvent::BodiesFailed { start_index, cause: e }
+                    }
+                }
+            }.boxed(),
+        };
+        self.outbound.push(future);
+    }
 }
 
 impl ConnectionHandler for Handler {
     type FromBehaviour = InEvent;
-    type ToBehaviour = Event;
+    type ToBehaviour = OutEvent;
     type InboundProtocol = ReadyUpgrade<StreamProtocol>;
     type OutboundProtocol = ReadyUpgrade<StreamProtocol>;
     type InboundOpenInfo = ();
-    type OutboundOpenInfo = ();
+    type OutboundOpenInfo = OutboundRequest;
 
     fn listen_protocol(&self) -> libp2p::swarm::SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
         SubstreamProtocol::new(ReadyUpgrade::new(PROTOCOL_NAME), ())
@@ -88,14 +147,20 @@ impl ConnectionHandler for Handler {
             }
             ConnectionEvent::FullyNegotiatedOutbound(FullyNegotiatedOutbound {
                 protocol: stream,
-                ..
+                info,
             }) => {
                 info!("Negotiated outbound!");
                 // stream.ignore_for_keep_alive();
-                self.outbound = Some(protocol::send_sync(stream, self.chain.clone()).boxed());
+                self.start_outbound(stream, info);
             }
-            ConnectionEvent::DialUpgradeError(_dial_upgrade_error) => {
-                // TODO: Return an event that we failed to sync?
+            ConnectionEvent::DialUpgradeError(DialUpgradeError { info, error }) => {
+                let cause = SyncFailure::Stream(error.to_string());
+                let event = match info {
+                    OutboundRequest::Sync => OutEvent::SyncError(cause),
+                    OutboundRequest::Headers => OutEvent::HeadersFailed(cause),
+                    OutboundRequest::Bodies { start_index, .. } => OutEvent::BodiesFailed { start_index, cause },
+                };
+                self.events.push_back(ConnectionHandlerEvent::NotifyBehaviour(event));
             }
             _ => {}
         }
@@ -103,11 +168,9 @@ impl ConnectionHandler for Handler {
 
     fn on_behaviour_event(&mut self, event: Self::FromBehaviour) {
         match event {
-            InEvent::ForceSync => {
-                self.events.push_back(ConnectionHandlerEvent::OutboundSubstreamRequest { protocol: SubstreamProtocol::new(
-                    ReadyUpgrade::new(PROTOCOL_NAME), ()
-                ) })
-            }
+            InEvent::ForceSync => self.request(OutboundRequest::Sync),
+            InEvent::FetchHeaders => self.request(OutboundRequest::Headers),
+            InEvent::FetchBodies { start_index, count } => self.request(OutboundRequest::Bodies { start_index, count }),
         }
     }
 
@@ -138,18 +201,9 @@ impl ConnectionHandler for Handler {
             }
         }
 
-        // Continue outbound sync.
-        if let Some(fut) = self.outbound.as_mut() {
-            match fut.poll_unpin(cx) {
-                Poll::Pending => {}
-                Poll::Ready(Err(e)) => {
-                    tracing::debug!("Failed to progress sync. Error: {:?}", e);
-                    self.outbound = None;
-                }
-                Poll::Ready(Ok(_)) => {
-                    self.outbound = None;
-                }
-            }
+        // Continue outbound requests, reporting any results to the behaviour
+        if let Poll::Ready(Some(event)) = self.outbound.poll_next_unpin(cx) {
+            return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(event));
         }
 
         return Poll::Pending;
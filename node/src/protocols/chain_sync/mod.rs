@@ -1,3 +1,4 @@
 pub(crate) mod behaviour;
+pub(crate) mod download;
 pub(crate) mod protocol;
-pub(crate) mod handler;
\ No newline at end of file
+pub(crate) mod handler;
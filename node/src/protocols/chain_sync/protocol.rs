@@ -1,4 +1,4 @@
-use std::{io, sync::Arc};
+use std::{sync::Arc, time::Duration};
 
 use async_std::sync::Mutex;
 use asynchronous_codec::{CborCodec, CborCodecError, Framed};
@@ -7,45 +7,232 @@ use futures::{AsyncRead, AsyncWrite, SinkExt, TryStreamExt};
 use libp2p::StreamProtocol;
 use serde::{Deserialize, Serialize};
 
-use crate::votechain::{block::Block, chain::Blockchain, errors};
+use crate::votechain::{block::{Block, BlockHeader}, chain::Blockchain, errors};
 
 
-pub const PROTOCOL_NAME: StreamProtocol = StreamProtocol::new("/votechain/sync/0.0");
+pub const PROTOCOL_NAME: StreamProtocol = StreamProtocol::new("/votechain/sync/1.0");
 
+/// Upper bound on the encoded size of a single page of blocks
+pub const MAX_BATCH_BYTES: u32 = 1024 * 1024;
+
+/// Upper bound on the number of headers in a single page
+pub const MAX_HEADERS_PER_PAGE: u32 = 2000;
+
+/// Upper bound on the headers fetched in one sync, however far ahead a peer claims to be.
+/// A longer chain is caught up over several syncs, each starting from the last one's head
+pub const MAX_HEADERS_PER_SYNC: u32 = 50 * MAX_HEADERS_PER_PAGE;
+
+/// Number of most recent blocks included individually in a locator before the spacing starts doubling
+const LOCATOR_DENSE_BLOCKS: u32 = 8;
+
+/// Upper bound on the entries of a locator. Ours never exceed 41, the dense blocks plus one per doubling of a u32 height
+pub const MAX_LOCATOR_LEN: usize = 64;
+
+/// Time a peer has to answer each request, so one which stays connected but silent can't hold up syncing
+pub const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
 
-/// Request allowing a responding node to identify the divergence point
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SyncRequest {
-    index: u32,
-    hash: Hash,
-}
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct ChainSyncInfo {
-    pub fork_index: u32,
+pub enum SyncRequest {
+    /// Hashes at exponentially spaced indexes back from our head, newest first,
+    /// allowing the responder to find the divergence point in a single round trip
+    Locator(Vec<(u32, Hash)>),
 
-    /// Vector of blocks (Including the shared divergence point block)
-    pub block: Block,
+    /// Request a page of up to `max_blocks` blocks starting at `start_index`, capped to roughly `max_bytes`
+    GetBlocks { start_index: u32, max_blocks: u32, max_bytes: u32 },
 
-    /// Number of blocks still to be sent
-    remaining: u32,
+    /// Request a page of up to `max_count` headers starting at `start_index`
+    GetHeaders { start_index: u32, max_count: u32 },
+}
+
+/// The most recent block shared between both chains
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncPoint {
+    pub fork_index: u32,
+    pub peer_height: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum SyncResponse {
-    Found(ChainSyncInfo),
+    Found(SyncPoint),
     NotFound,
+    Blocks {
+        start_index: u32,
+        blocks: Vec<Block>,
+        /// Number of blocks after this page still to be fetched
+        remaining: u32,
+    },
+    Headers {
+        start_index: u32,
+        headers: Vec<BlockHeader>,
+        remaining: u32,
+    },
+}
+
+/// A validated run of headers from a peer, linked back to a block shared with our chain
+#[derive(Debug, Clone)]
+pub struct HeaderChain {
+    pub sync_point: SyncPoint,
+    /// Our height at the time the locator was sent
+    pub local_height: u32,
+    /// Headers from the fork block onwards, so `headers[0]` is already on our chain
+    pub headers: Vec<BlockHeader>,
+}
+
+impl HeaderChain {
+    /// Index of the last header
+    pub fn tip_index(&self) -> u32 {
+        return self.sync_point.fork_index + self.headers.len() as u32 - 1;
+    }
+}
+
+/// Result of a completed outbound sync
+#[derive(Debug, Clone)]
+pub struct SyncOutcome {
+    pub sync_point: SyncPoint,
+    pub blocks_applied: u32,
+    pub ballots_returned: usize,
+}
+
+/// Why a sync with a peer failed
+#[derive(Debug, Clone)]
+pub enum SyncFailure {
+    /// The peer broke the protocol or sent data which failed validation
+    InvalidData(String),
+    /// The peer shares no blocks with us, not even genesis
+    NoCommonBlock,
+    /// The stream could not be opened, failed or was closed early
+    Stream(String),
+    /// Our own chain could not be read or written
+    Local(String),
+    /// The peer didn't answer a request within `RESPONSE_TIMEOUT`
+    Timeout,
+}
+
+/// Errors from the stream itself. Frames which don't decode are the peer's fault
+impl From<CborCodecError> for SyncFailure {
+    fn from(error: CborCodecError) -> Self {
+        match error {
+            CborCodecError::Io(e) => return SyncFailure::Stream(e.to_string()),
+            other => return SyncFailure::InvalidData(other.to_string()),
+        }
+    }
+}
+
+/// Blocks which fail to validate are the peer's fault, any other chain error is ours
+impl From<errors::Error> for SyncFailure {
+    fn from(error: errors::Error) -> Self {
+        match error {
+            errors::Error::InvalidNewBlock => return SyncFailure::InvalidData(error.to_string()),
+            other => return SyncFailure::Local(other.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for SyncFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyncFailure::InvalidData(cause) => write!(f, "Peer sent invalid data: {}", cause),
+            SyncFailure::NoCommonBlock => write!(f, "Peer shares no blocks with our chain"),
+            SyncFailure::Stream(cause) => write!(f, "Stream failed: {}", cause),
+            SyncFailure::Local(cause) => write!(f, "Local chain error: {}", cause),
+            SyncFailure::Timeout => write!(f, "Peer did not respond in time"),
+        }
+    }
+}
+
+/// Indexes sampled for a locator: the most recent blocks individually, then
+/// doubling the step back towards genesis, which is always included
+pub fn locator_indices(height: u32) -> Vec<u32> {
+    let mut indices = Vec::new();
+    let mut index = height;
+    let mut step = 1;
+
+    while index > 1 {
+        indices.push(index);
+        if indices.len() as u32 >= LOCATOR_DENSE_BLOCKS {
+            step *= 2;
+        }
+        index = index.saturating_sub(step);
+    }
+
+    indices.push(1);
+    return indices;
+}
+
+fn build_locator(chain: &Blockchain) -> Result<Vec<(u32, Hash)>, errors::Error> {
+    return locator_indices(chain.get_height())
+        .into_iter()
+        .map(|index| Ok((index, chain.get_hash_at(index)?)))
+        .collect();
+}
+
+/// Find the newest locator entry which is also present on our chain
+fn find_fork(chain: &Blockchain, locator: &[(u32, Hash)]) -> Option<u32> {
+    return locator.iter()
+        .find(|(index, hash)| matches!(chain.get_hash_at(*index), Ok(ours) if ours == *hash))
+        .map(|(index, _)| *index);
+}
+
+/// Send a request and await the peer's response, failing if the round trip takes longer than `RESPONSE_TIMEOUT`
+async fn request<T>(
+    framed_stream: &mut Framed<T, CborCodec<SyncRequest, SyncResponse>>,
+    request: SyncRequest,
+) -> Result<Option<SyncResponse>, SyncFailure>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let round_trip = async {
+        framed_stream.send(request).await?;
+        return framed_stream.try_next().await;
+    };
+
+    match async_std::future::timeout(RESPONSE_TIMEOUT, round_trip).await {
+        Ok(response) => return Ok(response?),
+        Err(_) => return Err(SyncFailure::Timeout),
+    }
+}
+
+/// Send our locator and await the peer's view of where our chains diverge
+/// Returns the sync point along with our height at the time of sending
+async fn exchange_locator<T>(
+    framed_stream: &mut Framed<T, CborCodec<SyncRequest, SyncResponse>>,
+    chain: &Arc<Mutex<Blockchain>>,
+) -> Result<(SyncPoint, u32), SyncFailure>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let (locator, our_height) = {
+        let guard = chain.lock().await;
+        let locator = build_locator(&guard)?;
+        (locator, guard.get_height())
+    };
+    match request(framed_stream, SyncRequest::Locator(locator)).await? {
+        Some(SyncResponse::Found(sync_point)) => return Ok((sync_point, our_height)),
+        Some(SyncResponse::NotFound) => {
+            tracing::info!("Peer did not match genesis block, assuming invalid");
+            framed_stream.close().await?;
+            return Err(SyncFailure::NoCommonBlock);
+        },
+        Some(_) => {
+            framed_stream.close().await?;
+            return Err(SyncFailure::InvalidData("Unexpected response to locator".to_string()));
+        },
+        None => {
+            tracing::info!("Didn't recieve a response from the peer");
+            return Err(SyncFailure::Stream("Didn't recieve a response from the peer".to_string()));
+        }
+    }
 }
 
 /// Initiates the sync with a given node
-/// 1. Identify fork point
-/// 2. Request all blocks post fork
-/// 3. Return event to process new block chain
-/// 
-/// TODO: May need to have additional capabilities for handling large diffs (Incremental read)
-/// If a long way behind we may also want to do additional work to validate that the chain we're
-/// pulling matches a few randomly selected nodes up to a certain point
-pub async fn send_sync<S>(mut stream: S, chain: Arc<Mutex<Blockchain>>) -> Result<S, CborCodecError>
+/// 1. Send a locator so the peer can identify the fork point
+/// 2. Page through all blocks after the fork
+/// 3. Apply pages as they arrive, once the peer's fork overtakes our own chain
+///
+/// Progress is written to the chain as it is made, so if the stream drops the next
+/// locator starts from the new head and the sync resumes where it left off
+pub async fn send_sync<S>(mut stream: S, chain: Arc<Mutex<Blockchain>>) -> Result<SyncOutcome, SyncFailure>
 where
     S: AsyncRead + AsyncWrite + Unpin,
 {
@@ -54,56 +241,148 @@ where
     let codec = CborCodec::<SyncRequest, SyncResponse>::new();
     let mut framed_stream = Framed::new(&mut stream, codec);
 
-    let mut height = chain.lock().await.get_height();
-    let mut block_buffer: Vec<Block> = Vec::new();
+    let (sync_point, our_height) = exchange_locator(&mut framed_stream, &chain).await?;
+
+    tracing::info!("Found divergence at index {}, peer height {}", sync_point.fork_index, sync_point.peer_height);
+    let mut outcome = SyncOutcome { sync_point: sync_point.clone(), blocks_applied: 0, ballots_returned: 0 };
+    if sync_point.peer_height <= our_height {
+        tracing::info!("Peer chain is not longer than ours, nothing to sync");
+        framed_stream.close().await?;
+        return Ok(outcome);
+    }
+
+    // Blocks of the peer's fork are held back until it overtakes our chain, as replacing
+    // our chain with a shorter one would lose blocks if the stream then dropped
+    let mut pending: Vec<Block> = Vec::new();
+    let mut applied = false;
+    let mut next_index = sync_point.fork_index;
 
     loop {
-        // Firstly send our request
-        let _ = framed_stream.send(SyncRequest { index: height, hash: chain.lock().await.get_hash_at(height).unwrap() }).await;
-        tracing::info!("Sent Request");
-
-        // Await the response
-        match framed_stream.try_next().await?{
-            // Peer found the divergence point and sent us the updates
-            Some(SyncResponse::Found(result)) => {
-                tracing::info!("Peer responded with block. Sync Height: {}, Remaining: {}, Head Hash: {}", result.fork_index, result.remaining, result.block.hash());
-                block_buffer.push(result.block);
-
-                if result.remaining == 0 {
-                    let mut guard = chain.lock().await;
-                    tracing::info!("Sync: Obtained lock");
-                    let update_result = guard.try_update_longest(result.fork_index, block_buffer);
-                    if update_result.is_err() {
-                        tracing::info!("Failed to update: {}", update_result.unwrap())
-                    }
-                    return Ok(stream)
-                }
+        let page_request = SyncRequest::GetBlocks { start_index: next_index, max_blocks: u32::MAX, max_bytes: MAX_BATCH_BYTES };
+        let (blocks, remaining) = match request(&mut framed_stream, page_request).await? {
+            Some(SyncResponse::Blocks { start_index, blocks, remaining }) if start_index == next_index && !blocks.is_empty() => (blocks, remaining),
+            Some(_) => {
+                framed_stream.close().await?;
+                return Err(SyncFailure::InvalidData("Peer sent an invalid page of blocks".to_string()));
             },
-            // Peer failed to match the provided hash and height, try again
-            Some(SyncResponse::NotFound) => {
-                // Failed to match genesis block, other chain is invalid
-                if height == 1 {
-                    tracing::info!("Peer did not match genesis block, assuming invalid");
-                    framed_stream.close().await?;
-                    return Err(CborCodecError::Io(std::io::Error::new(io::ErrorKind::Other, "Failed to find valid sync point with peer")));
-                }
+            None => {
+                return Err(SyncFailure::Stream("Peer closed the stream mid-sync".to_string()));
+            }
+        };
+
+        tracing::info!("Peer responded with {} blocks from {}, Remaining: {}", blocks.len(), next_index, remaining);
+        next_index += blocks.len() as u32;
+
+        let mut guard = chain.lock().await;
+        if applied {
+            for block in blocks {
+                guard.append(block)?;
+                outcome.blocks_applied += 1;
+            }
+        } else {
+            pending.extend(blocks);
+            if sync_point.fork_index + pending.len() as u32 - 1 > guard.get_height() {
+                let update = guard.try_update_longest(sync_point.fork_index, std::mem::take(&mut pending))?;
+                outcome.blocks_applied += update.blocks_applied;
+                outcome.ballots_returned += update.ballots_returned;
+                applied = true;
+            }
+        }
+        drop(guard);
+
+        if remaining == 0 {
+            break;
+        }
+    }
+
+    framed_stream.close().await?;
+    return Ok(outcome);
+}
 
-                tracing::info!("Peer failed to find block at height {}", height);
-                height -= 1;
-                continue;
+/// Fetch the peer's headers after the divergence point, checking each one links to the
+/// last and carries a valid proof of work before any block bodies are requested
+pub async fn fetch_headers<S>(mut stream: S, chain: Arc<Mutex<Blockchain>>) -> Result<HeaderChain, SyncFailure>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let codec = CborCodec::<SyncRequest, SyncResponse>::new();
+    let mut framed_stream = Framed::new(&mut stream, codec);
+
+    let (sync_point, local_height) = exchange_locator(&mut framed_stream, &chain).await?;
+    let fork_header = chain.lock().await.get_block(&sync_point.fork_index)?.header();
+    let mut headers = vec![fork_header];
+
+    // The claimed height is only used to know when to stop, so don't let it run on without end
+    let target_height = sync_point.peer_height.min(sync_point.fork_index.saturating_add(MAX_HEADERS_PER_SYNC));
+    if target_height < sync_point.peer_height {
+        tracing::info!("Peer claims height {}, fetching headers up to {} this sync", sync_point.peer_height, target_height);
+    }
+
+    while sync_point.fork_index + (headers.len() as u32) - 1 < target_height {
+        let next_index = sync_point.fork_index + headers.len() as u32;
+        let page_request = SyncRequest::GetHeaders { start_index: next_index, max_count: MAX_HEADERS_PER_PAGE };
+        let (page, remaining) = match request(&mut framed_stream, page_request).await? {
+            Some(SyncResponse::Headers { start_index, headers, remaining }) if start_index == next_index && !headers.is_empty() => (headers, remaining),
+            Some(_) => {
+                framed_stream.close().await?;
+                return Err(SyncFailure::InvalidData("Peer sent an invalid page of headers".to_string()));
             },
             None => {
-                tracing::info!("Didn't recieve a response from the peer");
-                framed_stream.close().await?;
-                return Err(CborCodecError::Io(std::io::Error::new(io::ErrorKind::TimedOut, "Didn't recieve a response from the peer")));
+                return Err(SyncFailure::Stream("Peer closed the stream mid-sync".to_string()));
             }
         };
+
+        for header in page {
+            if !header.extends(headers.last().unwrap()) || !header.signature_valid() {
+                framed_stream.close().await?;
+                return Err(SyncFailure::InvalidData("Peer sent a header which does not extend the chain".to_string()));
+            }
+            headers.push(header);
+        }
+
+        if remaining == 0 {
+            break;
+        }
     }
+
+    tracing::info!("Fetched {} headers from index {}", headers.len() - 1, sync_point.fork_index + 1);
+    framed_stream.close().await?;
+    return Ok(HeaderChain { sync_point, local_height, headers });
 }
 
+/// Download the bodies of `count` blocks from `start_index`
+/// The blocks are not checked here, as that requires the header chain held by the behaviour
+pub async fn fetch_bodies<S>(mut stream: S, start_index: u32, count: u32) -> Result<Vec<Block>, SyncFailure>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let codec = CborCodec::<SyncRequest, SyncResponse>::new();
+    let mut framed_stream = Framed::new(&mut stream, codec);
+    let mut blocks: Vec<Block> = Vec::new();
+
+    while (blocks.len() as u32) < count {
+        let next_index = start_index + blocks.len() as u32;
+        let wanted = count - blocks.len() as u32;
+        let page_request = SyncRequest::GetBlocks { start_index: next_index, max_blocks: wanted, max_bytes: MAX_BATCH_BYTES };
+        match request(&mut framed_stream, page_request).await? {
+            Some(SyncResponse::Blocks { start_index, blocks: page, .. })
+                if start_index == next_index && !page.is_empty() && page.len() as u32 <= wanted => blocks.extend(page),
+            Some(_) => {
+                framed_stream.close().await?;
+                return Err(SyncFailure::InvalidData("Peer sent an invalid page of blocks".to_string()));
+            },
+            None => {
+                return Err(SyncFailure::Stream("Peer closed the stream mid-download".to_string()));
+            }
+        }
+    }
+
+    framed_stream.close().await?;
+    return Ok(blocks);
+}
 
-/// Respond to heartbeat requests
-pub(crate) async fn recv_sync<S>(mut stream: S, chain: Arc<Mutex<Blockchain>>) -> Result<S, CborCodecError>
+/// Respond to sync requests, serving pages of blocks until the requester closes the stream
+pub(crate) async fn recv_sync<S>(mut stream: S, chain: Arc<Mutex<Blockchain>>) -> Result<S, SyncFailure>
 where
     S: AsyncRead + AsyncWrite + Unpin,
 {
@@ -113,69 +392,167 @@ where
 
     loop {
         // Await info from the initaiting peer
-        match framed_stream.try_next().await?{
-            Some(request) => {
-                tracing::info!("Recieved Request");
+        match framed_stream.try_next().await? {
+            Some(SyncRequest::Locator(locator)) => {
+                tracing::info!("Recieved Locator");
+                if locator.len() > MAX_LOCATOR_LEN {
+                    return Err(SyncFailure::InvalidData("Locator has too many entries".to_string()));
+                }
                 let guard = chain.lock().await;
-                tracing::info!("Current Height: {}", guard.get_height());
-
-                let hash = match guard.get_hash_at(request.index) {
-                    Ok(hash) => hash,
-                    Err(e) => {
-                        match e {
-                            // If BlockNotFound - Their chain is longer, so stop the search
-                            // TODO: Initiate backwards sync
-                            errors::Error::BlockNotFound(_) => {
-                                return Err(CborCodecError::Io(std::io::Error::new(io::ErrorKind::Other, "Failed to find valid sync point with peer")));
-                            },
-                            _ => {
-                                return Err(CborCodecError::Io(std::io::Error::new(io::ErrorKind::Other, "Other Error Happened")));
-                            }
-                        }
-                    }
-                };
-
-                if hash == request.hash {
-                    tracing::info!("Found divergence at index {}", request.index);
-                    let blocks = match guard.blocks_from(request.index) {
-                        Ok(blocks) => blocks,
-                        Err(e) => {
-                            tracing::info!("Block Read Error: {}", e);
-                            Vec::new()
-                        }
-                    };
-
-                    tracing::info!("Sending");
-                    let mut remaining: u32 = (blocks.len()).try_into().unwrap();
-                    for block in blocks {
-                        let send_result = framed_stream.send(SyncResponse::Found(ChainSyncInfo {
-                            fork_index: request.index,
-                            block,
-                            remaining: remaining - 1
-                        })).await;
-                        match send_result {
-                            Ok(_) => {
-                                remaining -= 1;
-                            },
-                            Err(e) => { tracing::info!("Sync had an error: {}", e); }
-                        };
-                    }
 
-                    tracing::info!("Sent Sync Response");
-                    return Ok(stream);
-                };
+                match find_fork(&guard, &locator) {
+                    Some(fork_index) => {
+                        tracing::info!("Found divergence at index {}", fork_index);
+                        let sync_point = SyncPoint { fork_index, peer_height: guard.get_height() };
+                        drop(guard);
+                        framed_stream.send(SyncResponse::Found(sync_point)).await?;
+                    },
+                    None => {
+                        drop(guard);
+                        framed_stream.send(SyncResponse::NotFound).await?;
+                        tracing::info!("Peer did not match genesis block, assuming invalid");
+                        return Err(SyncFailure::NoCommonBlock);
+                    }
+                }
+            },
+            Some(SyncRequest::GetBlocks { start_index, max_blocks, max_bytes }) => {
+                let guard = chain.lock().await;
+                let blocks = guard.blocks_page(start_index, max_blocks, max_bytes.min(MAX_BATCH_BYTES) as u64)?;
+                let remaining = guard.get_height().saturating_sub(start_index + blocks.len() as u32 - 1);
+                drop(guard);
 
-                let _ = framed_stream.send(SyncResponse::NotFound).await;
+                tracing::info!("Sending {} blocks from {}", blocks.len(), start_index);
+                framed_stream.send(SyncResponse::Blocks { start_index, blocks, remaining }).await?;
+            },
+            Some(SyncRequest::GetHeaders { start_index, max_count }) => {
+                let guard = chain.lock().await;
+                let headers = guard.headers_page(start_index, max_count.min(MAX_HEADERS_PER_PAGE))?;
+                let remaining = guard.get_height().saturating_sub(start_index + headers.len() as u32 - 1);
+                drop(guard);
 
-                if request.index == 0 {
-                    tracing::info!("Peer did not match genesis block, assuming invalid");
-                    return Err(CborCodecError::Io(std::io::Error::new(io::ErrorKind::Other, "Failed to find valid sync point with peer")));
-                }
+                tracing::info!("Sending {} headers from {}", headers.len(), start_index);
+                framed_stream.send(SyncResponse::Headers { start_index, headers, remaining }).await?;
             },
             None => {
-                framed_stream.close().await?;
-                return Err(CborCodecError::Io(std::io::Error::new(io::ErrorKind::TimedOut, "Didn't recieve metadata from the peer")));
+                tracing::info!("Sent Sync Response");
+                return Ok(stream);
             }
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use async_std::os::unix::net::UnixStream;
+    use ed25519_dalek::SigningKey;
+
+    use crate::votechain::block::test_genesis_info;
+
+    use super::*;
+
+    fn extend(chain: &mut Blockchain, sk: &mut SigningKey, count: u32) {
+        for _ in 0..count {
+            let head = chain.get_block(&chain.get_height()).unwrap();
+            chain.append(Block::new(sk, &head, Vec::new()).unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn locator_is_dense_then_sparse() {
+        assert_eq!(locator_indices(1), vec![1]);
+        assert_eq!(locator_indices(5), vec![5, 4, 3, 2, 1]);
+
+        let indices = locator_indices(1000);
+        assert_eq!(&indices[..8], &[1000, 999, 998, 997, 996, 995, 994, 993]);
+        assert_eq!(indices[8], 991);
+        assert_eq!(*indices.last().unwrap(), 1);
+        assert!(indices.len() < 20);
+    }
+
+    #[test]
+    fn syncs_to_longer_chain() {
+        let mut sk = SigningKey::from_bytes(&[0; 32]);
+        let mut ours = Blockchain::in_memory(test_genesis_info(), &sk).unwrap();
+        let mut theirs = Blockchain::in_memory(test_genesis_info(), &sk).unwrap();
+        extend(&mut ours, &mut sk, 1);
+        extend(&mut theirs, &mut sk, 4);
+        let their_head = theirs.get_hash_at(5).unwrap();
+
+        let ours = Arc::new(Mutex::new(ours));
+        let theirs = Arc::new(Mutex::new(theirs));
+        let (local, remote) = UnixStream::pair().unwrap();
+
+        async_std::task::block_on(async {
+            let (sent, received) = futures::join!(
+                send_sync(local, ours.clone()),
+                recv_sync(remote, theirs.clone())
+            );
+            received.unwrap();
+            let outcome = sent.unwrap();
+            assert_eq!(outcome.sync_point.fork_index, 1);
+            assert_eq!(outcome.blocks_applied, 4);
+        });
+
+        let guard = async_std::task::block_on(ours.lock());
+        assert_eq!(guard.get_height(), 5);
+        assert_eq!(guard.get_hash_at(5).unwrap(), their_head);
+    }
+
+    #[test]
+    fn oversized_locator_rejected() {
+        let sk = SigningKey::from_bytes(&[0; 32]);
+        let theirs = Arc::new(Mutex::new(Blockchain::in_memory(test_genesis_info(), &sk).unwrap()));
+        let genesis = async_std::task::block_on(theirs.lock()).get_hash_at(1).unwrap();
+
+        async_std::task::block_on(async {
+            let (local, remote) = UnixStream::pair().unwrap();
+            let mut framed_stream = Framed::new(local, CborCodec::<SyncRequest, SyncResponse>::new());
+            framed_stream.send(SyncRequest::Locator(vec![(1, genesis); MAX_LOCATOR_LEN + 1])).await.unwrap();
+
+            assert!(recv_sync(remote, theirs).await.is_err());
+        });
+    }
+
+    #[test]
+    fn silent_peer_times_out() {
+        let sk = SigningKey::from_bytes(&[0; 32]);
+        let ours = Arc::new(Mutex::new(Blockchain::in_memory(test_genesis_info(), &sk).unwrap()));
+
+        // The remote end stays open but never answers the locator
+        let (local, _remote) = UnixStream::pair().unwrap();
+        let result = async_std::task::block_on(fetch_headers(local, ours));
+        assert!(matches!(result, Err(SyncFailure::Timeout)));
+    }
+
+    #[test]
+    fn fetches_headers_then_bodies() {
+        let mut sk = SigningKey::from_bytes(&[0; 32]);
+        let mut theirs = Blockchain::in_memory(test_genesis_info(), &sk).unwrap();
+        extend(&mut theirs, &mut sk, 4);
+        let expected: Vec<BlockHeader> = (2..6).map(|index| theirs.get_block(&index).unwrap().header()).collect();
+
+        let ours = Arc::new(Mutex::new(Blockchain::in_memory(test_genesis_info(), &sk).unwrap()));
+        let theirs = Arc::new(Mutex::new(theirs));
+
+        async_std::task::block_on(async {
+            let (local, remote) = UnixStream::pair().unwrap();
+            let (header_chain, received) = futures::join!(
+                fetch_headers(local, ours.clone()),
+                recv_sync(remote, theirs.clone())
+            );
+            received.unwrap();
+            let header_chain = header_chain.unwrap();
+            assert_eq!(header_chain.tip_index(), 5);
+            assert_eq!(&header_chain.headers[1..], &expected[..]);
+
+            let (local, remote) = UnixStream::pair().unwrap();
+            let (blocks, received) = futures::join!(
+                fetch_bodies(local, 3, 2),
+                recv_sync(remote, theirs.clone())
+            );
+            received.unwrap();
+            let headers: Vec<BlockHeader> = blocks.unwrap().iter().map(|block| block.header()).collect();
+            assert_eq!(&headers[..], &expected[1..3]);
+        });
+    }
+}
@@ -0,0 +1,56 @@
+// Kademlia bootstrap peers and issue provider records
+//
+// Nodes register as providers of the issue chain they host, so peers can find others
+// voting on the same issue through the DHT rather than relying on mDNS.
+
+use std::fmt::Display;
+
+use libp2p::{kad, multiaddr::Protocol, Multiaddr, PeerId};
+
+#[derive(Debug)]
+pub enum DiscoveryError {
+    InvalidAddress(String),
+    MissingPeerId(Multiaddr),
+}
+
+impl Display for DiscoveryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiscoveryError::InvalidAddress(addr) => write!(f, "Invalid bootstrap address: {}", addr),
+            DiscoveryError::MissingPeerId(addr) => write!(f, "Bootstrap address {} must end in /p2p/<peer id>", addr),
+        }
+    }
+}
+
+impl std::error::Error for DiscoveryError {}
+
+/// DHT key under which nodes hosting an issue's chain advertise themselves
+pub fn issue_key(issue_id: &str) -> kad::RecordKey {
+    return kad::RecordKey::new(&format!("/votechain/issue/{issue_id}"));
+}
+
+/// Split a bootstrap multiaddr into its peer id and the address to reach it on
+pub fn parse_bootstrap(addr: &str) -> Result<(PeerId, Multiaddr), DiscoveryError> {
+    let mut multiaddr: Multiaddr = addr.parse().map_err(|_| DiscoveryError::InvalidAddress(addr.to_string()))?;
+
+    match multiaddr.pop() {
+        Some(Protocol::P2p(peer_id)) => return Ok((peer_id, multiaddr)),
+        _ => return Err(DiscoveryError::MissingPeerId(addr.parse().unwrap())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bootstrap_addresses() {
+        let peer_id = PeerId::random();
+        let (parsed, addr) = parse_bootstrap(&format!("/ip4/127.0.0.1/tcp/47474/p2p/{peer_id}")).unwrap();
+        assert_eq!(parsed, peer_id);
+        assert_eq!(addr, "/ip4/127.0.0.1/tcp/47474".parse::<Multiaddr>().unwrap());
+
+        assert!(matches!(parse_bootstrap("/ip4/127.0.0.1/tcp/47474"), Err(DiscoveryError::MissingPeerId(_))));
+        assert!(matches!(parse_bootstrap("not an address"), Err(DiscoveryError::InvalidAddress(_))));
+    }
+}
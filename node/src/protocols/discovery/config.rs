@@ -0,0 +1,21 @@
+use confique::Config;
+use serde::Serialize;
+
+#[derive(Config, Serialize)]
+pub struct DiscoveryConfig {
+    /// Multiaddrs of known nodes to bootstrap the DHT from, each ending in `/p2p/<peer id>`
+    #[config(default = [])]
+    pub bootstrap: Vec<String>,
+
+    /// Discover nodes on the local network via mDNS
+    #[config(default = true)]
+    pub mdns: bool,
+
+    /// Seconds between Kademlia random walks, keeping the routing table populated
+    #[config(default = 60)]
+    pub random_walk_secs: u64,
+
+    /// Seconds between lookups for other providers of our issue chain
+    #[config(default = 30)]
+    pub provider_lookup_secs: u64,
+}
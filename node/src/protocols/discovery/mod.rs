@@ -0,0 +1,2 @@
+pub(crate) mod bootstrap;
+pub(crate) mod config;
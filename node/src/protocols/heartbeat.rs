@@ -1,26 +1,33 @@
+use blake3::Hash;
 use serde::{Deserialize, Serialize};
 use libp2p::{request_response::{self, ProtocolSupport}, StreamProtocol};
 
+use crate::votechain::{chain::Blockchain, errors::Error};
 
-#[derive(Debug, Serialize, Deserialize)]
+
+/// Summary of a node's chain. Sent as both the request and the response, so each side learns the other's status
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HeartbeatMessage {
-    height: u32
+    pub height: u32,
+    pub head_hash: Hash,
+    pub total_work: u64,
 }
 
-pub type Behaviour = request_response::cbor::Behaviour::<HeartbeatMessage, HeartbeatMessage>;
+impl HeartbeatMessage {
+    pub fn from_chain(chain: &Blockchain) -> Result<Self, Error> {
+        return Ok(Self {
+            height: chain.get_height(),
+            head_hash: chain.get_hash_at(chain.get_height())?,
+            total_work: chain.total_work(),
+        });
+    }
+}
 
-// impl Default for Behaviour {
-//     fn default() -> Self {
-//         return Self::new(
-//             [(StreamProtocol::new("/votechain/heartbeat/0.0.0"), ProtocolSupport::Full)],
-//             request_response::Config::default()
-//         );
-//     }
-// }
+pub type Behaviour = request_response::cbor::Behaviour::<HeartbeatMessage, HeartbeatMessage>;
 
 pub fn get_behaviour() -> Behaviour {
     Behaviour::new(
-        [(StreamProtocol::new("/votechain/heartbeat/0.0"), ProtocolSupport::Full)],
+        [(StreamProtocol::new("/votechain/heartbeat/1.0"), ProtocolSupport::Full)],
         request_response::Config::default()
     )
 }
@@ -1,3 +1,7 @@
+pub mod authority;
 pub mod ballot_gossip;
 pub mod chain_sync;
-pub mod heartbeat;
\ No newline at end of file
+pub mod discovery;
+pub mod heartbeat;
+pub mod reputation;
+pub mod submission;
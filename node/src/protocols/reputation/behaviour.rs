@@ -0,0 +1,257 @@
+use std::{collections::{HashMap, VecDeque}, fmt::Display, task::Poll, time::{Duration, Instant}};
+
+use futures::StreamExt;
+use futures_ticker::Ticker;
+use libp2p::{core::Endpoint, swarm::{behaviour::ConnectionEstablished, dummy, CloseConnection, ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm}, Multiaddr, PeerId};
+use tracing::info;
+
+use super::config::ReputationConfig;
+
+/// Behaviour observed from a peer, each adjusting its score by the configured amount
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PeerAction {
+    /// Forwarded a ballot which failed validation
+    InvalidBallot,
+    /// Forwarded a ballot which passed validation
+    ValidBallot,
+    /// Sent sync data which broke the protocol or failed validation
+    InvalidSyncData,
+    /// A sync stream with the peer failed or timed out
+    SyncFailure,
+    /// Served a sync which extended our chain
+    SyncServed,
+    /// Any other failed protocol exchange, such as an unanswered heartbeat
+    ProtocolError,
+}
+
+#[derive(Debug)]
+pub enum Event {
+    /// The peer's score fell to the ban threshold, it has been disconnected
+    Banned { peer: PeerId, score: i32 },
+    /// The peer's ban has expired and it may reconnect
+    Unbanned(PeerId),
+}
+
+/// Reason given when refusing a connection to a banned peer
+#[derive(Debug)]
+pub struct BannedPeer(PeerId);
+
+impl Display for BannedPeer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Peer {} is banned", self.0)
+    }
+}
+
+impl std::error::Error for BannedPeer {}
+
+pub struct Behaviour {
+    config: ReputationConfig,
+
+    scores: HashMap<PeerId, i32>,
+
+    /// Banned peers, with the time each ban expires
+    banned: HashMap<PeerId, Instant>,
+
+    /// Connections to banned peers awaiting closure
+    close_connections: VecDeque<PeerId>,
+
+    events: VecDeque<Event>,
+
+    decay: Ticker,
+}
+
+impl Behaviour {
+    pub fn new(config: ReputationConfig) -> Self {
+        return Self {
+            config,
+            scores: HashMap::new(),
+            banned: HashMap::new(),
+            close_connections: VecDeque::new(),
+            events: VecDeque::new(),
+            decay: Ticker::new(Duration::from_secs(60)),
+        }
+    }
+
+    pub fn report(&mut self, peer_id: PeerId, action: PeerAction) {
+        self.adjust(peer_id, self.delta(action), Instant::now());
+    }
+
+    pub fn score(&self, peer_id: &PeerId) -> i32 {
+        return self.scores.get(peer_id).copied().unwrap_or(0);
+    }
+
+    pub fn is_banned(&self, peer_id: &PeerId) -> bool {
+        return self.banned.contains_key(peer_id);
+    }
+
+    fn delta(&self, action: PeerAction) -> i32 {
+        match action {
+            PeerAction::InvalidBallot => return -self.config.invalid_ballot_penalty,
+            PeerAction::InvalidSyncData => return -self.config.invalid_sync_penalty,
+            PeerAction::SyncFailure => return -self.config.sync_failure_penalty,
+            PeerAction::ProtocolError => return -self.config.protocol_error_penalty,
+            PeerAction::ValidBallot | PeerAction::SyncServed => return self.config.good_behaviour_reward,
+        }
+    }
+
+    fn adjust(&mut self, peer_id: PeerId, delta: i32, now: Instant) {
+        if self.is_banned(&peer_id) {
+            return;
+        }
+
+        let score = self.scores.entry(peer_id).or_insert(0);
+        *score = score.saturating_add(delta).min(self.config.max_score);
+
+        if *score <= self.config.ban_threshold {
+            let score = *score;
+            info!("REPUTATION: Banning {} with score {}", peer_id, score);
+            self.banned.insert(peer_id, now + Duration::from_secs(self.config.ban_duration_secs));
+            self.close_connections.push_back(peer_id);
+            self.events.push_back(Event::Banned { peer: peer_id, score });
+        }
+    }
+
+    /// Drift every score back towards zero and lift any expired bans
+    fn decay(&mut self, now: Instant) {
+        let step = self.config.decay_per_minute;
+        self.scores.retain(|_, score| {
+            *score = if *score > 0 { (*score - step).max(0) } else { (*score + step).min(0) };
+            *score != 0
+        });
+
+        let expired: Vec<PeerId> = self.banned.iter()
+            .filter(|(_, until)| **until <= now)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+
+        for peer_id in expired {
+            info!("REPUTATION: Ban on {} expired", peer_id);
+            self.banned.remove(&peer_id);
+            self.scores.remove(&peer_id);
+            self.events.push_back(Event::Unbanned(peer_id));
+        }
+    }
+
+    fn deny_banned(&self, peer_id: &PeerId) -> Result<(), ConnectionDenied> {
+        if self.is_banned(peer_id) {
+            return Err(ConnectionDenied::new(BannedPeer(*peer_id)));
+        }
+        return Ok(());
+    }
+}
+
+impl NetworkBehaviour for Behaviour {
+    type ConnectionHandler = dummy::ConnectionHandler;
+
+    type ToSwarm = Event;
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        maybe_peer: Option<PeerId>,
+        _addresses: &[Multiaddr],
+        _effective_role: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        if let Some(peer_id) = maybe_peer {
+            self.deny_banned(&peer_id)?;
+        }
+        return Ok(Vec::new());
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        peer: PeerId,
+        _local_addr: &Multiaddr,
+        _remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.deny_banned(&peer)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.deny_banned(&peer)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        _peer_id: PeerId,
+        _connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(&mut self, cx: &mut std::task::Context<'_>) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        while let Poll::Ready(Some(_)) = self.decay.poll_next_unpin(cx) {
+            self.decay(Instant::now());
+        }
+
+        if let Some(peer_id) = self.close_connections.pop_front() {
+            return Poll::Ready(ToSwarm::CloseConnection { peer_id, connection: CloseConnection::All });
+        }
+
+        if let Some(event) = self.events.pop_front() {
+            return Poll::Ready(ToSwarm::GenerateEvent(event));
+        }
+
+        Poll::Pending
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm) {
+        // A connection may have been mid-handshake when the ban landed
+        if let FromSwarm::ConnectionEstablished(ConnectionEstablished { peer_id, .. }) = event {
+            if self.is_banned(&peer_id) {
+                self.close_connections.push_back(peer_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_behaviour() -> Behaviour {
+        return Behaviour::new(ReputationConfig::builder().load().unwrap());
+    }
+
+    #[test]
+    fn repeated_offences_ban() {
+        let mut reputation = test_behaviour();
+        let peer_id = PeerId::random();
+
+        reputation.report(peer_id, PeerAction::InvalidSyncData);
+        assert!(!reputation.is_banned(&peer_id));
+
+        reputation.report(peer_id, PeerAction::InvalidSyncData);
+        assert!(reputation.is_banned(&peer_id));
+        assert_eq!(reputation.close_connections.pop_front(), Some(peer_id));
+        assert!(reputation.deny_banned(&peer_id).is_err());
+    }
+
+    #[test]
+    fn scores_decay_and_bans_expire() {
+        let mut reputation = test_behaviour();
+        let (offender, banned) = (PeerId::random(), PeerId::random());
+        let now = Instant::now();
+
+        reputation.report(offender, PeerAction::SyncFailure);
+        reputation.adjust(banned, -1000, now);
+
+        reputation.decay(now);
+        assert_eq!(reputation.score(&offender), -3);
+        assert!(reputation.is_banned(&banned));
+
+        reputation.decay(now + Duration::from_secs(reputation.config.ban_duration_secs));
+        assert!(!reputation.is_banned(&banned));
+        assert_eq!(reputation.score(&banned), 0);
+    }
+}
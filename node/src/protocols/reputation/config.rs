@@ -0,0 +1,41 @@
+use confique::Config;
+use serde::Serialize;
+
+#[derive(Config, Serialize, Clone)]
+pub struct ReputationConfig {
+    /// Score at or below which a peer is disconnected and banned
+    #[config(default = -100)]
+    pub ban_threshold: i32,
+
+    /// Highest score a peer can build up, limiting how much good behaviour can offset later offences
+    #[config(default = 100)]
+    pub max_score: i32,
+
+    /// How long a ban lasts, in seconds
+    #[config(default = 600)]
+    pub ban_duration_secs: u64,
+
+    /// Points every score moves back towards zero each minute, so old offences are forgiven
+    #[config(default = 2)]
+    pub decay_per_minute: i32,
+
+    /// Penalty for forwarding a ballot which failed validation
+    #[config(default = 25)]
+    pub invalid_ballot_penalty: i32,
+
+    /// Penalty for sync responses which break the protocol or fail validation
+    #[config(default = 50)]
+    pub invalid_sync_penalty: i32,
+
+    /// Penalty for sync streams which fail or time out
+    #[config(default = 5)]
+    pub sync_failure_penalty: i32,
+
+    /// Penalty for any other failed protocol exchange, such as an unanswered heartbeat
+    #[config(default = 5)]
+    pub protocol_error_penalty: i32,
+
+    /// Reward for forwarding a valid ballot or serving a useful sync
+    #[config(default = 1)]
+    pub good_behaviour_reward: i32,
+}
@@ -0,0 +1,2 @@
+pub(crate) mod behaviour;
+pub(crate) mod config;
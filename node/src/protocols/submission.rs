@@ -0,0 +1,16 @@
+// Direct ballot submission from clients, acknowledging acceptance and later inclusion
+//
+// Clients which only gossiped their ballot had no way to learn whether any node took it.
+// Here a node answers each submission, and each status request after it, with what it knows.
+
+use libp2p::{request_response::{self, ProtocolSupport}, StreamProtocol};
+use vote_lib::submission::{SubmitRequest, SubmitResponse};
+
+pub type Behaviour = request_response::cbor::Behaviour::<SubmitRequest, SubmitResponse>;
+
+pub fn get_behaviour() -> Behaviour {
+    Behaviour::new(
+        [(StreamProtocol::new("/votechain/submit/1.0"), ProtocolSupport::Inbound)],
+        request_response::Config::default()
+    )
+}
@@ -0,0 +1,13 @@
+use confique::Config;
+use serde::Serialize;
+
+#[derive(Config, Serialize)]
+pub struct RpcConfig {
+    /// Serve the JSON-RPC API
+    #[config(default = true)]
+    pub enabled: bool,
+
+    /// Address to serve the API on. Falls back to any free port on the same interface if taken
+    #[config(default = "127.0.0.1:47480")]
+    pub address: String,
+}
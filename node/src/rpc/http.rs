@@ -0,0 +1,147 @@
+// Minimal HTTP/1.1 transport for the JSON-RPC API
+//
+// Each connection carries a single POSTed request and is closed after the response.
+// The API only reads state, but it is still meant to be bound to loopback.
+
+use std::sync::Arc;
+
+use async_std::{io::{self, BufReader}, net::{TcpListener, TcpStream}, prelude::*, task};
+use tracing::{debug, warn};
+
+use super::{methods, state::NodeState};
+
+/// Largest request body accepted, requests are small JSON objects
+const MAX_BODY_BYTES: usize = 64 * 1024;
+/// Largest request line and headers accepted together
+const MAX_HEADER_BYTES: u64 = 8 * 1024;
+
+/// Accept connections until the listener fails, handling each on its own task
+pub async fn serve(listener: TcpListener, state: Arc<NodeState>) {
+    let mut incoming = listener.incoming();
+    while let Some(stream) = incoming.next().await {
+        match stream {
+            Ok(stream) => {
+                let state = state.clone();
+                task::spawn(async move {
+                    if let Err(e) = handle_connection(stream, &state).await {
+                        debug!("RPC connection failed: {}", e);
+                    }
+                });
+            },
+            Err(e) => warn!("RPC listener failed to accept: {}", e),
+        }
+    }
+}
+
+async fn handle_connection(stream: TcpStream, state: &NodeState) -> io::Result<()> {
+    // Bound the headers, so a client can't grow a line without end
+    let mut reader = BufReader::new(stream.clone()).take(MAX_HEADER_BYTES);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            if reader.limit() == 0 {
+                return write_response(stream, "431 Request Header Fields Too Large", "").await;
+            }
+            break;
+        }
+        if !line.ends_with('\n') && reader.limit() == 0 {
+            return write_response(stream, "431 Request Header Fields Too Large", "").await;
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    if !request_line.starts_with("POST ") {
+        return write_response(stream, "405 Method Not Allowed", "").await;
+    }
+    if content_length > MAX_BODY_BYTES {
+        return write_response(stream, "413 Payload Too Large", "").await;
+    }
+
+    let mut body = vec![0; content_length];
+    reader.set_limit(content_length as u64);
+    reader.read_exact(&mut body).await?;
+
+    let response = methods::dispatch(state, &body).await;
+    return write_response(stream, "200 OK", &response.to_string()).await;
+}
+
+async fn write_response(mut stream: TcpStream, status: &str, body: &str) -> io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    return stream.flush().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::sync::Mutex;
+    use ed25519_dalek::SigningKey;
+    use libp2p::PeerId;
+    use serde_json::{json, Value};
+
+    use crate::{census::DumbCensus, trustee::delegations::DelegationGraph, votechain::{block::test_genesis_info, chain::Blockchain}};
+
+    use super::*;
+
+    async fn post(addr: std::net::SocketAddr, request: Value) -> Value {
+        let body = request.to_string();
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}", body.len()).as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        return serde_json::from_str(body).unwrap();
+    }
+
+    #[async_std::test]
+    async fn serves_requests_on_loopback() {
+        let sk = SigningKey::from_bytes(&[0; 32]);
+        let census = Arc::new(DumbCensus::from_vec(vec![sk.verifying_key()]));
+        let state = Arc::new(NodeState::new(
+            Arc::new(Mutex::new(Blockchain::in_memory(test_genesis_info(), &sk).unwrap())),
+            census.clone(),
+            Arc::new(DelegationGraph::random(census.as_vec())),
+            PeerId::random(),
+        ));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        task::spawn(serve(listener, state));
+
+        let status = post(addr, json!({ "jsonrpc": "2.0", "method": "chain_status", "id": 1 })).await;
+        assert_eq!(status["id"], 1);
+        assert_eq!(status["result"]["height"], 1);
+        assert_eq!(status["result"]["issue_id"], "test");
+
+        let genesis = post(addr, json!({ "jsonrpc": "2.0", "method": "chain_getBlock", "params": { "index": 1 }, "id": 2 })).await;
+        assert_eq!(genesis["result"]["kind"], "genesis");
+
+        let member = post(addr, json!({
+            "jsonrpc": "2.0", "method": "census_contains", "params": { "key": hex::encode(sk.verifying_key().as_bytes()) }, "id": 3
+        })).await;
+        assert_eq!(member["result"]["member"], true);
+
+        let missing = post(addr, json!({ "jsonrpc": "2.0", "method": "chain_getBlock", "params": { "index": 9 }, "id": 4 })).await;
+        assert_eq!(missing["error"]["code"], methods::NOT_FOUND);
+
+        let unknown = post(addr, json!({ "jsonrpc": "2.0", "method": "nope", "id": 5 })).await;
+        assert_eq!(unknown["error"]["code"], methods::METHOD_NOT_FOUND);
+    }
+}
@@ -0,0 +1,325 @@
+// JSON-RPC 2.0 method dispatch over the shared node state
+//
+// Every method is read-only. Params are passed by name, e.g. `{"index": 3}` or `{"key": "<hex>"}`.
+
+use std::{collections::HashSet, fmt::Display};
+
+use ed25519_dalek::VerifyingKey;
+use serde_json::{json, Value};
+use vote_lib::{Ballot, Signed};
+
+use crate::votechain::{block::Block, errors::Error};
+
+use super::state::NodeState;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// The requested block or key isn't known to this node
+pub const NOT_FOUND: i64 = -32004;
+
+#[derive(Debug)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        return Self { code, message: message.into() };
+    }
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+impl From<Error> for RpcError {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::BlockNotFound(_) | Error::BlockPruned(_) => return RpcError::new(NOT_FOUND, error.to_string()),
+            _ => return RpcError::new(INTERNAL_ERROR, error.to_string()),
+        }
+    }
+}
+
+/// Handle a raw request body, always producing a JSON-RPC response
+pub async fn dispatch(state: &NodeState, body: &[u8]) -> Value {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => return error_response(Value::Null, RpcError::new(PARSE_ERROR, e.to_string())),
+    };
+
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let method = match request.get("method").and_then(Value::as_str) {
+        Some(method) if request.get("jsonrpc") == Some(&json!("2.0")) => method,
+        _ => return error_response(id, RpcError::new(INVALID_REQUEST, "Expected a JSON-RPC 2.0 request")),
+    };
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+    match call(state, method, &params).await {
+        Ok(result) => return json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        Err(e) => return error_response(id, e),
+    }
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    return json!({ "jsonrpc": "2.0", "error": { "code": error.code, "message": error.message }, "id": id });
+}
+
+async fn call(state: &NodeState, method: &str, params: &Value) -> Result<Value, RpcError> {
+    match method {
+        "chain_status" => return chain_status(state).await,
+        "chain_getBlock" => return get_block(state, params).await,
+        "pool_list" => return pool_list(state).await,
+        "pool_stats" => return pool_stats(state).await,
+        "net_peers" => return net_peers(state).await,
+        "net_syncStatus" => return sync_status(state).await,
+        "census_list" => return census_list(state),
+        "census_contains" => return census_contains(state, params),
+        "delegation_get" => return delegation_get(state, params),
+        "election_status" => return election_status(state).await,
+        _ => return Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method: {method}"))),
+    }
+}
+
+async fn chain_status(state: &NodeState) -> Result<Value, RpcError> {
+    let chain = state.chain.lock().await;
+    let height = chain.get_height();
+
+    return Ok(json!({
+        "issue_id": chain.issue_id(),
+        "genesis_hash": chain.get_hash_at(1)?.to_string(),
+        "trustee_key": chain.genesis_info().trustee_key.fingerprint().to_string(),
+        "height": height,
+        "head_hash": chain.get_hash_at(height)?.to_string(),
+        "total_work": chain.total_work(),
+        "sealed": chain.is_sealed(),
+    }));
+}
+
+async fn get_block(state: &NodeState, params: &Value) -> Result<Value, RpcError> {
+    let chain = state.chain.lock().await;
+
+    let index = match (params.get("index").and_then(Value::as_u64), params.get("hash").and_then(Value::as_str)) {
+        (Some(index), _) => u32::try_from(index).map_err(|_| RpcError::new(INVALID_PARAMS, format!("Block index {index} out of range")))?,
+        (None, Some(hash)) => {
+            let hash = blake3::Hash::from_hex(hash).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+            chain.index_of(&hash).ok_or(RpcError::new(NOT_FOUND, format!("No block found with hash {hash}")))?
+        },
+        (None, None) => return Err(RpcError::new(INVALID_PARAMS, "Expected an index or hash")),
+    };
+
+    return Ok(block_view(index, &chain.get_block(&index)?));
+}
+
+async fn pool_list(state: &NodeState) -> Result<Value, RpcError> {
+    let chain = state.chain.lock().await;
+    return Ok(Value::Array(chain.pooled_ballots().iter().map(ballot_view).collect()));
+}
+
+async fn pool_stats(state: &NodeState) -> Result<Value, RpcError> {
+    let chain = state.chain.lock().await;
+    let pool = chain.pooled_ballots();
+    let voters: HashSet<VerifyingKey> = pool.iter().map(|ballot| ballot.signer()).collect();
+
+    return Ok(json!({
+        "size": pool.len(),
+        "unique_voters": voters.len(),
+        "oldest": pool.iter().map(|ballot| ballot.timestamp()).min().map(|timestamp| timestamp.to_string()),
+    }));
+}
+
+async fn net_peers(state: &NodeState) -> Result<Value, RpcError> {
+    let network = state.network.lock().await;
+    let peers: Vec<Value> = network.connected.iter()
+        .map(|peer_id| {
+            let status = network.peer_status.get(peer_id);
+            json!({
+                "peer_id": peer_id.to_string(),
+                "height": status.map(|status| status.height),
+                "head_hash": status.map(|status| status.head_hash.to_string()),
+                "total_work": status.map(|status| status.total_work),
+            })
+        })
+        .collect();
+
+    return Ok(json!({ "local_peer_id": network.local_peer_id.to_string(), "peers": peers }));
+}
+
+async fn sync_status(state: &NodeState) -> Result<Value, RpcError> {
+    let local_height = state.chain.lock().await.get_height();
+    let network = state.network.lock().await;
+
+    let best_peer = network.peer_status.iter()
+        .max_by_key(|(_, status)| status.total_work)
+        .map(|(peer_id, status)| json!({ "peer_id": peer_id.to_string(), "height": status.height }));
+    let last_sync = network.last_sync.as_ref().map(|report| json!({
+        "peer_id": report.peer.to_string(),
+        "blocks_applied": report.blocks_applied,
+        "error": report.error,
+    }));
+
+    return Ok(json!({
+        "local_height": local_height,
+        "syncing": network.syncing.map(|peer_id| peer_id.to_string()),
+        "best_peer": best_peer,
+        "last_sync": last_sync,
+    }));
+}
+
+fn census_list(state: &NodeState) -> Result<Value, RpcError> {
+    let mut keys: Vec<String> = state.census.as_vec().iter().map(|key| hex::encode(key.as_bytes())).collect();
+    keys.sort();
+    return Ok(json!(keys));
+}
+
+fn census_contains(state: &NodeState, params: &Value) -> Result<Value, RpcError> {
+    let key = key_param(params)?;
+    return Ok(json!({ "key": hex::encode(key.as_bytes()), "member": state.census.contains_voter(&key) }));
+}
+
+fn delegation_get(state: &NodeState, params: &Value) -> Result<Value, RpcError> {
+    let key = key_param(params)?;
+    if !state.census.contains_voter(&key) {
+        return Err(RpcError::new(NOT_FOUND, "Key is not in the census"));
+    }
+
+    let delegators: Vec<String> = state.delegations.delegators_of(&key).iter().map(|key| hex::encode(key.as_bytes())).collect();
+    return Ok(json!({
+        "key": hex::encode(key.as_bytes()),
+        "delegate": state.delegations.delegate_of(&key).map(|key| hex::encode(key.as_bytes())),
+        "delegators": delegators,
+    }));
+}
+
+async fn election_status(state: &NodeState) -> Result<Value, RpcError> {
+    let chain = state.chain.lock().await;
+    let election = state.election.lock().await;
+
+    let ballots_on_chain: usize = (2..=chain.get_height())
+        .filter_map(|index| chain.try_get_block(&index))
+        .filter_map(|block| block.get_ballots().map(|ballots| ballots.len()))
+        .sum();
+
+    // A bootstrapped chain carries the result it was sealed with
+    let result = election.result.or(chain.snapshot().and_then(|snapshot| snapshot.result));
+
+    return Ok(json!({
+        "issue_id": chain.issue_id(),
+        "sealed": chain.is_sealed(),
+        "ballots_on_chain": ballots_on_chain,
+        "ballots_pooled": chain.pooled_ballots().len(),
+        "result": result,
+        "resolved_at": election.resolved_at,
+    }));
+}
+
+fn key_param(params: &Value) -> Result<VerifyingKey, RpcError> {
+    let invalid = || RpcError::new(INVALID_PARAMS, "Expected a hex encoded ed25519 public key as `key`");
+
+    let bytes = hex::decode(params.get("key").and_then(Value::as_str).ok_or_else(invalid)?).map_err(|_| invalid())?;
+    let bytes: [u8; 32] = bytes.try_into().map_err(|_| invalid())?;
+    return VerifyingKey::from_bytes(&bytes).map_err(|_| invalid());
+}
+
+fn block_view(index: u32, block: &Block) -> Value {
+    let header = block.header();
+    let kind = match (index, block.is_seal()) {
+        (1, _) => "genesis",
+        (_, true) => "seal",
+        _ => "ballots",
+    };
+    let ballots: Vec<Value> = block.get_ballots().map(|ballots| ballots.iter().map(ballot_view).collect()).unwrap_or_default();
+
+    return json!({
+        "index": index,
+        "hash": header.hash().to_string(),
+        "previous_hash": header.previous_hash.to_string(),
+        "timestamp": header.timestamp.to_string(),
+        "signatory": hex::encode(header.signatory.as_bytes()),
+        "ballot_root": header.ballot_root.to_string(),
+        "kind": kind,
+        "ballots": ballots,
+    });
+}
+
+fn ballot_view(ballot: &Signed<Ballot>) -> Value {
+    return json!({
+        "id": ballot.content_id().to_string(),
+        "signer": hex::encode(ballot.signer().as_bytes()),
+        "issue_id": ballot.issue_id(),
+        "timestamp": ballot.timestamp().to_string(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use async_std::sync::Mutex;
+    use ed25519_dalek::SigningKey;
+    use libp2p::PeerId;
+
+    use crate::{census::DumbCensus, trustee::delegations::DelegationGraph, votechain::{block::test_genesis_info, chain::Blockchain}};
+
+    use super::*;
+
+    fn test_state(sk: &SigningKey) -> NodeState {
+        let census = Arc::new(DumbCensus::from_vec(vec![sk.verifying_key()]));
+        return NodeState::new(
+            Arc::new(Mutex::new(Blockchain::in_memory(test_genesis_info(), sk).unwrap())),
+            census.clone(),
+            Arc::new(DelegationGraph::random(census.as_vec())),
+            PeerId::random(),
+        );
+    }
+
+    async fn block_by_hash(state: &NodeState, hash: blake3::Hash) -> Value {
+        let request = json!({ "jsonrpc": "2.0", "method": "chain_getBlock", "params": { "hash": hash.to_string() }, "id": 1 });
+        return dispatch(state, request.to_string().as_bytes()).await;
+    }
+
+    #[async_std::test]
+    async fn appended_blocks_found_by_hash() {
+        let mut sk = SigningKey::from_bytes(&[0; 32]);
+        let state = test_state(&sk);
+        let block = {
+            let mut chain = state.chain.lock().await;
+            let block = Block::new(&mut sk, &chain.get_block(&1).unwrap(), Vec::new()).unwrap();
+            chain.append(block.clone()).unwrap();
+            block
+        };
+
+        let response = block_by_hash(&state, block.hash()).await;
+        assert_eq!(response["result"]["index"], 2);
+    }
+
+    #[async_std::test]
+    async fn replaced_fork_hashes_not_found() {
+        let mut sk = SigningKey::from_bytes(&[0; 32]);
+        let state = test_state(&sk);
+        let (replaced, fork) = {
+            let mut chain = state.chain.lock().await;
+            let genesis = chain.get_block(&1).unwrap();
+            let replaced = Block::new(&mut sk, &genesis, Vec::new()).unwrap();
+            chain.append(replaced.clone()).unwrap();
+
+            let fork1 = Block::new(&mut sk, &genesis, Vec::new()).unwrap();
+            let fork2 = Block::new(&mut sk, &fork1, Vec::new()).unwrap();
+            chain.try_update_longest(1, vec![genesis, fork1.clone(), fork2]).unwrap();
+            (replaced, fork1)
+        };
+
+        let response = block_by_hash(&state, replaced.hash()).await;
+        assert_eq!(response["error"]["code"], NOT_FOUND);
+        let response = block_by_hash(&state, fork.hash()).await;
+        assert_eq!(response["result"]["index"], 2);
+    }
+}
@@ -0,0 +1,4 @@
+pub(crate) mod config;
+pub(crate) mod http;
+pub(crate) mod methods;
+pub(crate) mod state;
@@ -0,0 +1,71 @@
+use std::{collections::{HashMap, HashSet}, sync::Arc};
+
+use async_std::sync::Mutex;
+use libp2p::PeerId;
+
+use crate::{census::DumbCensus, protocols::heartbeat::HeartbeatMessage, trustee::delegations::DelegationGraph, votechain::chain::Blockchain};
+
+/// Everything the API reads from, shared with the swarm loop which keeps the network view current
+pub struct NodeState {
+    pub chain: Arc<Mutex<Blockchain>>,
+    pub census: Arc<DumbCensus>,
+    pub delegations: Arc<DelegationGraph>,
+    pub network: Mutex<NetworkStatus>,
+    pub election: Mutex<ElectionStatus>,
+}
+
+impl NodeState {
+    pub fn new(chain: Arc<Mutex<Blockchain>>, census: Arc<DumbCensus>, delegations: Arc<DelegationGraph>, local_peer_id: PeerId) -> Self {
+        return Self {
+            chain,
+            census,
+            delegations,
+            network: Mutex::new(NetworkStatus::new(local_peer_id)),
+            election: Mutex::new(ElectionStatus::default()),
+        };
+    }
+}
+
+/// The swarm's view of the network, which the API can't reach directly
+pub struct NetworkStatus {
+    pub local_peer_id: PeerId,
+    pub connected: HashSet<PeerId>,
+    /// Latest chain status each peer advertised through heartbeats
+    pub peer_status: HashMap<PeerId, HeartbeatMessage>,
+    pub syncing: Option<PeerId>,
+    pub last_sync: Option<SyncReport>,
+}
+
+impl NetworkStatus {
+    pub fn new(local_peer_id: PeerId) -> Self {
+        return Self {
+            local_peer_id,
+            connected: HashSet::new(),
+            peer_status: HashMap::new(),
+            syncing: None,
+            last_sync: None,
+        };
+    }
+
+    pub fn disconnected(&mut self, peer_id: &PeerId) {
+        self.connected.remove(peer_id);
+        self.peer_status.remove(peer_id);
+    }
+}
+
+/// Outcome of the most recent sync attempt
+#[derive(Debug, Clone)]
+pub struct SyncReport {
+    pub peer: PeerId,
+    pub blocks_applied: u32,
+    /// Why the sync failed, if it did
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ElectionStatus {
+    /// Most recent result, true if the vote passed
+    pub result: Option<bool>,
+    /// Chain height the result was resolved at
+    pub resolved_at: Option<u32>,
+}
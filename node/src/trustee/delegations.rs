@@ -6,6 +6,7 @@ use rand::{seq::SliceRandom, Rng};
 
 /// Delegation Graph stores the most simple mapping from delegator -> delegate
 ///  TODO: Potentially switch the delegations hashmap to a more efficient structure, such as a tree
+#[derive(Clone)]
 pub struct DelegationGraph {
     /// Storage backing for delegator -> delegate representation
     delegation_map: HashMap<VerifyingKey, VerifyingKey>,
@@ -60,6 +61,16 @@ impl DelegationGraph {
     }
 
 
+    /// The key this voter has delegated their vote to, if any
+    pub fn delegate_of(&self, public_key: &VerifyingKey) -> Option<VerifyingKey> {
+        return self.delegation_map.get(public_key).copied()
+    }
+
+    /// Every key which has delegated directly to this voter
+    pub fn delegators_of(&self, public_key: &VerifyingKey) -> Vec<VerifyingKey> {
+        return self.representation_adj_list.get(public_key).cloned().unwrap_or_default()
+    }
+
     /// Resolve a hashmap of voter-weight pairs for every voter who actually cast a ballot in this vote
     pub fn generate_weights(&self, voters: &HashSet<VerifyingKey>) -> HashMap<VerifyingKey, u64> {
         let mut weights: HashMap<VerifyingKey, u64> = HashMap::new();
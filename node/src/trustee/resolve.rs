@@ -5,7 +5,7 @@ use std::{collections::{HashMap, HashSet}, ops::Deref};
 use async_std::sync::MutexGuard;
 use curv::BigInt;
 use ed25519_dalek::VerifyingKey;
-use paillier::{Decrypt, DecryptionKey, EncodedCiphertext, Encrypt, EncryptionKey, Paillier, RawPlaintext};
+use paillier::{Decrypt, DecryptionKey, EncodedCiphertext, EncryptWithChosenRandomness, EncryptionKey, Paillier, Randomness, RawCiphertext, RawPlaintext};
 use vote_lib::{Ballot, Signed};
 
 use crate::votechain::chain::Blockchain;
@@ -18,6 +18,24 @@ struct WeightedVote {
 }
 
 pub fn generate_vote_result(dk: &DecryptionKey, ek: &EncryptionKey, chain: &Blockchain, delegations: DelegationGraph) -> bool {
+    return decide(dk, aggregate_votes(ek, chain, delegations));
+}
+
+/// Whether an aggregated (for, against) tally passes
+pub fn decide(dk: &DecryptionKey, tally: (BigInt, BigInt)) -> bool {
+    let (tally_for, tally_against) = tally;
+
+    // Temporary: Decrypt and compare plaintexts
+    // TODO: Switch out with protocol as described by Ordinos
+    let vote_for: BigInt = Paillier::decrypt(dk, RawCiphertext::from(tally_for)).into();
+    let vote_against: BigInt = Paillier::decrypt(dk, RawCiphertext::from(tally_against)).into();
+
+    return vote_for > vote_against
+}
+
+/// Homomorphically sum the latest weighted ballot of every voter on the chain
+/// Returns the (for, against) ciphertexts, which are safe to publish without the decryption key
+pub fn aggregate_votes(ek: &EncryptionKey, chain: &Blockchain, delegations: DelegationGraph) -> (BigInt, BigInt) {
     // Generate a voter -> weighted vote packet hashmap for every voter
     // Only includes entries for individuals who actually cast a vote (Delegators are excluded)
     let mut weighted_votes: HashMap<VerifyingKey, Ballot> = HashMap::new();
@@ -47,30 +65,26 @@ pub fn generate_vote_result(dk: &DecryptionKey, ek: &EncryptionKey, chain: &Bloc
 
     let weight_map: HashMap<VerifyingKey, u64> = delegations.generate_weights(&voter_set);
 
-    // Weight all vote packets
-    for (voter, weight) in weight_map {
-        weighted_votes.entry(voter).and_modify(|ballot| {
-            ballot.weight(&ek, weight);
-        });
-    }
-
-    // Sum all votes homomorphically
-    let all_ballots: Vec<&Ballot> = weighted_votes.values().collect();
+    let weighted = weighted_votes.iter()
+        .map(|(voter, ballot)| (ballot, weight_map.get(voter).copied().unwrap_or(1)));
 
-    let ptxt = RawPlaintext::from(BigInt::from(0));
-    let mut result_for = Paillier::encrypt(ek, ptxt.clone());
-    let mut result_against = Paillier::encrypt(ek, ptxt);
+    return tally(ek, weighted);
+}
 
-    for ballot in all_ballots {
-        (result_for, result_against) = ballot.sum(&ek, result_for, result_against)
+/// Weight and homomorphically sum ballots, starting from the encryption of zero under fixed
+/// randomness, so anyone holding the same ballots and weights derives the same ciphertexts
+pub fn tally<'a>(ek: &EncryptionKey, ballots: impl IntoIterator<Item = (&'a Ballot, u64)>) -> (BigInt, BigInt) {
+    let zero = Paillier::encrypt_with_chosen_randomness(ek, RawPlaintext::from(BigInt::from(0)), &Randomness(BigInt::from(1)));
+    let mut result_for = zero.clone();
+    let mut result_against = zero;
+
+    for (ballot, weight) in ballots {
+        let mut ballot = ballot.clone();
+        ballot.weight(ek, weight);
+        (result_for, result_against) = ballot.sum(ek, result_for, result_against)
     }
 
-    // Temporary: Decrypt and compare plaintexts
-    // TODO: Switch out with protocol as described by Ordinos
-    let vote_for: BigInt = Paillier::decrypt(dk, result_for).into();
-    let vote_against: BigInt = Paillier::decrypt(dk, result_against).into();
-
-    return vote_for > vote_against
+    return (result_for.into(), result_against.into())
 }
 
 #[cfg(test)]
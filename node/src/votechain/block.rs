@@ -1,13 +1,21 @@
-use std::{path::Path, time::{SystemTime, UNIX_EPOCH}};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use ed25519_dalek::{ed25519::signature::SignerMut, pkcs8::DecodePrivateKey, Signature, SigningKey, VerifyingKey};
+use ed25519_dalek::{ed25519::signature::SignerMut, pkcs8::DecodePrivateKey, Signature, SigningKey, Verifier, VerifyingKey};
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
-use vote_lib::{Ballot, Signed};
-use blake3::{Hasher, Hash};
+use vote_lib::{genesis::GenesisInfo, header::{header_hash, meets_difficulty, signing_hash, work_message}, merkle::{self, MerkleProof}, Ballot, Signed};
+use blake3::Hash;
+
+pub use vote_lib::header::BlockHeader;
 
 use super::errors::Error;
 
+/// Expected number of signatures tried to find a valid proof of work, at 12 leading zero bits
+pub const WORK_PER_BLOCK: u64 = 1 << 12;
+
+// Every node must derive an identical genesis block, so the key is shared and fixed at build time
+const GENESIS_KEY_DER: &[u8] = include_bytes!("../../../temp/identities/default.der");
+
 // TODO: Breakout block components into different tables and store separately
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct BlockMeta {
@@ -17,7 +25,7 @@ struct BlockMeta {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 enum BlockData {
-    Genesis(String),
+    Genesis(GenesisInfo),
     Ballots(Vec<Signed<Ballot>>),
     Seal(String),
 }
@@ -49,17 +57,7 @@ impl Block {
             // TODO: Throw an error (Maybe we validate prev on addition to ensure this never happens? Clock resync could mess with it)
         }
 
-        let previous_hash = prev.hash();
-        let (nonce, signature) = Block::proof_of_work(sk, &previous_hash);
-
-        return Ok(Self {
-            timestamp,
-            previous_hash,
-            signatory: sk.verifying_key(),
-            signature,
-            data: BlockData::Ballots(data),
-            nonce,
-        });
+        return Ok(Block::signed(sk, timestamp, prev.hash(), BlockData::Ballots(data)));
     }
 
     pub fn seal(sk: &mut SigningKey, prev: &Block) -> Self {
@@ -67,21 +65,11 @@ impl Block {
             .duration_since(UNIX_EPOCH)
             .expect("Time Moved Backwards")
             .as_millis();
-        let previous_hash = prev.hash();
-        let (nonce, signature) = Block::proof_of_work(sk, &previous_hash);
-        let data = String::new();
-
-        return Self {
-            timestamp,
-            previous_hash,
-            signatory: sk.verifying_key(),
-            signature,
-            data: BlockData::Seal(data),
-            nonce
-        };
+        return Block::signed(sk, timestamp, prev.hash(), BlockData::Seal(String::new()));
     }
 
-    pub fn genesis() -> Self {
+    /// The first block of an issue's chain, committing to the issue and its trustee key
+    pub fn genesis(info: GenesisInfo) -> Self {
         // TODO: Find better way of timestamping genesis blocks, such that they are easily replicated across nodes
         // let timestamp = SystemTime::now()
         //     .duration_since(UNIX_EPOCH)
@@ -89,36 +77,63 @@ impl Block {
         //     .as_millis();
         let timestamp = 0;
         let previous_hash: Hash = [0;32].into();
-        let mut sk: SigningKey = DecodePrivateKey::read_pkcs8_der_file(Path::new("./temp/identities/default.der")).unwrap();
-        let (nonce, signature) = Block::proof_of_work(&mut sk, &previous_hash);
-        let data = String::new();
+        let mut sk: SigningKey = DecodePrivateKey::from_pkcs8_der(GENESIS_KEY_DER).unwrap();
 
-        return Self {
+        return Block::signed(&mut sk, timestamp, previous_hash, BlockData::Genesis(info));
+    }
+
+    /// Build a block and sign its signing hash as proof of work
+    fn signed(sk: &mut SigningKey, timestamp: u128, previous_hash: Hash, data: BlockData) -> Self {
+        let mut block = Self {
             timestamp,
             previous_hash,
             signatory: sk.verifying_key(),
-            signature,
-            data: BlockData::Genesis(data),
-            nonce,
+            signature: Signature::from_bytes(&[0; 64]),
+            data,
+            nonce: [0; 8],
         };
+        (block.nonce, block.signature) = Block::proof_of_work(sk, &signing_hash(&block.hash(), &block.signatory));
+
+        return block;
     }
 
     pub fn hash(&self) -> Hash {
-        let mut hasher = Hasher::new();
+        return header_hash(self.timestamp, &self.previous_hash, &self.ballot_root());
+    }
 
-        hasher.update(&self.timestamp.to_be_bytes());
-        hasher.update(self.previous_hash.as_bytes());
+    pub fn header(&self) -> BlockHeader {
+        return BlockHeader {
+            timestamp: self.timestamp,
+            previous_hash: self.previous_hash,
+            ballot_root: self.ballot_root(),
+            signatory: self.signatory,
+            signature: self.signature,
+            nonce: self.nonce,
+        };
+    }
+
+    /// Merkle root over the ballots held in this block, allowing inclusion proofs
+    /// for individual ballots. Genesis blocks commit to their contents in its place,
+    /// and seal blocks have an all-zero root
+    pub fn ballot_root(&self) -> Hash {
+        match &self.data {
+            BlockData::Genesis(info) => return info.commitment(),
+            _ => return merkle::root(&self.ballot_leaves()),
+        }
+    }
+
+    /// Inclusion proof for the ballot at `index` against the ballot root
+    pub fn ballot_proof(&self, index: usize) -> Option<MerkleProof> {
+        return MerkleProof::new(&self.ballot_leaves(), index);
+    }
+
+    fn ballot_leaves(&self) -> Vec<Hash> {
         match &self.data {
             BlockData::Ballots(ballots) => {
-                for signed_ballot in ballots {
-                    hasher.update(&bincode::serialize(signed_ballot).unwrap());
-                }
+                return ballots.iter().map(ballot_leaf).collect();
             },
-            _ => {}
+            _ => return Vec::new(),
         }
-        
-        
-        return hasher.finalize().into();
     }
 
     pub fn is_valid(&self, prev: &Block) -> bool {
@@ -129,22 +144,23 @@ impl Block {
         return true;
     }
 
+    /// Check the signatory's claim over the whole header, including the proof of work
+    pub fn signature_valid(&self) -> bool {
+        let message = work_message(&signing_hash(&self.hash(), &self.signatory), &self.nonce);
+        return meets_difficulty(&self.signature)
+            && self.signatory.verify(&message, &self.signature).is_ok();
+    }
+
     // Simple proof of work calculation
     // Iterates to find a Nonce value which results in a signature with
-    // it's first 4 bits all zeroes
+    // it's first 12 bits all zeroes
     fn proof_of_work(sk: &mut SigningKey, hash: &Hash) -> ([u8; 8], Signature) {
         let mut rng = rand::thread_rng();
         let mut nonce: [u8; 8] = [0; 8];
         loop {
             rng.fill_bytes(&mut nonce);
-            let mut adjusted_hash = *(hash.clone().as_bytes());
-            for i in 0..8 {
-                adjusted_hash[i] = adjusted_hash[i] ^ nonce[i];
-            }
-    
-            let sig = sk.sign(&adjusted_hash);
-            let bytes = sig.to_bytes();
-            if bytes[0] == 0 && bytes[1] & 240 == 0 {
+            let sig = sk.sign(&work_message(hash, &nonce));
+            if meets_difficulty(&sig) {
                 return (nonce, sig)
             }
         }
@@ -154,6 +170,21 @@ impl Block {
         return self.previous_hash.clone();
     }
 
+    pub fn signatory(&self) -> VerifyingKey {
+        return self.signatory;
+    }
+
+    pub fn is_seal(&self) -> bool {
+        return matches!(self.data, BlockData::Seal(_));
+    }
+
+    pub fn genesis_info(&self) -> Option<&GenesisInfo> {
+        match &self.data {
+            BlockData::Genesis(info) => return Some(info),
+            _ => return None,
+        }
+    }
+
     pub fn get_ballots(&self) -> Option<&Vec<Signed<Ballot>>> {
         match &self.data {
             BlockData::Ballots(ballots) => return Some(ballots),
@@ -162,6 +193,20 @@ impl Block {
     }
 }
 
+/// Leaf committing to a ballot under a block's ballot root
+pub fn ballot_leaf(ballot: &Signed<Ballot>) -> Hash {
+    return merkle::leaf_hash(&bincode::serialize(ballot).unwrap());
+}
+
+/// Genesis content for tests, using the pregenerated simulation trustee key so no keypair is generated
+#[cfg(test)]
+pub(crate) fn test_genesis_info() -> GenesisInfo {
+    let keypair: paillier::Keypair = bincode::deserialize(include_bytes!("../../../temp/trustees.key")).unwrap();
+    let signer: SigningKey = DecodePrivateKey::from_pkcs8_der(GENESIS_KEY_DER).unwrap();
+    let (trustee_key, _dk) = vote_lib::trustee::split(&signer, "test".into(), keypair);
+    return GenesisInfo::new("test".into(), trustee_key);
+}
+
 #[cfg(test)]
 mod tests {
     use rand::rngs::OsRng;
@@ -171,7 +216,7 @@ mod tests {
     #[test]
     fn validates_correctly() {
         let mut sk = SigningKey::generate(&mut OsRng);
-        let initial = Block::genesis();
+        let initial = Block::genesis(test_genesis_info());
         let ballots: Vec<Signed<Ballot>> = Vec::new();
         
         let block = Block::new(&mut sk, &initial, ballots).unwrap();
@@ -179,6 +224,46 @@ mod tests {
         assert!(block.is_valid(&initial))
     }
 
+    #[test]
+    fn signature_validates() {
+        let mut sk = SigningKey::generate(&mut OsRng);
+        let block = Block::seal(&mut sk, &Block::genesis(test_genesis_info()));
+
+        assert!(block.signature_valid())
+    }
+
+    #[test]
+    fn header_matches_block() {
+        let mut sk = SigningKey::generate(&mut OsRng);
+        let genesis = Block::genesis(test_genesis_info());
+        let block = Block::new(&mut sk, &genesis, Vec::new()).unwrap();
+        let header = block.header();
+
+        assert_eq!(header.hash(), block.hash());
+        assert!(header.signature_valid());
+        assert!(header.extends(&genesis.header()));
+    }
+
+    #[test]
+    fn reused_signature_rejected() {
+        let mut sk = SigningKey::generate(&mut OsRng);
+        let genesis = Block::genesis(test_genesis_info());
+        let block = Block::new(&mut sk, &genesis, Vec::new()).unwrap();
+
+        // A real signature and nonce lifted onto a header with a different ballot root or signatory
+        let mut header = block.header();
+        header.ballot_root = [1; 32].into();
+        assert!(!header.signature_valid());
+
+        let mut header = block.header();
+        header.timestamp += 1;
+        assert!(!header.signature_valid());
+
+        let mut header = block.header();
+        header.signatory = SigningKey::generate(&mut OsRng).verifying_key();
+        assert!(!header.signature_valid());
+    }
+
     fn test_proof_of_work() {
 
     }
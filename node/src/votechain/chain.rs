@@ -1,98 +1,151 @@
-use std::{collections::HashMap, fmt::{Debug, Display}, fs, path::Path};
+use std::{collections::HashMap, fmt::{Debug, Display}, path::Path};
 
 use blake3::Hash;
-use ed25519_dalek::SigningKey;
-use heed::{types::{OwnedType, SerdeBincode}, Database, Env, EnvOpenOptions};
-use tracing::info;
-use vote_lib::{Ballot, Signed};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use tracing::{info, warn};
+use paillier::{DecryptionKey, EncryptionKey};
+use vote_lib::{genesis::GenesisInfo, receipt::{Receipt, Supersession}, submission::Inclusion, Ballot, Signed, SigningContext};
 
-use super::{block::Block, errors::Error, config::BlockchainConfig};
+use super::{block::{Block, BlockHeader, WORK_PER_BLOCK}, errors::Error, config::BlockchainConfig, snapshot::{self, Snapshot, SNAPSHOT_KEY}, store::{ChainStore, LmdbStore, MemoryStore, StoreOp}};
 
-
-// TODO: Tune block size to optimize for memory footprint
-const BLOCK_SIZE: usize = 2;
+/// Pooled ballots needed before a block is appended, unless configured otherwise
+const DEFAULT_BLOCK_SIZE: usize = 2;
 
 // TODO: Make blockchain access methods async & include interior 
 // mutexing (Assume that the chain is shared amongst potentially many threads)
 
+/// Summary of a section of the chain replaced by a longer fork
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChainUpdate {
+    pub new_height: u32,
+    /// Blocks written after the fork point
+    pub blocks_applied: u32,
+    /// Ballots from replaced blocks which were returned to the pool
+    pub ballots_returned: usize,
+}
+
 struct ChainMetadata {
     pub height: u32,
 }
 
 pub struct Blockchain {
+    // Issue and trustee key committed to by the genesis block
+    genesis: GenesisInfo,
+
     // Primary Chain Storage
-    chain_config: BlockchainConfig,
-    db_env: Env,
-    chain_db: Database<OwnedType<u32>, SerdeBincode<Block>>,
+    store: Box<dyn ChainStore>,
     hash_indexes: HashMap<Hash, u32>,
+    // Where every ballot on the chain sits, by content id and by signer
+    ballot_index: BallotIndex,
     metadata: ChainMetadata,
 
+    // Snapshot replacing the pruned ballot history of a sealed chain
+    snapshot: Option<Signed<Snapshot>>,
+    // Keys trusted to sign snapshots, always including our own
+    authorities: Vec<VerifyingKey>,
+
     // Pool of unsubmitted votes
     ballot_pool: Vec<Signed<Ballot>>,
+    block_size: usize,
 
     // Node Private key for adding new blocks
     signing_key: SigningKey
 }
 
 impl Blockchain {
-    pub fn new(config: BlockchainConfig, issue_id: String, sk: &SigningKey) -> Result<Self, Error> {
-        let path = Path::new(&config.path).join(Path::new(&issue_id));
-
-        let _ = fs::create_dir_all(path.clone());
-        // let env = EnvOpenOptions::new().open(Path::new(path.parent().unwrap()).join(path.file_name().unwrap())).unwrap();
-        let env = EnvOpenOptions::new()
-                        .map_size(10 * 1024 * 1024)
-                        .open(path)
-                        .unwrap();
-        let block_data_db: Database<OwnedType<u32>, SerdeBincode<Block>> = env.create_database(None).unwrap();
-
-        if block_data_db.is_empty(&env.read_txn()?)? {
+    /// Open (or create) the LMDB backed chain for an issue under the configured path
+    pub fn new(config: BlockchainConfig, genesis: GenesisInfo, sk: &SigningKey) -> Result<Self, Error> {
+        let path = Path::new(&config.path).join(Path::new(&genesis.issue_id));
+        let store = LmdbStore::open(&path, &config)?;
+
+        let mut chain = Self::with_store(Box::new(store), genesis, sk)?;
+        chain.block_size = config.block_size;
+        chain.authorities.extend(config.authority_keys().map_err(|_| Error::InvalidConfig("authorities must be hex encoded public keys"))?);
+        return Ok(chain);
+    }
+
+    /// Build a chain which only lives in memory, primarily for testing
+    pub fn in_memory(genesis: GenesisInfo, sk: &SigningKey) -> Result<Self, Error> {
+        return Self::with_store(Box::new(MemoryStore::new()), genesis, sk);
+    }
+
+    pub fn with_store(mut store: Box<dyn ChainStore>, genesis: GenesisInfo, sk: &SigningKey) -> Result<Self, Error> {
+        if store.block_count()? == 0 {
             // Build and insert genesis block
             info!("No blocks found, adding genesis");
-            let mut wtxn = env.write_txn()?;
-            let genesis = Block::genesis();
-            block_data_db.put(&mut wtxn, &1, &genesis)?;
-            wtxn.commit()?;
+            let genesis_block = Block::genesis(genesis.clone());
+            store.put_block(1, genesis_block.clone())?;
 
             // Setup mapping from hashes to indexes for easier lookup
             let mut hash_index_map = HashMap::new();
-            hash_index_map.insert(genesis.hash(), 1);
+            hash_index_map.insert(genesis_block.hash(), 1);
 
             return Ok(Self {
-                chain_config: config,
-                db_env: env,
-                chain_db: block_data_db,
+                genesis,
+                store,
                 hash_indexes: hash_index_map,
+                ballot_index: BallotIndex::default(),
                 metadata: ChainMetadata { height: 1 },
+                snapshot: None,
+                authorities: vec![sk.verifying_key()],
                 ballot_pool: Vec::new(),
+                block_size: DEFAULT_BLOCK_SIZE,
                 signing_key: sk.clone(),
             })
         }
 
+        // Genesis blocks once held only the issue id. Every later block links to the genesis hash,
+        // so such chains can't be rewritten to commit to a trustee key
+        let stored_genesis = match store.get_block(1) {
+            Err(Error::Heed(heed::Error::Decoding { .. })) | Err(Error::Bincode(_)) => return Err(Error::LegacyGenesis),
+            result => result?,
+        };
+
+        // An existing chain must have been opened for the same issue under the same trustee key
+        let stored_commitment = stored_genesis.and_then(|block| block.genesis_info().map(|info| info.commitment()));
+        if stored_commitment != Some(genesis.commitment()) {
+            return Err(Error::GenesisMismatch);
+        }
+
         let mut hash_indexes = HashMap::new();
+        let mut ballot_index = BallotIndex::default();
         let mut block_count = 0;
-        block_data_db.iter(&env.read_txn()?)?.for_each(|read_result| {
-            match read_result {
-                Ok((index, block)) => {
-                    info!("Read block {}", index);
-                    hash_indexes.insert(block.hash(), index);
-                    block_count += 1;
-                },
-                Err(_) => {}
-            }
-        });
+        store.for_each_block(&mut |index, block| {
+            info!("Read block {}", index);
+            hash_indexes.insert(block.hash(), index);
+            ballot_index.insert(index, &block);
+            block_count += 1;
+        })?;
+
+        // A pruned chain only holds its genesis and seal blocks, so take the height from the snapshot
+        let snapshot: Option<Signed<Snapshot>> = match store.get_metadata(SNAPSHOT_KEY)? {
+            Some(bytes) => Some(bincode::deserialize(&bytes)?),
+            None => None,
+        };
+        let height = match &snapshot {
+            Some(snapshot) => snapshot.height,
+            None => block_count,
+        };
 
         return Ok(Self {
-            chain_config: config,
-            db_env: env,
-            chain_db: block_data_db,
+            genesis,
+            store,
             hash_indexes: hash_indexes,
-            metadata: ChainMetadata { height: block_count },
+            ballot_index,
+            metadata: ChainMetadata { height },
+            snapshot,
+            authorities: vec![sk.verifying_key()],
             ballot_pool: Vec::new(),
+            block_size: DEFAULT_BLOCK_SIZE,
             signing_key: sk.clone(),
         })
     }
 
+    /// Index of the block with this hash on our chain
+    pub fn index_of(&self, hash: &Hash) -> Option<u32> {
+        return self.hash_indexes.get(hash).copied();
+    }
+
     pub fn get_block_from_hash(&self, hash: Hash) -> Result<Block, Error> {
         match self.hash_indexes.get(&hash) {
             Some(index) => { return self.get_block(index); },
@@ -102,13 +155,18 @@ impl Blockchain {
     }
 
     pub fn get_block(&self, index: &u32) -> Result<Block, Error> {
-        let rtxn = self.db_env.read_txn()?;
-        match self.chain_db.get(&rtxn, index)? {
+        match self.store.get_block(*index)? {
             Some(block) => Ok(block),
+            None if self.is_pruned(*index) => Err(Error::BlockPruned(*index)),
             None => Err(Error::BlockNotFound(*index))
         }
     }
 
+    /// Whether the block at this index was dropped when the chain was snapshotted
+    pub fn is_pruned(&self, index: u32) -> bool {
+        return self.snapshot.is_some() && index > 1 && index < self.metadata.height;
+    }
+
     pub fn try_get_block(&self, index: &u32) -> Option<Block> {
         match self.get_block(index) {
             Ok(block) => Some(block),
@@ -120,37 +178,84 @@ impl Blockchain {
         return self.metadata.height;
     }
 
+    /// Cumulative proof of work behind the head
+    /// Difficulty is currently fixed, so this grows linearly with height
+    pub fn total_work(&self) -> u64 {
+        return self.metadata.height as u64 * WORK_PER_BLOCK;
+    }
+
     pub fn get_hash_at(&self, index: u32) -> Result<Hash, Error> {
         return Ok(self.get_block(&index)?.hash())
     }
 
     pub fn blocks_from(&self, start_index: u32) -> Result<Vec<Block>, Error> {
-        let rtxn = self.db_env.read_txn()?;
         let mut blocks = Vec::new();
 
         for index in start_index..self.metadata.height+1 {
-            match self.chain_db.get(&rtxn, &index)? {
-                Some(block) => blocks.push(block),
-                None => return Err(Error::BlockNotFound(index))
+            blocks.push(self.get_block(&index)?);
+        }
+
+        return Ok(blocks);
+    }
+
+    /// Read up to `max_blocks` consecutive blocks from `start_index`, stopping before the encoded size would exceed `max_bytes`
+    /// At least one block is always returned, so a single oversized block can't stall a sync
+    pub fn blocks_page(&self, start_index: u32, max_blocks: u32, max_bytes: u64) -> Result<Vec<Block>, Error> {
+        let first = self.get_block(&start_index)?;
+        let mut size = bincode::serialized_size(&first)?;
+        let mut blocks = vec![first];
+        let end_index = self.metadata.height.min(start_index.saturating_add(max_blocks.max(1) - 1));
+
+        for index in start_index+1..end_index+1 {
+            let block = self.get_block(&index)?;
+            size += bincode::serialized_size(&block)?;
+            if size > max_bytes {
+                break;
             }
+            blocks.push(block);
         }
 
         return Ok(blocks);
     }
 
-    /// Append a new block, return the new height if successful
+    /// Headers of up to `max_count` consecutive blocks from `start_index`
+    pub fn headers_page(&self, start_index: u32, max_count: u32) -> Result<Vec<BlockHeader>, Error> {
+        let end_index = self.metadata.height.min(start_index.saturating_add(max_count.max(1) - 1));
+        let mut headers = Vec::new();
+
+        for index in start_index..end_index+1 {
+            headers.push(self.get_block(&index)?.header());
+        }
+
+        return Ok(headers);
+    }
+
+    /// Append a new block from a peer, checking its ballots as well as its place in the chain
     pub fn append(&mut self, block: Block) -> Result<(), Error> {
-        let head_index = &self.metadata.height;
+        if !self.ballots_valid(std::slice::from_ref(&block)) {
+            return Err(Error::InvalidNewBlock);
+        }
+        return self.append_block(block);
+    }
+
+    /// Append a block whose ballots are already known to be valid
+    fn append_block(&mut self, block: Block) -> Result<(), Error> {
+        let head_index = self.metadata.height;
         let head_block = self.get_block(&self.metadata.height)?;
 
+        if head_block.is_seal() {
+            return Err(Error::ChainSealed);
+        }
+
         if !block.is_valid(&head_block) {
             return Err(Error::InvalidNewBlock);
         }
 
         // Write new block to db
-        let mut wtxn = self.db_env.write_txn()?;
-        self.chain_db.put(&mut wtxn, &(head_index+1), &block)?;
-        wtxn.commit()?;
+        let hash = block.hash();
+        self.ballot_index.insert(head_index+1, &block);
+        self.store.put_block(head_index+1, block)?;
+        self.hash_indexes.insert(hash, head_index+1);
 
         self.metadata.height += 1;
 
@@ -158,44 +263,61 @@ impl Blockchain {
     }
 
     /// Call to update the chain to match the longest known chain from the network
-    /// If the provided updates are valid, returns a list of 'lost' votes (Votes not included in the new chain which were on the old one)
-    /// Otherwise returns None, indicating that the provided set of blocks was somehow invalid.
+    /// Replace everything after `fork_index` with `blocks`, where `blocks[0]` is our block at the fork.
+    /// Ballots on replaced blocks are returned to the pool and counted in the returned summary
     // 
     // TODO: Validate Separately or Inline? Ideally this recieves a stream which
     // continuously yeilds older blocks until either we reach genesis or the alternative
     // chain is deemed invalid (May want some early exit clauses too)
-    pub fn try_update_longest(&mut self, fork_index: u32, blocks: Vec<Block>) -> Result<u32, Error> {
+    pub fn try_update_longest(&mut self, fork_index: u32, blocks: Vec<Block>) -> Result<ChainUpdate, Error> {
+        if self.snapshot.is_some() {
+            return Err(Error::ChainSealed)
+        }
+
         if &blocks[0].hash() != &self.get_block(&fork_index)?.hash() || !is_valid_chain(&blocks) {
             return Err(Error::InvalidNewBlock)
         }
+        if !self.ballots_valid(&blocks[1..]) {
+            return Err(Error::InvalidNewBlock)
+        }
 
-        // Strip back to divergence point, appending lost votes to the ballot pool
-        let mut wtxn = self.db_env.write_txn()?;
-        let range = fork_index..self.metadata.height;
+        // Strip back to divergence point, collecting lost votes for the ballot pool
+        let mut ops = Vec::new();
+        let mut lost_ballots = Vec::new();
         info!("Stripping");
-        for index in range {
-            if let Some(block) = self.chain_db.get(&wtxn, &index)? {
-                if self.chain_db.delete(&mut wtxn, &index)? {
-                    if let Some(ballots) = block.get_ballots() {
-                        for ballot in ballots {
-                            // TODO: Verify if ballot (Or a newer ballot from the same caster) is already in the pool
-                            self.ballot_pool.push(ballot.clone())
-                        }
-                    }
-                };
+        for index in fork_index+1..self.metadata.height+1 {
+            if let Some(block) = self.store.get_block(index)? {
+                if let Some(ballots) = block.get_ballots() {
+                    lost_ballots.extend(ballots.iter().cloned());
+                }
+                ops.push(StoreOp::DeleteBlock(index));
             }
         }
 
         // Iteratively reappend
         let mut index = fork_index;
+        let mut ballot_index = self.ballot_index.clone();
+        ballot_index.truncate(fork_index);
+        // Hashes of replaced blocks would otherwise point at heights now held by the fork
+        let mut hash_indexes = self.hash_indexes.clone();
+        hash_indexes.retain(|_, existing| *existing <= fork_index);
         info!("Appending");
-        for block in &blocks {
+        for block in blocks {
             info!("New Height: {}", index);
-            self.chain_db.put(&mut wtxn, &index, block)?;
+            ballot_index.insert(index, &block);
+            hash_indexes.insert(block.hash(), index);
+            ops.push(StoreOp::PutBlock(index, block));
             index += 1;
         }
 
-        wtxn.commit()?;
+        // Strip and reappend atomically, so a failure can't leave a partial chain behind
+        self.store.write(ops)?;
+        self.ballot_index = ballot_index;
+        self.hash_indexes = hash_indexes;
+
+        // TODO: Verify if ballot (Or a newer ballot from the same caster) is already in the pool
+        let ballots_returned = lost_ballots.len();
+        self.ballot_pool.extend(lost_ballots);
 
         info!("Finished Update");
 
@@ -203,24 +325,128 @@ impl Blockchain {
 
         info!("New Sync Height: {}", self.metadata.height);
 
-        return Ok(index);
+        return Ok(ChainUpdate {
+            new_height: self.metadata.height,
+            blocks_applied: self.metadata.height - fork_index,
+            ballots_returned,
+        });
     }
 
     pub fn pool_ballot(&mut self, ballot: Signed<Ballot>) -> Result<(), Error> {
         self.ballot_pool.push(ballot);
 
-        // TODO: Replace with configurable block size & tune
         let pool_size = self.ballot_pool.len();
-        if pool_size >= BLOCK_SIZE {
+        if pool_size >= self.block_size {
             info!("Appending new block");
-            let block_ballots = self.ballot_pool.split_off(pool_size - BLOCK_SIZE);
+            let block_ballots = self.ballot_pool.split_off(pool_size - self.block_size);
             let prev = &self.get_block(&self.get_height())?;
-            let _ = self.append(Block::new(&mut self.signing_key.clone(), prev, block_ballots)?);
+            // Pooled ballots were validated as they arrived
+            let _ = self.append_block(Block::new(&mut self.signing_key.clone(), prev, block_ballots)?);
         }
 
         Ok(())
     }
 
+    pub fn issue_id(&self) -> &str {
+        return &self.genesis.issue_id;
+    }
+
+    pub fn genesis_info(&self) -> &GenesisInfo {
+        return &self.genesis;
+    }
+
+    /// Hash of the genesis block, which every ballot on this chain is signed over
+    pub fn genesis_hash(&self) -> Hash {
+        return self.genesis.hash();
+    }
+
+    /// Key every ballot on this chain must be encrypted and proven under
+    pub fn trustee_key(&self) -> &EncryptionKey {
+        return self.genesis.encryption_key();
+    }
+
+    /// Whether every ballot in blocks from a peer is signed, for this issue and proven under our trustee key.
+    /// Signatures across all the blocks are verified as one batch
+    fn ballots_valid(&self, blocks: &[Block]) -> bool {
+        let ballots: Vec<(&Block, &Signed<Ballot>)> = blocks.iter()
+            .flat_map(|block| block.get_ballots().into_iter().flatten().map(move |ballot| (block, ballot)))
+            .collect();
+
+        if let Some((block, ballot)) = ballots.iter().find(|(_, ballot)| ballot.signing_retired()) {
+            warn!("Rejecting block {} with ballot {} signed under a retired signing version", block.hash(), ballot.content_id());
+            return false;
+        }
+
+        let signed: Vec<&Signed<Ballot>> = ballots.iter().map(|(_, ballot)| *ballot).collect();
+        let genesis = self.genesis_hash();
+        if let Err(index) = vote_lib::verify_batch(&signed, &SigningContext::Issue { issue_id: self.issue_id(), genesis: &genesis }) {
+            let (block, ballot) = ballots[index];
+            warn!("Rejecting block {} with invalid ballot signature {}", block.hash(), ballot.content_id());
+            return false;
+        }
+
+        for (block, ballot) in ballots {
+            if ballot.issue_id() != self.issue_id() || !ballot.validate_proofs(self.trustee_key()) {
+                warn!("Rejecting block {} with invalid ballot {}", block.hash(), ballot.content_id());
+                return false;
+            }
+        }
+        return true;
+    }
+
+    /// Ballots waiting to be included in a block
+    pub fn pooled_ballots(&self) -> &[Signed<Ballot>] {
+        return &self.ballot_pool;
+    }
+
+    /// Whether a ballot with this content id is waiting in the pool
+    pub fn is_pooled(&self, content_id: &Hash) -> bool {
+        return self.ballot_pool.iter().any(|ballot| &ballot.content_id() == content_id);
+    }
+
+    /// Locate a ballot in the chain by its content id
+    /// Ballots in pruned blocks are not found
+    pub fn ballot_inclusion(&self, content_id: &Hash) -> Option<Inclusion> {
+        return self.locate_ballot(content_id).map(|(inclusion, _)| inclusion);
+    }
+
+    /// Everything a voter needs to verify a ballot's inclusion without trusting this node
+    pub fn ballot_receipt(&self, content_id: &Hash) -> Result<Option<Receipt>, Error> {
+        let (inclusion, ballot) = match self.locate_ballot(content_id) {
+            Some(located) => located,
+            None => return Ok(None),
+        };
+        let headers = self.headers_page(1, self.get_height())?;
+
+        // The most recent ballot cast after this one by the same key, whether on the chain or pooled
+        let chain_ballots = self.ballot_index.signed_by(&ballot.signer())
+            .filter_map(|content_id| self.locate_ballot(content_id))
+            .map(|(inclusion, newer)| Supersession { ballot: newer, inclusion: Some(inclusion) });
+        let pooled_ballots = self.ballot_pool.iter()
+            .filter(|other| other.signer() == ballot.signer())
+            .map(|newer| Supersession { ballot: newer.clone(), inclusion: None });
+        let superseded_by = chain_ballots.chain(pooled_ballots)
+            .filter(|other| other.ballot.timestamp() > ballot.timestamp())
+            .max_by_key(|other| other.ballot.timestamp());
+
+        return Ok(Some(Receipt { ballot, inclusion, headers, superseded_by }));
+    }
+
+    fn locate_ballot(&self, content_id: &Hash) -> Option<(Inclusion, Signed<Ballot>)> {
+        let (index, position) = self.ballot_index.locate(content_id)?;
+        let block = self.try_get_block(&index)?;
+        let ballot = block.get_ballots()?.get(position)?.clone();
+
+        let inclusion = Inclusion {
+            block_hash: block.hash(),
+            block_index: index,
+            ballot_root: block.ballot_root(),
+            proof: block.ballot_proof(position)?,
+            depth: self.get_height() - index + 1,
+        };
+        return Some((inclusion, ballot));
+    }
+
     pub fn blocks(&self) -> Vec<Block> {
         let mut blocks: Vec<Block> = Vec::new();
         for index in 1..self.metadata.height {
@@ -236,8 +462,106 @@ impl Blockchain {
         }
     }
 
-    pub fn seal(self) {
-        todo!("Add a sealing block")
+    /// Close the chain to further ballots by appending a sealing block
+    pub fn seal(&mut self) -> Result<(), Error> {
+        let head = self.get_block(&self.metadata.height)?;
+        if head.is_seal() {
+            return Err(Error::ChainSealed);
+        }
+
+        let seal = Block::seal(&mut self.signing_key.clone(), &head);
+        let seal_hash = seal.hash();
+        self.store.put_block(self.metadata.height + 1, seal)?;
+
+        self.metadata.height += 1;
+        self.hash_indexes.insert(seal_hash, self.metadata.height);
+
+        return Ok(());
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        match self.get_block(&self.metadata.height) {
+            Ok(head) => head.is_seal(),
+            Err(_) => false,
+        }
+    }
+
+    /// Export a signed snapshot of this sealed chain, tallying each voter's latest ballot under the resolved `weights`
+    pub fn export_snapshot(&self, weights: &HashMap<VerifyingKey, u64>, result: Option<bool>) -> Result<Signed<Snapshot>, Error> {
+        if let Some(snapshot) = &self.snapshot {
+            return Ok(snapshot.clone());
+        }
+
+        if !self.is_sealed() {
+            return Err(Error::ChainNotSealed);
+        }
+
+        let blocks = self.blocks_from(1)?;
+        let snapshot = Snapshot::from_blocks(self.genesis.issue_id.clone(), &blocks, weights, result)?;
+
+        return Ok(snapshot.sign(&self.signing_key));
+    }
+
+    /// Replace the ballot history of this sealed chain with the provided snapshot
+    /// Only the genesis and seal blocks are retained locally
+    pub fn prune(&mut self, snapshot: Signed<Snapshot>) -> Result<(), Error> {
+        snapshot::verify_signed(&snapshot, &self.get_block(&1)?, &self.authorities)?;
+
+        if !self.is_sealed() || snapshot.height != self.metadata.height {
+            return Err(Error::InvalidSnapshot("snapshot does not match local chain"));
+        }
+        for (index, hash) in (1..).zip(snapshot.block_hashes()) {
+            if self.get_hash_at(index)? != hash {
+                return Err(Error::InvalidSnapshot("snapshot does not match local chain"));
+            }
+        }
+
+        let mut ops = vec![StoreOp::PutMetadata(SNAPSHOT_KEY.to_string(), bincode::serialize(&snapshot)?)];
+        for index in 2..self.metadata.height {
+            ops.push(StoreOp::DeleteBlock(index));
+        }
+        self.store.write(ops)?;
+
+        self.hash_indexes.retain(|_, index| *index == 1 || *index == snapshot.height);
+        self.ballot_index = BallotIndex::default();
+        self.snapshot = Some(snapshot);
+
+        info!("Pruned chain to snapshot at height {}", self.metadata.height);
+
+        return Ok(());
+    }
+
+    /// Bootstrap this chain from a verified snapshot rather than syncing every block
+    /// The result is checked against the tally when given the trustees' decryption key
+    pub fn bootstrap(&mut self, snapshot: Signed<Snapshot>, dk: Option<&DecryptionKey>) -> Result<(), Error> {
+        let genesis = self.get_block(&1)?;
+        snapshot::verify_signed(&snapshot, &genesis, &self.authorities)?;
+
+        if dk.is_some_and(|dk| !snapshot.result_valid(dk)) {
+            return Err(Error::InvalidSnapshot("result does not match the tally"));
+        }
+
+        self.store.write(vec![
+            StoreOp::ClearBlocks,
+            StoreOp::PutBlock(1, genesis.clone()),
+            StoreOp::PutBlock(snapshot.height, snapshot.seal.clone()),
+            StoreOp::PutMetadata(SNAPSHOT_KEY.to_string(), bincode::serialize(&snapshot)?),
+        ])?;
+
+        self.hash_indexes.clear();
+        self.hash_indexes.insert(genesis.hash(), 1);
+        self.hash_indexes.insert(snapshot.head_hash(), snapshot.height);
+        self.ballot_index = BallotIndex::default();
+        self.metadata.height = snapshot.height;
+        self.snapshot = Some(snapshot);
+
+        info!("Bootstrapped chain from snapshot at height {}", self.metadata.height);
+
+        return Ok(());
+    }
+
+    pub fn snapshot(&self) -> Option<&Signed<Snapshot>> {
+        return self.snapshot.as_ref();
     }
 }
 
@@ -254,7 +578,7 @@ impl Debug for Blockchain {
         f.debug_struct("Blockchain")
             .field("height", &self.metadata.height)
             .field("max_hash", &block.hash())
-            .field("db_path", &self.chain_config.path)
+            .field("store", &self.store)
             .finish()
     }
 }
@@ -273,6 +597,44 @@ impl Iterator for BlockchainIter {
     }
 }
 
+/// Location of every ballot on the chain, so lookups needn't read each block
+#[derive(Default, Clone)]
+struct BallotIndex {
+    // Block index and position within the block, by content id
+    locations: HashMap<Hash, (u32, usize)>,
+    // Content ids of every ballot cast by each key
+    signers: HashMap<VerifyingKey, Vec<Hash>>,
+}
+
+impl BallotIndex {
+    /// Record where each ballot in the block at `index` sits
+    fn insert(&mut self, index: u32, block: &Block) {
+        for (position, ballot) in block.get_ballots().into_iter().flatten().enumerate() {
+            if self.locations.insert(ballot.content_id(), (index, position)).is_none() {
+                self.signers.entry(ballot.signer()).or_default().push(ballot.content_id());
+            }
+        }
+    }
+
+    /// Forget ballots in blocks after `index`
+    fn truncate(&mut self, index: u32) {
+        self.locations.retain(|_, (block_index, _)| *block_index <= index);
+        let locations = &self.locations;
+        self.signers.retain(|_, content_ids| {
+            content_ids.retain(|content_id| locations.contains_key(content_id));
+            return !content_ids.is_empty();
+        });
+    }
+
+    fn locate(&self, content_id: &Hash) -> Option<(u32, usize)> {
+        return self.locations.get(content_id).copied();
+    }
+
+    fn signed_by(&self, signer: &VerifyingKey) -> impl Iterator<Item = &Hash> {
+        return self.signers.get(signer).into_iter().flatten();
+    }
+}
+
 /// Validate if a vector of blocks represents a valid sequence
 pub fn is_valid_chain(blocks: &Vec<Block>) -> bool {
     if blocks.is_empty() || blocks.len() == 1 {
@@ -290,11 +652,92 @@ pub fn is_valid_chain(blocks: &Vec<Block>) -> bool {
 
 #[cfg(test)]
 mod tests {
+    use std::{collections::HashMap, sync::{Arc, Mutex}};
+
     use ed25519_dalek::SigningKey;
 
-    use crate::votechain::block::Block;
+    use paillier::{KeyGeneration, Paillier};
+
+    use crate::votechain::{block::{ballot_leaf, test_genesis_info, Block}, errors::Error, store::{ChainStore, MemoryStore}};
+
+    use vote_lib::{genesis::GenesisInfo, merkle::{self, MerkleProof}, receipt::ReceiptError, trustee::TrusteeKey, Ballot, Signed};
+
+    use super::{is_valid_chain, Blockchain, ChainUpdate};
+
+    fn test_chain(sk: &SigningKey) -> Blockchain {
+        return Blockchain::in_memory(test_genesis_info(), sk).unwrap();
+    }
+
+    #[test]
+    fn stored_chain_for_another_genesis_rejected() {
+        let sk = SigningKey::from_bytes(&[0; 32]);
+        let mut store = MemoryStore::new();
+        let other = GenesisInfo::new("other".into(), test_genesis_info().trustee_key);
+        store.put_block(1, Block::genesis(other)).unwrap();
+
+        assert!(matches!(Blockchain::with_store(Box::new(store), test_genesis_info(), &sk), Err(Error::GenesisMismatch)));
+    }
+
+    #[test]
+    fn ballots_under_another_trustee_key_rejected() {
+        let mut sk = SigningKey::from_bytes(&[0; 32]);
+        let mut chain = test_chain(&sk);
+        let (other_ek, _dk) = Paillier::keypair().keys();
+        let ballot = Signed::new(&sk, Ballot::new(&other_ek, true, "test".into(), chain.genesis_hash()));
+
+        let block = Block::new(&mut sk, &chain.get_block(&1).unwrap(), vec![ballot]).unwrap();
+        assert!(matches!(chain.append(block), Err(Error::InvalidNewBlock)));
+        assert_eq!(chain.get_height(), 1);
+    }
+
+    #[test]
+    fn ballots_from_another_chain_for_the_issue_rejected() {
+        let mut sk = SigningKey::from_bytes(&[0; 32]);
+        let mut chain = test_chain(&sk);
+        let ek = chain.trustee_key().clone();
+
+        // Same issue and trustee key, but a genesis of its own
+        let relabelled = Signed::new(&SigningKey::from_bytes(&[3; 32]), TrusteeKey::new("other".into(), ek.clone()));
+        let mut other = Blockchain::in_memory(GenesisInfo::new("test".into(), relabelled), &sk).unwrap();
+        assert_ne!(chain.genesis_hash(), other.genesis_hash());
+
+        let ballot = Signed::new(&SigningKey::from_bytes(&[1; 32]), Ballot::new(&ek, true, "test".into(), chain.genesis_hash()));
+        let replayed = Block::new(&mut sk, &other.get_block(&1).unwrap(), vec![ballot.clone()]).unwrap();
+        assert!(matches!(other.append(replayed), Err(Error::InvalidNewBlock)));
+        assert_eq!(other.get_height(), 1);
 
-    use super::is_valid_chain;
+        let block = Block::new(&mut sk, &chain.get_block(&1).unwrap(), vec![ballot]).unwrap();
+        chain.append(block).unwrap();
+    }
+
+    #[test]
+    fn bad_signature_in_later_synced_block_reported() {
+        let mut sk = SigningKey::from_bytes(&[0; 32]);
+        let mut chain = test_chain(&sk);
+        let ek = chain.trustee_key().clone();
+        let genesis_hash = chain.genesis_hash();
+        let genesis = chain.get_block(&1).unwrap();
+
+        // An otherwise well formed ballot with its signature corrupted
+        let mut bytes = bincode::serialize(&Signed::new(&sk, Ballot::new(&ek, true, "test".into(), genesis_hash))).unwrap();
+        bytes[0] ^= 1;
+        let forged: Signed<Ballot> = bincode::deserialize(&bytes).unwrap();
+
+        let first = Block::new(&mut sk, &genesis, vec![Signed::new(&sk, Ballot::new(&ek, false, "test".into(), genesis_hash))]).unwrap();
+        let second = Block::new(&mut sk, &first, vec![forged]).unwrap();
+
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::fmt().with_writer(logs.clone()).with_ansi(false).finish();
+        let valid = tracing::subscriber::with_default(subscriber, || chain.ballots_valid(&[first.clone(), second.clone()]));
+
+        assert!(!valid);
+        let logs = String::from_utf8(logs.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains(&second.hash().to_string()));
+        assert!(!logs.contains(&first.hash().to_string()));
+
+        assert!(matches!(chain.try_update_longest(1, vec![genesis, first, second]), Err(Error::InvalidNewBlock)));
+        assert_eq!(chain.get_height(), 1);
+    }
 
     #[test]
     fn empty_chain_valid() {
@@ -304,7 +747,7 @@ mod tests {
     #[test]
     fn valid_chain_validates() {
         let mut sk = SigningKey::from_bytes(&[0; 32]);
-        let genesis = Block::genesis();
+        let genesis = Block::genesis(test_genesis_info());
         let block1 = Block::new(&mut sk, &genesis, Vec::new()).unwrap();
         let block2 = Block::new(&mut sk, &block1, Vec::new()).unwrap();
 
@@ -316,7 +759,7 @@ mod tests {
     #[test]
     fn invalid_chain_fails() {
         let mut sk = SigningKey::from_bytes(&[0; 32]);
-        let genesis = Block::genesis();
+        let genesis = Block::genesis(test_genesis_info());
         let block1 = Block::new(&mut sk, &genesis, Vec::new()).unwrap();
         let block2 = Block::new(&mut sk, &genesis, Vec::new()).unwrap();
 
@@ -324,4 +767,172 @@ mod tests {
 
         assert!(!is_valid_chain(&chain))
     }
+
+    #[test]
+    fn new_chain_has_genesis() {
+        let chain = test_chain(&SigningKey::from_bytes(&[0; 32]));
+
+        assert_eq!(chain.get_height(), 1);
+        assert_eq!(chain.get_hash_at(1).unwrap(), Block::genesis(test_genesis_info()).hash());
+        assert_eq!(chain.genesis_hash(), chain.get_hash_at(1).unwrap());
+    }
+
+    #[test]
+    fn append_extends_head() {
+        let mut sk = SigningKey::from_bytes(&[0; 32]);
+        let mut chain = test_chain(&sk);
+        let block = Block::new(&mut sk, &chain.get_block(&1).unwrap(), Vec::new()).unwrap();
+
+        chain.append(block.clone()).unwrap();
+
+        assert_eq!(chain.get_height(), 2);
+        assert!(matches!(chain.append(block), Err(Error::InvalidNewBlock)));
+    }
+
+    #[test]
+    fn longer_fork_replaces_chain() {
+        let mut sk = SigningKey::from_bytes(&[0; 32]);
+        let mut chain = test_chain(&sk);
+        let genesis = chain.get_block(&1).unwrap();
+        chain.append(Block::new(&mut sk, &genesis, Vec::new()).unwrap()).unwrap();
+
+        let fork1 = Block::new(&mut sk, &genesis, Vec::new()).unwrap();
+        let fork2 = Block::new(&mut sk, &fork1, Vec::new()).unwrap();
+        let update = chain.try_update_longest(1, vec![genesis, fork1, fork2.clone()]).unwrap();
+
+        assert_eq!(update, ChainUpdate { new_height: 3, blocks_applied: 2, ballots_returned: 0 });
+        assert_eq!(chain.get_height(), 3);
+        assert_eq!(chain.get_hash_at(3).unwrap(), fork2.hash());
+    }
+
+    #[test]
+    fn fork_returns_ballots_from_replaced_blocks_only() {
+        // Everything after the fork block is stripped, the fork block itself is kept
+        let mut sk = SigningKey::from_bytes(&[0; 32]);
+        let mut chain = test_chain(&sk);
+        let ek = chain.trustee_key().clone();
+        let genesis_hash = chain.genesis_hash();
+        let genesis = chain.get_block(&1).unwrap();
+        let kept = Block::new(&mut sk, &genesis, vec![Signed::new(&sk, Ballot::new(&ek, true, "test".into(), genesis_hash))]).unwrap();
+        chain.append(kept.clone()).unwrap();
+        let replaced = Signed::new(&sk, Ballot::new(&ek, false, "test".into(), genesis_hash));
+        chain.append(Block::new(&mut sk, &kept, vec![replaced.clone()]).unwrap()).unwrap();
+
+        let fork1 = Block::new(&mut sk, &kept, Vec::new()).unwrap();
+        let fork2 = Block::new(&mut sk, &fork1, Vec::new()).unwrap();
+        let update = chain.try_update_longest(2, vec![kept, fork1, fork2]).unwrap();
+
+        assert_eq!(update.ballots_returned, 1);
+        assert_eq!(chain.pooled_ballots().len(), 1);
+        assert!(chain.is_pooled(&replaced.content_id()));
+        assert!(chain.ballot_inclusion(&replaced.content_id()).is_none());
+    }
+
+    #[test]
+    fn pooled_ballots_are_found_once_included() {
+        let sk = SigningKey::from_bytes(&[0; 32]);
+        let mut chain = test_chain(&sk);
+        let ek = chain.trustee_key().clone();
+        let genesis_hash = chain.genesis_hash();
+        let first = Signed::new(&sk, Ballot::new(&ek, true, "test".into(), genesis_hash));
+        let second = Signed::new(&sk, Ballot::new(&ek, false, "test".into(), genesis_hash));
+
+        chain.pool_ballot(first.clone()).unwrap();
+        assert!(chain.is_pooled(&first.content_id()));
+        assert!(chain.ballot_inclusion(&first.content_id()).is_none());
+
+        chain.pool_ballot(second).unwrap();
+        let inclusion = chain.ballot_inclusion(&first.content_id()).unwrap();
+        assert!(!chain.is_pooled(&first.content_id()));
+        assert_eq!((inclusion.block_index, inclusion.depth), (2, 1));
+        assert!(inclusion.proves(&first));
+    }
+
+    #[test]
+    fn receipts_verify_and_report_newer_ballots() {
+        let sk = SigningKey::from_bytes(&[0; 32]);
+        let mut chain = test_chain(&sk);
+        let ek = chain.trustee_key().clone();
+        let genesis_hash = chain.genesis_hash();
+        let first = Signed::new(&sk, Ballot::new(&ek, true, "test".into(), genesis_hash));
+        let other_voter = Signed::new(&SigningKey::from_bytes(&[1; 32]), Ballot::new(&ek, true, "test".into(), genesis_hash));
+
+        chain.pool_ballot(first.clone()).unwrap();
+        chain.pool_ballot(other_voter).unwrap();
+        let mut block_sk = sk.clone();
+        chain.append(Block::new(&mut block_sk, &chain.get_block(&2).unwrap(), Vec::new()).unwrap()).unwrap();
+
+        let genesis = chain.get_hash_at(1).unwrap();
+        let receipt = chain.ballot_receipt(&first.content_id()).unwrap().unwrap();
+        assert_eq!(receipt.verify(&first.content_id(), "test", &genesis, &[sk.verifying_key()]), Ok(2));
+        assert!(receipt.superseding().is_none());
+
+        // Headers must descend from the pinned genesis and be signed by a known authority
+        let stranger = SigningKey::from_bytes(&[2; 32]).verifying_key();
+        assert_eq!(receipt.verify(&first.content_id(), "test", &chain.get_hash_at(2).unwrap(), &[sk.verifying_key()]), Err(ReceiptError::GenesisMismatch));
+        assert_eq!(receipt.verify(&first.content_id(), "test", &genesis, &[stranger]), Err(ReceiptError::UnknownSignatory(2)));
+
+        let newer = Signed::new(&sk, Ballot::new(&ek, false, "test".into(), genesis_hash));
+        chain.pool_ballot(newer.clone()).unwrap();
+        let receipt = chain.ballot_receipt(&first.content_id()).unwrap().unwrap();
+        assert_eq!(receipt.superseding().unwrap().content_id(), newer.content_id());
+        assert!(receipt.superseded_by.unwrap().inclusion.is_none());
+
+        // Once included, the newer ballot is found through the signer index
+        chain.pool_ballot(Signed::new(&SigningKey::from_bytes(&[1; 32]), Ballot::new(&ek, false, "test".into(), genesis_hash))).unwrap();
+        let receipt = chain.ballot_receipt(&first.content_id()).unwrap().unwrap();
+        assert_eq!(receipt.superseded_by.unwrap().inclusion.unwrap().block_index, 4);
+    }
+
+    #[test]
+    fn receipt_with_reused_signature_rejected() {
+        let sk = SigningKey::from_bytes(&[0; 32]);
+        let mut chain = test_chain(&sk);
+        let ek = chain.trustee_key().clone();
+        let genesis_hash = chain.genesis_hash();
+        let cast = Signed::new(&sk, Ballot::new(&ek, true, "test".into(), genesis_hash));
+        chain.pool_ballot(cast.clone()).unwrap();
+        chain.pool_ballot(Signed::new(&SigningKey::from_bytes(&[1; 32]), Ballot::new(&ek, true, "test".into(), genesis_hash))).unwrap();
+
+        // Keep the authority's signature and nonce on block 2, but swap in a root over a ballot never cast there
+        let forged = Signed::new(&SigningKey::from_bytes(&[2; 32]), Ballot::new(&ek, false, "test".into(), genesis_hash));
+        let leaves = vec![ballot_leaf(&forged)];
+        let mut receipt = chain.ballot_receipt(&cast.content_id()).unwrap().unwrap();
+        receipt.headers.truncate(2);
+        receipt.headers[1].ballot_root = merkle::root(&leaves);
+        receipt.ballot = forged.clone();
+        receipt.inclusion.block_hash = receipt.headers[1].hash();
+        receipt.inclusion.ballot_root = receipt.headers[1].ballot_root;
+        receipt.inclusion.proof = MerkleProof::new(&leaves, 0).unwrap();
+
+        let genesis = chain.get_hash_at(1).unwrap();
+        assert_eq!(receipt.verify(&forged.content_id(), "test", &genesis, &[sk.verifying_key()]), Err(ReceiptError::InvalidSignatory(2)));
+    }
+
+    #[test]
+    fn sealed_chain_prunes_and_bootstraps() {
+        let mut sk = SigningKey::from_bytes(&[0; 32]);
+        let mut chain = test_chain(&sk);
+        let genesis = chain.get_block(&1).unwrap();
+        chain.append(Block::new(&mut sk, &genesis, Vec::new()).unwrap()).unwrap();
+
+        assert!(matches!(chain.export_snapshot(&HashMap::new(), None), Err(Error::ChainNotSealed)));
+        chain.seal().unwrap();
+
+        let snapshot = chain.export_snapshot(&HashMap::new(), Some(true)).unwrap();
+        chain.prune(snapshot.clone()).unwrap();
+
+        assert_eq!(chain.get_height(), 3);
+        assert!(matches!(chain.get_block(&2), Err(Error::BlockPruned(2))));
+
+        // Snapshots are only trusted from configured authorities
+        let mut stranger = test_chain(&SigningKey::from_bytes(&[1; 32]));
+        assert!(matches!(stranger.bootstrap(snapshot.clone(), None), Err(Error::InvalidSnapshot(_))));
+
+        let mut fresh = test_chain(&sk);
+        fresh.bootstrap(snapshot, None).unwrap();
+
+        assert_eq!(fresh.get_height(), 3);
+        assert!(fresh.is_sealed());
+    }
 }
@@ -1,7 +1,40 @@
 use confique::Config;
+use ed25519_dalek::VerifyingKey;
+use serde::Serialize;
 
-#[derive(Config)]
+#[derive(Config, Serialize, Clone)]
 pub struct BlockchainConfig {
-    #[config(default = "./temp/blockchains/solochain")]
-    pub path: String
-}
\ No newline at end of file
+    /// Directory holding one LMDB database per issue chain
+    #[config(default = "./temp/blockchains/solochain", env = "PNYX_DATA_PATH")]
+    pub path: String,
+
+    /// Initial size of the LMDB memory map for each chain, in bytes
+    #[config(default = 10485760)]
+    pub map_size: usize,
+
+    /// Largest size the memory map may grow to once full, in bytes
+    #[config(default = 1073741824)]
+    pub max_map_size: usize,
+
+    /// Pooled ballots needed before a new block is appended
+    #[config(default = 2)]
+    pub block_size: usize,
+
+    /// Hex encoded keys of nodes trusted to sign snapshots, besides this node's own key
+    #[config(default = [])]
+    pub authorities: Vec<String>,
+}
+
+impl BlockchainConfig {
+    /// The configured authorities, failing on the first entry which isn't an ed25519 public key
+    pub fn authority_keys(&self) -> Result<Vec<VerifyingKey>, String> {
+        return self.authorities.iter()
+            .map(|key| {
+                let bytes: [u8; 32] = hex::decode(key).ok()
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .ok_or(format!("'{key}' is not a hex encoded public key"))?;
+                return VerifyingKey::from_bytes(&bytes).map_err(|e| e.to_string());
+            })
+            .collect();
+    }
+}
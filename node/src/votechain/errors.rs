@@ -1,11 +1,26 @@
 use std::{fmt::Display, io};
 
+use vote_lib::wire::WireError;
+
 #[derive(Debug)]
 pub enum Error {
     Heed(heed::Error),
     Io(io::Error),
+    Bincode(bincode::Error),
+    Wire(WireError),
+    StorageFull(usize),
+    /// The store's environment was closed to grow it and could not be reopened
+    StorageUnavailable,
+    InvalidConfig(&'static str),
     BlockNotFound(u32),
+    BlockPruned(u32),
     InvalidNewBlock,
+    ChainSealed,
+    ChainNotSealed,
+    InvalidSnapshot(&'static str),
+    GenesisMismatch,
+    /// The stored genesis block predates committing to the trustee key
+    LegacyGenesis,
 }
 
 impl Display for Error {
@@ -13,8 +28,19 @@ impl Display for Error {
         match self {
             Error::Heed(error) => write!(f, "{}", error),
             Error::Io(error) => write!(f, "{}", error),
+            Error::Bincode(error) => write!(f, "{}", error),
+            Error::Wire(error) => write!(f, "{}", error),
+            Error::StorageFull(map_size) => write!(f, "Chain storage is full at the maximum map size of {} bytes", map_size),
+            Error::StorageUnavailable => write!(f, "Chain storage could not be reopened after growing"),
+            Error::InvalidConfig(reason) => write!(f, "Invalid chain configuration: {}", reason),
             Error::BlockNotFound(index) => write!(f, "No block found at index {}", index),
+            Error::BlockPruned(index) => write!(f, "Block at index {} has been pruned into a snapshot", index),
             Error::InvalidNewBlock => write!(f, "Provided block failed to validate"),
+            Error::ChainSealed => write!(f, "Chain has been sealed and cannot be modified"),
+            Error::ChainNotSealed => write!(f, "Chain must be sealed before it can be snapshotted"),
+            Error::InvalidSnapshot(reason) => write!(f, "Snapshot failed to validate: {}", reason),
+            Error::GenesisMismatch => write!(f, "Stored chain was opened under a different issue or trustee key"),
+            Error::LegacyGenesis => write!(f, "Stored chain's genesis block predates trustee key commitments and can't be migrated, remove its directory to start the chain afresh"),
         }
     }
 }
@@ -31,4 +57,16 @@ impl From<io::Error> for Error {
     fn from(error: io::Error) -> Error {
         Error::Io(error)
     }
-}
\ No newline at end of file
+}
+
+impl From<bincode::Error> for Error {
+    fn from(error: bincode::Error) -> Error {
+        Error::Bincode(error)
+    }
+}
+
+impl From<WireError> for Error {
+    fn from(error: WireError) -> Error {
+        Error::Wire(error)
+    }
+}
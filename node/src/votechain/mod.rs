@@ -1,4 +1,6 @@
 pub mod chain;
 pub mod block;
 pub mod errors;
-pub mod config;
\ No newline at end of file
+pub mod config;
+pub mod snapshot;
+pub mod store;
\ No newline at end of file
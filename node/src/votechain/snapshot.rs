@@ -0,0 +1,368 @@
+// Compact snapshots of sealed chains
+//
+// Once an issue has been sealed, the ballot history is only needed to re-derive the tally.
+// A snapshot keeps the final aggregated ciphertexts, the result and enough hashing to
+// tie it back to the original chain, allowing nodes to prune ballots and bootstrap quickly.
+
+use std::{collections::{HashMap, HashSet}, fs, path::Path};
+
+use blake3::Hash;
+use curv::BigInt;
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use paillier::DecryptionKey;
+use serde::{Deserialize, Serialize};
+use vote_lib::{merkle::{self, MerkleProof}, wire, Ballot, Signable, Signed, SigningContext};
+
+use crate::trustee::resolve;
+
+use super::{block::{ballot_leaf, Block, BlockHeader}, errors::Error};
+
+/// Store metadata key holding the snapshot of a pruned chain
+pub const SNAPSHOT_KEY: &str = "snapshot";
+
+/// The ballot counted for one voter, with proof it was included in the snapshotted chain
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CountedBallot {
+    pub ballot: Signed<Ballot>,
+    /// Voting power after delegation, as resolved by the exporting node
+    pub weight: u64,
+    /// Index of the block holding the ballot
+    pub block_index: u32,
+    pub proof: MerkleProof,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Snapshot {
+    pub issue_id: String,
+    /// Hash of the chain's genesis block, binding the signature to this chain
+    pub genesis: Hash,
+    /// Height of the sealed chain, including the seal block
+    pub height: u32,
+    /// Header of every block in the chain, in order from genesis to seal
+    pub headers: Vec<BlockHeader>,
+    /// Merkle root over the hash of every block
+    pub chain_root: Hash,
+    /// Latest ballot of every voter, which the tally sums
+    pub counted: Vec<CountedBallot>,
+    /// Final homomorphically aggregated ciphertexts
+    pub tally_for: BigInt,
+    pub tally_against: BigInt,
+    /// Decrypted outcome, if the exporting node held the decryption key
+    pub result: Option<bool>,
+    /// The signed seal block closing the chain
+    pub seal: Block,
+}
+
+impl Signable for Snapshot {
+    const TAG: u8 = 3;
+    const DOMAIN: &'static str = "snapshot";
+
+    fn context(&self) -> SigningContext<'_> {
+        return SigningContext::Issue { issue_id: &self.issue_id, genesis: &self.genesis };
+    }
+}
+
+impl Snapshot {
+    /// Build a snapshot from the full sequence of blocks of a sealed chain, counting each
+    /// voter's latest ballot under the resolved `weights`
+    pub fn from_blocks(issue_id: String, blocks: &[Block], weights: &HashMap<VerifyingKey, u64>, result: Option<bool>) -> Result<Self, Error> {
+        let seal = match blocks.last() {
+            Some(block) if block.is_seal() => block.clone(),
+            _ => return Err(Error::ChainNotSealed),
+        };
+        let Some(info) = blocks[0].genesis_info() else {
+            return Err(Error::InvalidSnapshot("expected a genesis block"));
+        };
+
+        // Later ballots replace earlier ones from the same voter, matching the tally
+        let mut latest: HashMap<VerifyingKey, CountedBallot> = HashMap::new();
+        for (index, block) in (1..).zip(blocks) {
+            for (position, ballot) in block.get_ballots().into_iter().flatten().enumerate() {
+                if latest.get(&ballot.signer()).is_some_and(|counted| ballot.timestamp() <= counted.ballot.timestamp()) {
+                    continue;
+                }
+                let Some(proof) = block.ballot_proof(position) else {
+                    return Err(Error::InvalidSnapshot("no inclusion proof for a ballot in its block"));
+                };
+                latest.insert(ballot.signer(), CountedBallot {
+                    ballot: ballot.clone(),
+                    weight: weights.get(&ballot.signer()).copied().unwrap_or(1),
+                    block_index: index,
+                    proof,
+                });
+            }
+        }
+        let counted: Vec<CountedBallot> = latest.into_values().collect();
+
+        let headers: Vec<BlockHeader> = blocks.iter().map(|block| block.header()).collect();
+        let block_hashes: Vec<Hash> = headers.iter().map(|header| header.hash()).collect();
+        let (tally_for, tally_against) = resolve::tally(info.encryption_key(), counted.iter().map(|counted| (&*counted.ballot, counted.weight)));
+
+        return Ok(Self {
+            issue_id,
+            genesis: block_hashes[0],
+            height: blocks.len() as u32,
+            chain_root: merkle::root(&block_hashes),
+            headers,
+            counted,
+            tally_for,
+            tally_against,
+            result,
+            seal,
+        })
+    }
+
+    /// Check the snapshot is internally consistent and extends the expected genesis block:
+    /// every header links to the last, and the tally sums ballots included under those headers.
+    ///
+    /// Voter weights come from the exporting node's delegation graph and aren't committed to by
+    /// the chain, nor is the census, so a voter left out or given another weight isn't caught here.
+    /// Those rest on the exporting authority's signature alone, checked by `verify_signed`
+    pub fn verify(&self, genesis: &Block) -> Result<(), Error> {
+        let Some(info) = genesis.genesis_info() else {
+            return Err(Error::InvalidSnapshot("expected a genesis block"));
+        };
+        if self.issue_id != info.issue_id {
+            return Err(Error::InvalidSnapshot("snapshot is for a different issue"));
+        }
+
+        if self.headers.len() != self.height as usize || self.height < 2 {
+            return Err(Error::InvalidSnapshot("height does not match headers"));
+        }
+
+        if self.headers[0] != genesis.header() || self.genesis != genesis.hash() {
+            return Err(Error::InvalidSnapshot("genesis block mismatch"));
+        }
+
+        for pair in self.headers.windows(2) {
+            if !pair[1].extends(&pair[0]) {
+                return Err(Error::InvalidSnapshot("headers do not link"));
+            }
+            if !pair[1].signature_valid() {
+                return Err(Error::InvalidSnapshot("block signature invalid"));
+            }
+        }
+
+        if merkle::root(&self.block_hashes()) != self.chain_root {
+            return Err(Error::InvalidSnapshot("chain root mismatch"));
+        }
+
+        if !self.seal.is_seal() || &self.seal.header() != self.headers.last().unwrap() {
+            return Err(Error::InvalidSnapshot("seal does not close the chain"));
+        }
+
+        self.verify_tally(info.encryption_key())?;
+
+        return Ok(());
+    }
+
+    /// Check each counted ballot is a valid ballot for this issue, included in a block of the
+    /// chain, with at most one per voter, and that the tally is their weighted sum
+    fn verify_tally(&self, ek: &paillier::EncryptionKey) -> Result<(), Error> {
+        let ballots: Vec<&Signed<Ballot>> = self.counted.iter().map(|counted| &counted.ballot).collect();
+        if ballots.iter().any(|ballot| ballot.signing_retired()) {
+            return Err(Error::InvalidSnapshot("counted ballot signed under a retired signing version"));
+        }
+        if vote_lib::verify_batch(&ballots, &SigningContext::Issue { issue_id: &self.issue_id, genesis: &self.genesis }).is_err() {
+            return Err(Error::InvalidSnapshot("counted ballot signature invalid"));
+        }
+
+        let mut voters = HashSet::new();
+        for counted in &self.counted {
+            // Ballots can only sit between the genesis and seal blocks
+            let header = match counted.block_index {
+                index if index > 1 && index < self.height => &self.headers[index as usize - 1],
+                _ => return Err(Error::InvalidSnapshot("counted ballot outside the chain")),
+            };
+            if !counted.proof.verify(&ballot_leaf(&counted.ballot), &header.ballot_root) {
+                return Err(Error::InvalidSnapshot("counted ballot not included in its block"));
+            }
+            if !voters.insert(counted.ballot.signer()) {
+                return Err(Error::InvalidSnapshot("voter counted more than once"));
+            }
+            if counted.ballot.issue_id() != self.issue_id || !counted.ballot.validate_proofs(ek) {
+                return Err(Error::InvalidSnapshot("counted ballot invalid"));
+            }
+        }
+
+        let tally = resolve::tally(ek, self.counted.iter().map(|counted| (&*counted.ballot, counted.weight)));
+        if tally != (self.tally_for.clone(), self.tally_against.clone()) {
+            return Err(Error::InvalidSnapshot("tally does not match counted ballots"));
+        }
+
+        return Ok(());
+    }
+
+    /// Whether the claimed result matches the decrypted tally. Without the decryption key the
+    /// result rests on the exporting authority's signature
+    pub fn result_valid(&self, dk: &DecryptionKey) -> bool {
+        match self.result {
+            Some(result) => return resolve::decide(dk, (self.tally_for.clone(), self.tally_against.clone())) == result,
+            None => return true,
+        }
+    }
+
+    pub fn block_hashes(&self) -> Vec<Hash> {
+        return self.headers.iter().map(|header| header.hash()).collect();
+    }
+
+    pub fn head_hash(&self) -> Hash {
+        return self.headers[self.headers.len() - 1].hash();
+    }
+
+    pub fn sign(self, sk: &SigningKey) -> Signed<Snapshot> {
+        return Signed::new(sk, self);
+    }
+}
+
+/// Verify the exporting node is one of the trusted `authorities` and signed the snapshot,
+/// then the snapshot contents
+pub fn verify_signed(snapshot: &Signed<Snapshot>, genesis: &Block, authorities: &[VerifyingKey]) -> Result<(), Error> {
+    let Some(info) = genesis.genesis_info() else {
+        return Err(Error::InvalidSnapshot("expected a genesis block"));
+    };
+    if !authorities.contains(&snapshot.signer()) {
+        return Err(Error::InvalidSnapshot("snapshot signed by an unknown authority"));
+    }
+    if snapshot.signing_retired() {
+        return Err(Error::InvalidSnapshot("snapshot signed under a retired signing version"));
+    }
+    if !snapshot.signature_valid(&SigningContext::Issue { issue_id: &info.issue_id, genesis: &genesis.hash() }) {
+        return Err(Error::InvalidSnapshot("snapshot signature invalid"));
+    }
+
+    return snapshot.verify(genesis);
+}
+
+pub fn write_to(snapshot: &Signed<Snapshot>, path: impl AsRef<Path>) -> Result<(), Error> {
+    fs::write(path, wire::encode(snapshot))?;
+    return Ok(());
+}
+
+pub fn read_from(path: impl AsRef<Path>) -> Result<Signed<Snapshot>, Error> {
+    return Ok(wire::decode(&fs::read(path)?)?);
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+
+    use vote_lib::{signing_message, SIGNING_VERSION};
+
+    use crate::votechain::block::test_genesis_info;
+
+    use super::*;
+
+    fn sealed_chain(sk: &mut SigningKey) -> Vec<Block> {
+        let info = test_genesis_info();
+        let ballots = vec![
+            Signed::new(&SigningKey::generate(&mut OsRng), Ballot::new(info.encryption_key(), true, "test".into(), info.hash())),
+            Signed::new(&SigningKey::generate(&mut OsRng), Ballot::new(info.encryption_key(), false, "test".into(), info.hash())),
+        ];
+        let genesis = Block::genesis(info);
+        let block = Block::new(sk, &genesis, ballots).unwrap();
+        let seal = Block::seal(sk, &block);
+
+        return vec![genesis, block, seal];
+    }
+
+    fn unsigned(blocks: &[Block]) -> Snapshot {
+        return Snapshot::from_blocks("test".into(), blocks, &HashMap::new(), None).unwrap();
+    }
+
+    #[test]
+    fn snapshot_verifies() {
+        let mut sk = SigningKey::generate(&mut OsRng);
+        let blocks = sealed_chain(&mut sk);
+        let snapshot = unsigned(&blocks).sign(&sk);
+
+        assert_eq!(snapshot.counted.len(), 2);
+        assert!(verify_signed(&snapshot, &blocks[0], &[sk.verifying_key()]).is_ok())
+    }
+
+    #[test]
+    fn golden_framing() {
+        let mut sk = SigningKey::from_bytes(&[7; 32]);
+        let blocks = sealed_chain(&mut sk);
+        let snapshot = unsigned(&blocks).sign(&sk);
+        let data = bincode::serialize(&*snapshot).unwrap();
+
+        let message = signing_message(SIGNING_VERSION, &snapshot.context(), &*snapshot);
+        let context = format!("{}{}", "706e79782d7369676e65642f736e617073686f7400010301040000000000000074657374", blocks[0].hash().to_hex());
+        assert_eq!(hex::encode(&message[..message.len() - data.len()]), context);
+        // Issue id, then genesis hash
+        assert!(hex::encode(&data).starts_with("040000000000000074657374"));
+
+        let encoded = wire::encode(&snapshot);
+        assert_eq!(hex::encode(&encoded[..4]), "706e0103");
+        assert_eq!(&encoded[encoded.len() - data.len() - 1..], [data, vec![SIGNING_VERSION]].concat());
+    }
+
+    #[test]
+    fn unknown_authority_rejected() {
+        let mut sk = SigningKey::generate(&mut OsRng);
+        let blocks = sealed_chain(&mut sk);
+        let snapshot = unsigned(&blocks).sign(&sk);
+
+        let other = SigningKey::generate(&mut OsRng).verifying_key();
+        assert!(verify_signed(&snapshot, &blocks[0], &[other]).is_err())
+    }
+
+    #[test]
+    fn legacy_signature_rejected() {
+        use ed25519_dalek::Signer;
+
+        let mut sk = SigningKey::generate(&mut OsRng);
+        let blocks = sealed_chain(&mut sk);
+        let snapshot = unsigned(&blocks);
+
+        // Bare bincode signed over the data alone, which still verifies under its own rules
+        let signature = sk.sign(&bincode::serialize(&snapshot).unwrap());
+        let bytes = bincode::serialize(&(signature, sk.verifying_key(), snapshot)).unwrap();
+        let legacy = wire::decode::<Snapshot>(&bytes).unwrap();
+        assert!(legacy.signature_valid(&SigningContext::Issue { issue_id: "test", genesis: &blocks[0].hash() }));
+
+        assert!(verify_signed(&legacy, &blocks[0], &[sk.verifying_key()]).is_err())
+    }
+
+    #[test]
+    fn unsealed_chain_rejected() {
+        let mut sk = SigningKey::generate(&mut OsRng);
+        let blocks = sealed_chain(&mut sk);
+
+        assert!(Snapshot::from_blocks("test".into(), &blocks[..2], &HashMap::new(), None).is_err())
+    }
+
+    #[test]
+    fn tampered_headers_rejected() {
+        let mut sk = SigningKey::generate(&mut OsRng);
+        let blocks = sealed_chain(&mut sk);
+
+        // A substituted intermediate block breaks the link to the seal, even with a matching root
+        let mut snapshot = unsigned(&blocks);
+        snapshot.headers[1] = Block::new(&mut sk, &blocks[0], Vec::new()).unwrap().header();
+        snapshot.chain_root = merkle::root(&snapshot.block_hashes());
+        assert!(snapshot.verify(&blocks[0]).is_err());
+
+        // As does a new ballot root under the original signature, once the seal is relinked to it
+        let mut snapshot = unsigned(&blocks);
+        snapshot.headers[1].ballot_root = [1; 32].into();
+        snapshot.headers[2].previous_hash = snapshot.headers[1].hash();
+        snapshot.chain_root = merkle::root(&snapshot.block_hashes());
+        assert!(matches!(snapshot.verify(&blocks[0]), Err(Error::InvalidSnapshot("block signature invalid"))));
+    }
+
+    #[test]
+    fn tampered_tally_rejected() {
+        let mut sk = SigningKey::generate(&mut OsRng);
+        let blocks = sealed_chain(&mut sk);
+
+        let mut snapshot = unsigned(&blocks);
+        snapshot.tally_for = BigInt::from(1);
+        assert!(snapshot.verify(&blocks[0]).is_err());
+
+        let mut snapshot = unsigned(&blocks);
+        snapshot.counted.pop();
+        assert!(snapshot.verify(&blocks[0]).is_err());
+    }
+}
@@ -0,0 +1,324 @@
+use std::{fmt::Debug, fs, mem::size_of, path::{Path, PathBuf}};
+
+use heed::{types::{ByteSlice, OwnedType, SerdeBincode, Str}, Database, Env, EnvOpenOptions, MdbError, RoTxn, RwTxn};
+use tracing::info;
+
+use crate::votechain::{block::Block, config::BlockchainConfig, errors::Error};
+
+use super::{ChainStore, StoreOp};
+
+type BlockDatabase = Database<OwnedType<u32>, SerdeBincode<Block>>;
+type MetadataDatabase = Database<Str, ByteSlice>;
+
+/// Handles to the tables held within the environment
+#[derive(Clone, Copy)]
+struct Tables {
+    blocks: BlockDatabase,
+    metadata: MetadataDatabase,
+}
+
+/// LMDB environment holding the blocks of a single chain
+/// The memory map starts small and is grown whenever a write fills it
+pub struct LmdbStore {
+    path: PathBuf,
+    map_size: usize,
+    max_map_size: usize,
+
+    // Only `None` while the environment is being reopened with a larger map
+    env: Option<Env>,
+    tables: Tables,
+}
+
+impl LmdbStore {
+    pub fn open(path: &Path, config: &BlockchainConfig) -> Result<Self, Error> {
+        if config.map_size == 0 || config.map_size > config.max_map_size {
+            return Err(Error::InvalidConfig("map_size must be non-zero and no larger than max_map_size"));
+        }
+
+        fs::create_dir_all(path)?;
+        let (env, tables) = Self::open_env(path, config.map_size)?;
+
+        let mut store = Self {
+            path: path.to_path_buf(),
+            map_size: config.map_size,
+            max_map_size: config.max_map_size,
+            env: Some(env),
+            tables,
+        };
+        store.migrate_unnamed()?;
+
+        return Ok(store)
+    }
+
+    fn open_env(path: &Path, map_size: usize) -> Result<(Env, Tables), Error> {
+        let env = EnvOpenOptions::new()
+            .map_size(map_size)
+            .max_dbs(2)
+            .open(path)?;
+        let tables = Tables {
+            blocks: env.create_database(Some("blocks"))?,
+            metadata: env.create_database(Some("metadata"))?,
+        };
+
+        return Ok((env, tables))
+    }
+
+    /// Stores written before the tables were named hold their blocks in the unnamed database,
+    /// keyed by index alongside the table names. Move any found into the blocks table
+    fn migrate_unnamed(&mut self) -> Result<(), Error> {
+        let Some(unnamed) = self.env()?.open_database::<ByteSlice, ByteSlice>(None)? else {
+            return Ok(());
+        };
+        let blocks = self.tables.blocks.remap_types::<ByteSlice, ByteSlice>();
+
+        let moved = self.write_txn(|wtxn, _| {
+            let mut legacy = Vec::new();
+            for entry in unnamed.iter(wtxn)? {
+                let (key, value) = entry?;
+                // Table names never share the length of a block index
+                if key.len() == size_of::<u32>() {
+                    legacy.push((key.to_vec(), value.to_vec()));
+                }
+            }
+
+            for (key, value) in &legacy {
+                blocks.put(wtxn, key, value)?;
+                unnamed.delete(wtxn, key)?;
+            }
+            Ok(legacy.len())
+        })?;
+
+        if moved > 0 {
+            info!("Moved {} blocks from the unnamed database into the blocks table", moved);
+        }
+        return Ok(());
+    }
+
+    fn env(&self) -> Result<&Env, Error> {
+        return self.env.as_ref().ok_or(Error::StorageUnavailable);
+    }
+
+    pub fn path(&self) -> &Path {
+        return &self.path;
+    }
+
+    pub fn map_size(&self) -> usize {
+        return self.map_size;
+    }
+
+    fn read<T>(&self, op: impl FnOnce(&RoTxn, &Tables) -> Result<T, heed::Error>) -> Result<T, Error> {
+        let rtxn = self.env()?.read_txn()?;
+        return Ok(op(&rtxn, &self.tables)?);
+    }
+
+    /// Run a write operation, committing on success
+    /// If the map fills up the transaction is discarded, the map grown, and the operation retried
+    fn write_txn<T>(&mut self, mut op: impl FnMut(&mut RwTxn, &Tables) -> Result<T, heed::Error>) -> Result<T, Error> {
+        loop {
+            let result = {
+                let mut wtxn = self.env()?.write_txn()?;
+                match op(&mut wtxn, &self.tables) {
+                    Ok(value) => wtxn.commit().map(|_| value),
+                    Err(error) => Err(error),
+                }
+            };
+
+            match result {
+                Err(heed::Error::Mdb(MdbError::MapFull)) => self.grow()?,
+                other => return Ok(other?),
+            }
+        }
+    }
+
+    fn grow(&mut self) -> Result<(), Error> {
+        if self.map_size >= self.max_map_size {
+            return Err(Error::StorageFull(self.map_size));
+        }
+
+        let new_size = self.map_size.saturating_mul(2).min(self.max_map_size);
+        info!("Chain storage full, growing map from {} to {} bytes", self.map_size, new_size);
+
+        // heed will only reopen an environment once every handle to it has been dropped
+        if let Some(env) = self.env.take() {
+            env.prepare_for_closing().wait();
+        }
+
+        // Only one environment may be open on a path, so the old one can't be kept open
+        // alongside. Should the larger map fail to open, reopen at the old size instead
+        let (env, tables, result) = match Self::open_env(&self.path, new_size) {
+            Ok((env, tables)) => {
+                self.map_size = new_size;
+                (env, tables, Ok(()))
+            },
+            Err(error) => {
+                let (env, tables) = Self::open_env(&self.path, self.map_size)?;
+                (env, tables, Err(error))
+            },
+        };
+        self.env = Some(env);
+        self.tables = tables;
+
+        return result;
+    }
+}
+
+impl ChainStore for LmdbStore {
+    fn get_block(&self, index: u32) -> Result<Option<Block>, Error> {
+        return self.read(|rtxn, tables| tables.blocks.get(rtxn, &index));
+    }
+
+    fn for_each_block(&self, visit: &mut dyn FnMut(u32, Block)) -> Result<(), Error> {
+        return self.read(|rtxn, tables| {
+            for read_result in tables.blocks.iter(rtxn)? {
+                let (index, block) = read_result?;
+                visit(index, block);
+            }
+            Ok(())
+        });
+    }
+
+    fn block_count(&self) -> Result<u64, Error> {
+        return self.read(|rtxn, tables| tables.blocks.len(rtxn));
+    }
+
+    fn get_metadata(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        return self.read(|rtxn, tables| Ok(tables.metadata.get(rtxn, key)?.map(|value| value.to_vec())));
+    }
+
+    fn write(&mut self, ops: Vec<StoreOp>) -> Result<(), Error> {
+        return self.write_txn(|wtxn, tables| {
+            for op in &ops {
+                match op {
+                    StoreOp::PutBlock(index, block) => tables.blocks.put(wtxn, index, block)?,
+                    StoreOp::DeleteBlock(index) => { tables.blocks.delete(wtxn, index)?; },
+                    StoreOp::ClearBlocks => tables.blocks.clear(wtxn)?,
+                    StoreOp::PutMetadata(key, value) => tables.metadata.put(wtxn, key, value)?,
+                    StoreOp::DeleteMetadata(key) => { tables.metadata.delete(wtxn, key)?; },
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+impl Debug for LmdbStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LmdbStore")
+            .field("path", &self.path)
+            .field("map_size", &self.map_size)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::votechain::block::test_genesis_info;
+
+    use super::*;
+
+    fn test_config(dir: &Path, map_size: usize, max_map_size: usize) -> BlockchainConfig {
+        return BlockchainConfig {
+            path: dir.to_string_lossy().into(),
+            map_size,
+            max_map_size,
+            block_size: 2,
+            authorities: Vec::new(),
+        };
+    }
+
+    #[test]
+    fn grows_when_full() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config(dir.path(), 64 * 1024, 64 * 1024 * 1024);
+        let mut store = LmdbStore::open(dir.path(), &config).unwrap();
+        let block = Block::genesis(test_genesis_info());
+
+        for index in 1..2000 {
+            store.put_block(index, block.clone()).unwrap();
+        }
+
+        assert!(store.map_size() > config.map_size);
+        assert_eq!(store.block_count().unwrap(), 1999);
+    }
+
+    #[test]
+    fn full_at_max_size_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config(dir.path(), 64 * 1024, 64 * 1024);
+        let mut store = LmdbStore::open(dir.path(), &config).unwrap();
+        let block = Block::genesis(test_genesis_info());
+
+        let result = (1..2000).try_for_each(|index| store.put_block(index, block.clone()));
+
+        assert!(matches!(result, Err(Error::StorageFull(_))));
+    }
+
+    #[test]
+    fn migrates_blocks_from_unnamed_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config(dir.path(), 1024 * 1024, 1024 * 1024);
+        let block = Block::genesis(test_genesis_info());
+
+        // Lay the chain out as stores did before the tables were named
+        let env = EnvOpenOptions::new().map_size(config.map_size).open(dir.path()).unwrap();
+        let unnamed: BlockDatabase = env.create_database(None).unwrap();
+        let mut wtxn = env.write_txn().unwrap();
+        unnamed.put(&mut wtxn, &1, &block).unwrap();
+        unnamed.put(&mut wtxn, &2, &block).unwrap();
+        wtxn.commit().unwrap();
+        env.prepare_for_closing().wait();
+
+        let store = LmdbStore::open(dir.path(), &config).unwrap();
+
+        assert_eq!(store.block_count().unwrap(), 2);
+        assert_eq!(store.get_block(2).unwrap().unwrap().hash(), block.hash());
+    }
+
+    #[test]
+    fn legacy_genesis_reported() {
+        use ed25519_dalek::{Signature, SigningKey, VerifyingKey};
+        use serde::Serialize;
+
+        use crate::votechain::chain::Blockchain;
+
+        // Blocks as laid out when the genesis block held only the issue id
+        #[derive(Serialize)]
+        enum LegacyData { Genesis(String) }
+        #[derive(Serialize)]
+        struct LegacyBlock { timestamp: u128, previous_hash: blake3::Hash, signatory: VerifyingKey, signature: Signature, data: LegacyData, nonce: [u8; 8] }
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config(dir.path(), 1024 * 1024, 1024 * 1024);
+        let sk = SigningKey::from_bytes(&[0; 32]);
+        let legacy = LegacyBlock {
+            timestamp: 0,
+            previous_hash: [0; 32].into(),
+            signatory: sk.verifying_key(),
+            signature: Signature::from_bytes(&[0; 64]),
+            data: LegacyData::Genesis("test".into()),
+            nonce: [0; 8],
+        };
+
+        let env = EnvOpenOptions::new().map_size(config.map_size).max_dbs(2).open(dir.path()).unwrap();
+        let blocks: Database<OwnedType<u32>, SerdeBincode<LegacyBlock>> = env.create_database(Some("blocks")).unwrap();
+        let mut wtxn = env.write_txn().unwrap();
+        blocks.put(&mut wtxn, &1, &legacy).unwrap();
+        wtxn.commit().unwrap();
+        env.prepare_for_closing().wait();
+
+        let store = LmdbStore::open(dir.path(), &config).unwrap();
+        assert!(matches!(Blockchain::with_store(Box::new(store), test_genesis_info(), &sk), Err(Error::LegacyGenesis)));
+    }
+
+    #[test]
+    fn metadata_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config(dir.path(), 1024 * 1024, 1024 * 1024);
+        let mut store = LmdbStore::open(dir.path(), &config).unwrap();
+
+        store.put_metadata("key", vec![1, 2, 3]).unwrap();
+
+        assert_eq!(store.get_metadata("key").unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(store.block_count().unwrap(), 0);
+    }
+}
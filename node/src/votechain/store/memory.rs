@@ -0,0 +1,61 @@
+use std::{collections::{BTreeMap, HashMap}, fmt::Debug};
+
+use crate::votechain::{block::Block, errors::Error};
+
+use super::{ChainStore, StoreOp};
+
+/// Purely in-memory store, used for tests and throwaway chains
+#[derive(Default)]
+pub struct MemoryStore {
+    blocks: BTreeMap<u32, Block>,
+    metadata: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+}
+
+impl ChainStore for MemoryStore {
+    fn get_block(&self, index: u32) -> Result<Option<Block>, Error> {
+        return Ok(self.blocks.get(&index).cloned());
+    }
+
+    fn for_each_block(&self, visit: &mut dyn FnMut(u32, Block)) -> Result<(), Error> {
+        for (index, block) in &self.blocks {
+            visit(*index, block.clone());
+        }
+        return Ok(());
+    }
+
+    fn block_count(&self) -> Result<u64, Error> {
+        return Ok(self.blocks.len() as u64);
+    }
+
+    fn get_metadata(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        return Ok(self.metadata.get(key).cloned());
+    }
+
+    fn write(&mut self, ops: Vec<StoreOp>) -> Result<(), Error> {
+        // Nothing here can fail part way through, so the batch is trivially atomic
+        for op in ops {
+            match op {
+                StoreOp::PutBlock(index, block) => { self.blocks.insert(index, block); },
+                StoreOp::DeleteBlock(index) => { self.blocks.remove(&index); },
+                StoreOp::ClearBlocks => self.blocks.clear(),
+                StoreOp::PutMetadata(key, value) => { self.metadata.insert(key, value); },
+                StoreOp::DeleteMetadata(key) => { self.metadata.remove(&key); },
+            }
+        }
+        return Ok(());
+    }
+}
+
+impl Debug for MemoryStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoryStore")
+            .field("blocks", &self.blocks.len())
+            .finish()
+    }
+}
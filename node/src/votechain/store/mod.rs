@@ -0,0 +1,46 @@
+use std::fmt::Debug;
+
+use super::{block::Block, errors::Error};
+
+pub mod lmdb;
+pub mod memory;
+
+pub use lmdb::LmdbStore;
+pub use memory::MemoryStore;
+
+/// A single modification to a store, applied as part of an atomic batch
+#[derive(Debug, Clone)]
+pub enum StoreOp {
+    PutBlock(u32, Block),
+    DeleteBlock(u32),
+    ClearBlocks,
+    PutMetadata(String, Vec<u8>),
+    DeleteMetadata(String),
+}
+
+/// Backing storage for the blocks and metadata of a single chain
+pub trait ChainStore: Debug + Send {
+    fn get_block(&self, index: u32) -> Result<Option<Block>, Error>;
+
+    /// Visit every stored block in ascending index order
+    fn for_each_block(&self, visit: &mut dyn FnMut(u32, Block)) -> Result<(), Error>;
+
+    fn block_count(&self) -> Result<u64, Error>;
+
+    fn get_metadata(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Apply a batch of operations in order. Either all of them are applied or none are
+    fn write(&mut self, ops: Vec<StoreOp>) -> Result<(), Error>;
+
+    fn put_block(&mut self, index: u32, block: Block) -> Result<(), Error> {
+        return self.write(vec![StoreOp::PutBlock(index, block)]);
+    }
+
+    fn delete_block(&mut self, index: u32) -> Result<(), Error> {
+        return self.write(vec![StoreOp::DeleteBlock(index)]);
+    }
+
+    fn put_metadata(&mut self, key: &str, value: Vec<u8>) -> Result<(), Error> {
+        return self.write(vec![StoreOp::PutMetadata(key.to_string(), value)]);
+    }
+}
@@ -0,0 +1,2 @@
+// The bindings live in vote_lib::wasm, this crate only packages them as a cdylib for wasm-pack
+pub use vote_lib::wasm::*;